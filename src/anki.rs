@@ -0,0 +1,120 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{filename, toc::Toc, Extraction};
+
+/// How many of the lines of a page must be definitions for it to continue the glossary.
+const MIN_DEFINITIONS: f32 = 0.3;
+/// The most words a term may have.
+const MAX_TERM_WORDS: usize = 6;
+
+/// Finds the glossary of the book, the pages from a "Glossary" heading onward whose lines mostly
+/// read like "Term: definition" or "Term — definition", and writes its terms as tab separated
+/// Anki decks into `dir`. With a TOC, every term goes into the deck of the chapter whose text
+/// first mentions it, and the rest into a glossary deck. Returns the paths of the decks and the
+/// number of cards.
+pub fn write_decks(
+    dir: &Path,
+    extraction: &Extraction,
+    toc: Option<&Toc>,
+) -> Result<(Vec<PathBuf>, usize)> {
+    let mut cards = Vec::new();
+    let mut glossary_pages = Vec::new();
+    let mut in_glossary = false;
+    for (page, text) in extraction.page_texts.iter().enumerate() {
+        let lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        let mut heading = false;
+        let mut definitions = Vec::new();
+        let mut count = 0;
+        for line in lines {
+            count += 1;
+            if line.eq_ignore_ascii_case("glossary") {
+                heading = true;
+            } else if let Some(card) = definition(line) {
+                definitions.push(card);
+            }
+        }
+        if heading {
+            in_glossary = true;
+        } else if (definitions.len() as f32) < MIN_DEFINITIONS * count as f32 {
+            in_glossary = false;
+        }
+        if in_glossary {
+            glossary_pages.push(page);
+            cards.extend(definitions);
+        }
+    }
+    let mut decks = Vec::<(String, Vec<(String, String)>)>::new();
+    if let Some(toc) = toc {
+        let end = extraction.page_texts.len();
+        for (i, chapter) in toc.entries.iter().enumerate() {
+            let next = toc
+                .entries
+                .get(i + 1)
+                .map_or(end, |next| next.page as usize);
+            let text = (chapter.page as usize..next.min(end))
+                .filter(|page| !glossary_pages.contains(page))
+                .map(|page| extraction.page_texts[page].to_lowercase())
+                .collect::<String>();
+            let (mentioned, rest) = cards
+                .into_iter()
+                .partition(|(term, _)| mentions(&text, &term.to_lowercase()));
+            cards = rest;
+            decks.push((chapter.title.clone(), mentioned));
+        }
+    }
+    decks.push(("Glossary".to_string(), cards));
+    fs::create_dir_all(dir)?;
+    let mut paths = Vec::new();
+    let mut total = 0;
+    for (i, (title, cards)) in decks.into_iter().enumerate() {
+        if cards.is_empty() {
+            continue;
+        }
+        let tag = filename::sanitize(&title).replace(' ', "_");
+        let mut deck = "#separator:tab\n#html:false\n#tags column:3\n".to_string();
+        for (term, definition) in &cards {
+            deck += &format!("{}\t{}\t{}\n", clean(term), clean(definition), tag);
+        }
+        let path = dir.join(filename::sanitize(&format!("{:02} {}.tsv", i + 1, title)));
+        fs::write(&path, deck)?;
+        paths.push(path);
+        total += cards.len();
+    }
+    Ok((paths, total))
+}
+
+/// Splits a glossary line into its term and definition.
+fn definition(line: &str) -> Option<(String, String)> {
+    let (term, definition) = [": ", " — ", " – ", " - "]
+        .iter()
+        .find_map(|separator| line.split_once(separator))?;
+    let (term, definition) = (term.trim(), definition.trim());
+    let words = term.split_whitespace().count();
+    let starts_like_a_term = term.starts_with(|char: char| char.is_alphanumeric());
+    if words == 0 || words > MAX_TERM_WORDS || !starts_like_a_term {
+        return None;
+    }
+    if definition.split_whitespace().count() < 2 {
+        return None;
+    }
+    Some((term.to_string(), definition.to_string()))
+}
+
+/// Whether `text` contains `term` as a whole word.
+fn mentions(text: &str, term: &str) -> bool {
+    text.match_indices(term).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + term.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// Keeps a field on one line of the deck.
+fn clean(field: &str) -> String {
+    field.replace(['\t', '\n'], " ")
+}
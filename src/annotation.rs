@@ -0,0 +1,289 @@
+use std::fmt::{self, Display, Formatter};
+
+use anyhow::{bail, Result};
+use sonic_rs::{
+    to_array_iter, to_object_iter, JsonContainerTrait, JsonValueTrait, LazyValue, Value,
+};
+
+use crate::{Text, TextPageData};
+
+/// The matrix of a text run that comes without a usable one.
+const IDENTITY: [f32; 6] = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+/// The keys the text layer is kept under in the annotation data.
+const CONTAINERS: [&str; 3] = ["TextPageData", "textPageData", "textLayer"];
+/// The keys of the objects the annotation data or the text layer may be wrapped in.
+const WRAPPERS: [&str; 4] = ["data", "result", "annotation", "page"];
+/// How deep the annotation data or the text layer may be wrapped.
+const MAX_DEPTH: usize = 3;
+
+/// The field names of a version of the annotation format.
+struct Schema {
+    /// The text runs of the page.
+    texts: &'static str,
+    /// The text matrix of a run.
+    matrix: &'static str,
+    /// The characters of a run.
+    chars: &'static str,
+}
+
+/// The versions of the annotation format, the one the player has used the longest first.
+const SCHEMAS: [Schema; 3] = [
+    Schema {
+        texts: "texts",
+        matrix: "mt",
+        chars: "cs",
+    },
+    Schema {
+        texts: "textRuns",
+        matrix: "matrix",
+        chars: "chars",
+    },
+    Schema {
+        texts: "runs",
+        matrix: "transform",
+        chars: "glyphs",
+    },
+];
+
+/// The error of a text layer in none of the known versions of the format, with its field names.
+#[derive(Debug)]
+pub struct UnknownFormat(Vec<String>);
+
+impl Display for UnknownFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the text layer is in an unknown format, with {}",
+            self.0.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownFormat {}
+
+/// Reads the text layer of a page from its annotation data, `{"TextPageData": "..."}` with the
+/// text layer encoded as JSON once more inside the string, or as an object. The version of the
+/// format is told apart by the field holding the text runs, and the text layer may be wrapped in
+/// objects such as `{"data": {...}}`.
+///
+/// The annotation data is skipped through rather than parsed whole, and its text runs are parsed
+/// one at a time, since it runs to tens of megabytes on pages dense with formulas. Only JSON that
+/// doesn't parse and a text layer in none of the known versions are errors. A missing text layer,
+/// runs or characters leave the page or run empty, other fields are ignored, numbers that aren't
+/// finite count as missing, a run without a matrix of six numbers gets the identity matrix, and
+/// characters without a position or a code point are left out, so an odd page doesn't end the
+/// whole run.
+pub fn parse(annotation: &str) -> Result<TextPageData> {
+    match lenient(annotation, |annotation| find_layer(annotation, 0))? {
+        Layer::Runs(data) => Ok(TextPageData { data }),
+        Layer::Unknown(keys) => bail!(UnknownFormat(keys)),
+        Layer::Empty => Ok(TextPageData { data: Vec::new() }),
+    }
+}
+
+/// Parses JSON, reading `NaN`, `Infinity` and numbers too large for a float as `null` if it
+/// doesn't parse as it is.
+fn lenient<T>(json: &str, read: impl Fn(&str) -> sonic_rs::Result<T>) -> sonic_rs::Result<T> {
+    read(json).or_else(|error| match non_finite_as_null(json) {
+        Some(json) => read(&json),
+        None => Err(error),
+    })
+}
+
+/// Replaces the numbers outside strings that aren't finite with `null`, or returns `None` if
+/// there are none.
+fn non_finite_as_null(json: &str) -> Option<String> {
+    let mut out = String::with_capacity(json.len());
+    let mut replaced = false;
+    let mut rest = json;
+    while let Some(c) = rest.chars().next() {
+        if c == '"' {
+            // copy the string through its closing quote, skipping escaped quotes
+            let mut end = 1;
+            let mut escaped = false;
+            for (i, c) in rest.char_indices().skip(1) {
+                end = i + c.len_utf8();
+                match c {
+                    '\\' if !escaped => escaped = true,
+                    '"' if !escaped => break,
+                    _ => escaped = false,
+                }
+            }
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "+-.".contains(c)))
+            .unwrap_or(rest.len());
+        let token = &rest[..len.max(c.len_utf8())];
+        let non_finite = match token {
+            "NaN" | "Infinity" | "-Infinity" => true,
+            _ => {
+                token.starts_with(|c: char| c == '-' || c.is_ascii_digit())
+                    && token
+                        .parse::<f64>()
+                        .is_ok_and(|number| number.is_infinite())
+            }
+        };
+        if non_finite {
+            out.push_str("null");
+            replaced = true;
+        } else {
+            out.push_str(token);
+        }
+        rest = &rest[token.len()..];
+    }
+    replaced.then_some(out)
+}
+
+/// A text layer as read, before it is known whether it is in a known format.
+enum Layer {
+    Runs(Vec<Text>),
+    /// An object without text runs in any known version, with its field names.
+    Unknown(Vec<String>),
+    Empty,
+}
+
+/// Iterates over the fields of an object without parsing their values, or returns `None` for
+/// valid JSON that is no object.
+fn fields(
+    json: &str,
+) -> sonic_rs::Result<Option<impl Iterator<Item = sonic_rs::Result<(String, LazyValue<'_>)>>>> {
+    if !json.trim_start().starts_with('{') {
+        sonic_rs::from_str::<Value>(json)?;
+        return Ok(None);
+    }
+    Ok(Some(to_object_iter(json).map(|field| {
+        field.map(|(key, value)| (key.to_string(), value))
+    })))
+}
+
+/// Finds the text layer in annotation data wrapped `depth` objects deep.
+fn find_layer(json: &str, depth: usize) -> sonic_rs::Result<Layer> {
+    let Some(fields) = fields(json)? else {
+        return Ok(Layer::Empty);
+    };
+    let mut wrapped = Vec::new();
+    for field in fields {
+        let (key, value) = field?;
+        if CONTAINERS.contains(&key.as_str()) {
+            return match value.as_str() {
+                Some(data) => lenient(data, |data| read_layer(data, 0)),
+                None => read_layer(value.as_raw_str(), 0),
+            };
+        }
+        if depth < MAX_DEPTH && WRAPPERS.contains(&key.as_str()) {
+            wrapped.push(value);
+        }
+    }
+    for value in &wrapped {
+        match find_layer(value.as_raw_str(), depth + 1)? {
+            Layer::Empty => {}
+            layer => return Ok(layer),
+        }
+    }
+    Ok(Layer::Empty)
+}
+
+/// Tells the version of a text layer wrapped `depth` objects deep apart and reads its text runs.
+fn read_layer(json: &str, depth: usize) -> sonic_rs::Result<Layer> {
+    let Some(fields) = fields(json)? else {
+        return Ok(Layer::Empty);
+    };
+    let mut runs = None;
+    let mut wrapped = Vec::new();
+    let mut keys = Vec::new();
+    for field in fields {
+        let (key, value) = field?;
+        let schema = SCHEMAS.iter().find(|schema| schema.texts == key);
+        match schema {
+            Some(schema) if runs.is_none() => runs = Some(read_runs(schema, &value)?),
+            _ if depth < MAX_DEPTH && WRAPPERS.contains(&key.as_str()) => wrapped.push(value),
+            _ => {}
+        }
+        keys.push(key);
+    }
+    if let Some(runs) = runs {
+        return Ok(Layer::Runs(runs));
+    }
+    // the runs of the text layer itself come before those of an object wrapped in it
+    for value in &wrapped {
+        if let Layer::Runs(runs) = read_layer(value.as_raw_str(), depth + 1)? {
+            return Ok(Layer::Runs(runs));
+        }
+    }
+    Ok(match keys.is_empty() {
+        true => Layer::Empty,
+        false => Layer::Unknown(keys),
+    })
+}
+
+/// Reads the text runs of a text layer a run at a time, rather than the whole layer at once.
+fn read_runs(schema: &Schema, runs: &LazyValue) -> sonic_rs::Result<Vec<Text>> {
+    if !runs.is_array() {
+        return Ok(Vec::new());
+    }
+    let mut texts = Vec::new();
+    for run in to_array_iter(runs.as_raw_str()) {
+        let run = sonic_rs::from_str::<Value>(run?.as_raw_str())?;
+        texts.extend(read_text(schema, &run));
+    }
+    Ok(texts)
+}
+
+fn read_text(schema: &Schema, value: &Value) -> Option<Text> {
+    value.as_object()?;
+    let matrix = value
+        .get(schema.matrix)
+        .and_then(|matrix| matrix.as_array())
+        .and_then(|matrix| {
+            let numbers = matrix.iter().map(finite).collect::<Option<Vec<_>>>()?;
+            numbers.try_into().ok()
+        })
+        .unwrap_or(IDENTITY);
+    let stream = value
+        .get(schema.chars)
+        .and_then(|stream| stream.as_array())
+        .map(|stream| stream.iter().filter_map(char_box).collect())
+        .unwrap_or_default();
+    Some(Text { matrix, stream })
+}
+
+/// Reads a character as `[x, y, width, height, code point]`, ignoring anything after, or as an
+/// object such as `{"x": 1, "y": 2, "width": 3, "height": 4, "char": "A"}`.
+fn char_box(value: &Value) -> Option<(f32, f32, f32, f32, u32)> {
+    let field = |keys: &[&str], i: usize| match value.as_array() {
+        Some(fields) => fields.get(i),
+        None => keys.iter().find_map(|key| value.get(key)),
+    };
+    let number = |keys: &[&str], i: usize| field(keys, i).and_then(finite);
+    let (x, y) = (number(&["x"], 0)?, number(&["y"], 1)?);
+    // a character without a size still has a place in the text
+    let w = number(&["w", "width"], 2).unwrap_or(0.0);
+    let h = number(&["h", "height"], 3).unwrap_or(0.0);
+    let char = field(&["c", "char", "code"], 4).and_then(code_point)?;
+    Some((x, y, w, h, char))
+}
+
+/// Reads a code point given as a number or as a string of the character.
+fn code_point(value: &Value) -> Option<u32> {
+    if let Some(char) = value.as_str() {
+        let mut chars = char.chars();
+        return chars
+            .next()
+            .filter(|_| chars.next().is_none())
+            .map(u32::from);
+    }
+    let char = value.as_u64().or_else(|| {
+        value
+            .as_f64()
+            .filter(|char| char.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(char))
+            .map(|char| char as u64)
+    })?;
+    u32::try_from(char).ok()
+}
+
+fn finite(value: &Value) -> Option<f32> {
+    Some(value.as_f64()? as f32).filter(|number| number.is_finite())
+}
@@ -0,0 +1,245 @@
+use std::{
+    fs,
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Result};
+use ring::constant_time::verify_slices_are_equal;
+
+use crate::{http, metrics::Jobs};
+
+/// The states of a job, by the extension of its file in the queue directory.
+const STATES: [(&str, &str); 5] = [
+    ("json", "pending"),
+    ("queued", "queued"),
+    ("running", "running"),
+    ("done", "done"),
+    ("failed", "failed"),
+];
+
+/// How many connections are handled at a time, with as many more waiting to be.
+const WORKERS: usize = 8;
+
+/// The command line options a job may use, with whether they take a value. Options that read
+/// or write files, run programs, send the document anywhere or fetch other addresses are left
+/// out, as anyone who can submit jobs could use them.
+const ALLOWED: [(&str, bool); 45] = [
+    ("-c", true),
+    ("--cookie", true),
+    ("--profile", true),
+    ("-a", true),
+    ("--auth-token", true),
+    ("-p", true),
+    ("--product-id", true),
+    ("-u", true),
+    ("--uuid", true),
+    ("--title", true),
+    ("--author", true),
+    ("--isbn", true),
+    ("--series", true),
+    ("--edition", true),
+    ("--detect-edition", false),
+    ("--year", true),
+    ("--format", true),
+    ("--verify", false),
+    ("--verify-sample", true),
+    ("--verify-text", false),
+    ("--index-links", false),
+    ("--attach-sources", false),
+    ("--layers", false),
+    ("--text-visible", false),
+    ("--tagged", false),
+    ("--lang", true),
+    ("--color-management", false),
+    ("--image-codec", true),
+    ("--jpeg-quality", true),
+    ("--max-size", true),
+    ("--max-memory", true),
+    ("--low-resource", false),
+    ("--stamp", true),
+    ("--stamp-position", true),
+    ("--dedupe-images", false),
+    ("--object-streams", false),
+    ("--xref-stream", false),
+    ("--pdf-version", true),
+    ("--linearize", false),
+    ("--max-requests", true),
+    ("--http", true),
+    ("--pool-size", true),
+    ("--pool-idle-timeout", true),
+    ("--tcp-keepalive", true),
+    ("--no-space-check", false),
+];
+
+/// Serves an HTTP API in front of the job queue in `dir` on background threads, to clients
+/// that send `token` as `Authorization: Bearer <token>`:
+///
+/// - `POST /jobs` with a JSON array of command line arguments submits a job, whose document is
+///   written to `<id>.pdf`, and responds with `{"id": "<id>"}`.
+/// - `GET /jobs/<id>` responds with the state of the job and the last line of its output.
+/// - `GET /jobs/<id>/result` downloads the document of a finished job.
+/// - `GET /metrics` responds with the counters of the jobs in the Prometheus text format.
+pub fn listen(addr: SocketAddr, dir: PathBuf, jobs: Arc<Jobs>, token: String) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Listening on http://{}.", addr);
+    let (connections, incoming) = mpsc::sync_channel::<TcpStream>(WORKERS);
+    let incoming = Arc::new(Mutex::new(incoming));
+    let token = Arc::new(token);
+    for _ in 0..WORKERS {
+        let (incoming, dir, jobs, token) =
+            (incoming.clone(), dir.clone(), jobs.clone(), token.clone());
+        thread::spawn(move || loop {
+            let Ok(stream) = incoming.lock().unwrap().recv() else {
+                break;
+            };
+            let _ = handle(stream, addr, &dir, &jobs, &token);
+        });
+    }
+    thread::spawn(move || {
+        // waits while every worker is busy and as many connections are waiting
+        for stream in listener.incoming().flatten() {
+            if connections.send(stream).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle(stream: TcpStream, addr: SocketAddr, dir: &Path, jobs: &Jobs, token: &str) -> Result<()> {
+    let request = http::read_request(&stream)?;
+    let authorized = request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|sent| verify_slices_are_equal(sent.as_bytes(), token.as_bytes()).is_ok());
+    // browsers send the page a request comes from, which for this API is never another site
+    let cross_site = request
+        .header("origin")
+        .is_some_and(|origin| origin != format!("http://{addr}"));
+    let (method, path, body) = (request.method.as_str(), &request.path, &request.body);
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    let (status, content_type, body) = match (method, segments.as_slice()) {
+        _ if cross_site => json(
+            "403 Forbidden",
+            sonic_rs::json!({ "error": "cross-origin requests are not allowed" }),
+        ),
+        _ if !authorized => json(
+            "401 Unauthorized",
+            sonic_rs::json!({ "error": "a valid bearer token is required" }),
+        ),
+        ("POST", ["jobs"])
+            if !request
+                .header("content-type")
+                .is_some_and(|value| value.starts_with("application/json")) =>
+        {
+            json(
+                "415 Unsupported Media Type",
+                sonic_rs::json!({ "error": "jobs are submitted as application/json" }),
+            )
+        }
+        ("POST", ["jobs"]) => match submit(dir, body) {
+            Ok(id) => json("200 OK", sonic_rs::json!({ "id": id })),
+            Err(error) => json(
+                "400 Bad Request",
+                sonic_rs::json!({ "error": error.to_string() }),
+            ),
+        },
+        ("GET", ["jobs", id]) if is_id(id) => match state(dir, id) {
+            Some(state) => {
+                let log = fs::read_to_string(dir.join(format!("{id}.log"))).unwrap_or_default();
+                let progress = log.lines().rev().find(|line| !line.trim().is_empty());
+                json(
+                    "200 OK",
+                    sonic_rs::json!({ "id": id, "state": state, "progress": progress }),
+                )
+            }
+            None => not_found(),
+        },
+        ("GET", ["jobs", id, "result"]) if is_id(id) && state(dir, id) == Some("done") => {
+            match fs::read(dir.join(format!("{id}.pdf"))) {
+                Ok(document) => ("200 OK", "application/pdf", document),
+                Err(_) => not_found(),
+            }
+        }
+        ("GET", ["metrics"]) => match jobs.render(dir) {
+            Ok(metrics) => ("200 OK", "text/plain; version=0.0.4", metrics.into_bytes()),
+            Err(error) => json(
+                "500 Internal Server Error",
+                sonic_rs::json!({ "error": error.to_string() }),
+            ),
+        },
+        _ => not_found(),
+    };
+    http::write_response(&stream, status, &[("Content-Type", content_type)], &body)?;
+    Ok(())
+}
+
+fn submit(dir: &Path, body: &[u8]) -> Result<String> {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let mut args = sonic_rs::from_slice::<Vec<String>>(body)?;
+    check_args(&args)?;
+    let id = format!(
+        "{}-{}",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    args.extend(["-o".to_string(), format!("{id}.pdf")]);
+    // write under another name first so the queue never sees a partial job
+    let partial = dir.join(format!("{id}.partial"));
+    fs::write(&partial, sonic_rs::to_string(&args)?)?;
+    fs::rename(partial, dir.join(format!("{id}.json")))?;
+    Ok(id)
+}
+
+/// Fails unless every argument is an option of `ALLOWED` or the value of one.
+fn check_args(args: &[String]) -> Result<()> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        // the value of a long option may follow an =, and that of a short one the letter
+        let (name, attached) = match arg.split_once('=') {
+            Some((name, _)) if arg.starts_with("--") => (name, true),
+            _ if !arg.starts_with("--") && arg.starts_with('-') && arg.len() > 2 => {
+                (arg.get(..2).unwrap_or(arg), true)
+            }
+            _ => (arg.as_str(), false),
+        };
+        let takes_value = ALLOWED
+            .iter()
+            .find(|(allowed, _)| *allowed == name)
+            .map(|(_, takes_value)| *takes_value)
+            .ok_or_else(|| anyhow!("jobs submitted through the API can't use {}", name))?;
+        if attached && !takes_value {
+            bail!("{} takes no value", name);
+        }
+        if takes_value && !attached && args.next().is_none() {
+            bail!("{} needs a value", name);
+        }
+    }
+    Ok(())
+}
+
+fn state(dir: &Path, id: &str) -> Option<&'static str> {
+    STATES
+        .iter()
+        .find(|(extension, _)| dir.join(format!("{id}.{extension}")).exists())
+        .map(|(_, state)| *state)
+}
+
+fn is_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|char| char.is_ascii_digit() || char == '-')
+}
+
+fn json(status: &'static str, value: sonic_rs::Value) -> (&'static str, &'static str, Vec<u8>) {
+    (status, "application/json", value.to_string().into_bytes())
+}
+
+fn not_found() -> (&'static str, &'static str, Vec<u8>) {
+    json("404 Not Found", sonic_rs::json!({ "error": "not found" }))
+}
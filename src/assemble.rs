@@ -0,0 +1,127 @@
+use crate::{
+    raster::Image,
+    sink::{Page, MM},
+    stamp::Stamp,
+    tags, TextPageData,
+};
+
+/// Points per pixel of a page image, laid out at 12 pixels per millimeter.
+pub const PAGE_SCALE: f32 = MM / 12.0;
+/// Points per pixel a page image is drawn at, 300 DPI.
+pub const IMAGE_SCALE: f32 = 72.0 / 300.0;
+/// The color `text_visible` draws the text in.
+const TEXT_VISIBLE_COLOR: [f32; 3] = [1.0, 0.0, 0.0];
+
+/// How the pages of a document are laid out.
+pub struct Layout<'a> {
+    /// The title of the book, for the stamps.
+    pub title: &'a str,
+    /// Put the page images and the text into separate layers that can be toggled in a viewer.
+    pub layers: bool,
+    /// Mark up the text runs and page images for the structure tree.
+    pub tagged: bool,
+    /// Draw the text in red over the page images instead of invisible under them.
+    pub text_visible: bool,
+}
+
+/// Returns the size in points of a page whose image is `w` by `h` pixels.
+pub fn page_size(w: u32, h: u32) -> (f32, f32) {
+    (w as f32 * PAGE_SCALE, h as f32 * PAGE_SCALE)
+}
+
+/// Lays out the cover, `image` drawn at `placement` on a page of `size` points.
+pub fn cover(
+    image: Image,
+    size: (f32, f32),
+    placement: (f32, f32, f32, f32),
+    layout: &Layout,
+) -> Page {
+    let mut page = Page::new(size);
+    if layout.layers {
+        page.begin_layer("Page image");
+    }
+    draw_page_image(&mut page, image, placement, layout.tagged);
+    if layout.layers {
+        page.end_layer();
+    }
+    page
+}
+
+/// Lays out page `number` of the book from its image of `w` by `h` pixels and its text layer,
+/// with `stamps` drawn on it, and returns it along with its text, a line per text run.
+pub fn page(
+    number: u32,
+    image: Image,
+    (w, h): (u32, u32),
+    texts: TextPageData,
+    stamps: &[&Stamp],
+    layout: &Layout,
+) -> (Page, String) {
+    let size = page_size(w, h);
+    let mut page = Page::new(size);
+    // the image layer is drawn over the text layer, hiding the text until toggled off
+    if layout.layers {
+        page.begin_layer("Text");
+    }
+    let placement = (0.0, 0.0, w as f32 * IMAGE_SCALE, h as f32 * IMAGE_SCALE);
+    let page_text = if layout.text_visible {
+        // the text shows over the image, to check how the two line up
+        draw_page_image(&mut page, image, placement, layout.tagged);
+        page.set_fill_color(TEXT_VISIBLE_COLOR);
+        let page_text = write_text_layer(&mut page, texts, true, layout.tagged);
+        page.set_fill_color([0.0; 3]);
+        page_text
+    } else {
+        let page_text = write_text_layer(&mut page, texts, layout.layers, layout.tagged);
+        if layout.layers {
+            page.end_layer();
+            page.begin_layer("Page image");
+        }
+        draw_page_image(&mut page, image, placement, layout.tagged);
+        page_text
+    };
+    for stamp in stamps {
+        stamp.draw(&mut page, layout.title, number, size, layout.tagged);
+    }
+    if layout.layers {
+        page.end_layer();
+    }
+    (page, page_text)
+}
+
+/// Writes the text runs of a page, filled or invisible, and returns their text a line per run.
+fn write_text_layer(page: &mut Page, texts: TextPageData, visible: bool, tagged: bool) -> String {
+    page.begin_text(1.0, visible);
+    let mut page_text = String::new();
+    for (run, data) in texts.data.into_iter().enumerate() {
+        if tagged {
+            tags::begin(page, "P", run as u32 + 1);
+        }
+        let mut matrix = data.matrix;
+        for (x, y, _, _, char) in data.stream {
+            matrix[4] = x;
+            matrix[5] = y;
+            page.set_text_matrix(matrix);
+            if let Some(char) = char::from_u32(char) {
+                page.write_text(char.encode_utf8(&mut [0; 4]));
+                page_text.push(char);
+            }
+        }
+        page_text.push('\n');
+        if tagged {
+            tags::end(page);
+        }
+    }
+    page.end_text();
+    page_text
+}
+
+fn draw_page_image(page: &mut Page, image: Image, placement: (f32, f32, f32, f32), tagged: bool) {
+    if tagged {
+        tags::begin(page, "Figure", 0);
+    }
+    page.draw_image(image, placement);
+    if tagged {
+        tags::end(page);
+    }
+}
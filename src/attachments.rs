@@ -0,0 +1,38 @@
+use anyhow::Result;
+use lopdf::{dictionary, Document, Object, Stream};
+
+use crate::catalog::{names_mut, text_string};
+
+/// Embeds every `(name, data)` pair as a file attachment of the document.
+pub fn attach(document: &mut Document, mut files: Vec<(String, Vec<u8>)>) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    // name tree leaves must be sorted by their byte representation
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut names = Vec::new();
+    for (name, data) in files {
+        let size = data.len() as i64;
+        let mut stream = Stream::new(
+            dictionary! {
+                "Type" => "EmbeddedFile",
+                "Subtype" => Object::Name(b"application/json".to_vec()),
+                "Params" => dictionary! { "Size" => size },
+            },
+            data,
+        );
+        stream.compress()?;
+        let stream = document.add_object(stream);
+        let file_spec = document.add_object(dictionary! {
+            "Type" => "Filespec",
+            "F" => text_string(&name),
+            "UF" => text_string(&name),
+            "EF" => dictionary! { "F" => stream },
+        });
+        names.push(text_string(&name));
+        names.push(file_spec.into());
+    }
+    let embedded_files = document.add_object(dictionary! { "Names" => names });
+    names_mut(document)?.set("EmbeddedFiles", embedded_files);
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use crate::{
+    crossref::{self, Link},
+    TextPageData,
+};
+
+/// How many of the text runs of a page must list page numbers for it to continue the index.
+const MIN_ENTRIES: f32 = 0.3;
+
+/// Collects the page numbers listed on the index pages of a book as its pages come in, and the
+/// page numbers printed on every page to tell which page each of them refers to.
+#[derive(Default)]
+pub struct IndexLinks {
+    in_index: bool,
+    /// How often each difference between a page and the number printed on it was seen.
+    offsets: HashMap<i64, u32>,
+    /// The page, rectangle and printed page number of every listed page number.
+    references: Vec<(u32, [f32; 4], u32)>,
+}
+
+impl IndexLinks {
+    pub fn add_page(&mut self, page: u32, texts: &TextPageData) {
+        let runs = texts
+            .data
+            .iter()
+            .map(crossref::chars)
+            .filter(|chars| !chars.is_empty())
+            .collect::<Vec<_>>();
+        let mut heading = false;
+        let mut entries = 0;
+        let mut references = Vec::new();
+        for chars in &runs {
+            let text = chars.iter().map(|(char, _)| char).collect::<String>();
+            let text = text.trim();
+            if text.eq_ignore_ascii_case("index") {
+                heading = true;
+            }
+            if !text.is_empty() && text.len() <= 4 && text.chars().all(|char| char.is_ascii_digit())
+            {
+                let printed = text.parse::<i64>().unwrap();
+                *self.offsets.entry(page as i64 - printed).or_default() += 1;
+            }
+            // an entry such as "Cells, 12, 45–47" lists its pages after the term
+            let Some(comma) = chars.iter().position(|(char, _)| *char == ',') else {
+                continue;
+            };
+            let numbers = numbers(&chars[comma..]);
+            if !numbers.is_empty() {
+                entries += 1;
+            }
+            references.extend(
+                numbers
+                    .into_iter()
+                    .map(|(rect, printed)| (page, rect, printed)),
+            );
+        }
+        if heading {
+            self.in_index = true;
+        } else if (entries as f32) < MIN_ENTRIES * runs.len() as f32 {
+            self.in_index = false;
+        }
+        if self.in_index {
+            self.references.extend(references);
+        }
+    }
+
+    /// Returns a link for every listed page number, assuming the difference between a page and
+    /// its printed number seen most often holds for the whole book.
+    pub fn links(&self) -> Vec<Link> {
+        let Some((&offset, _)) = self
+            .offsets
+            .iter()
+            .max_by_key(|&(offset, count)| (count, -offset))
+        else {
+            return Vec::new();
+        };
+        self.references
+            .iter()
+            .filter_map(|&(page, rect, printed)| {
+                let target = u32::try_from(printed as i64 + offset).ok()?;
+                Some(Link { page, rect, target })
+            })
+            .collect()
+    }
+}
+
+/// Returns the bounding box and value of every number in `chars`.
+fn numbers(chars: &[(char, [f32; 4])]) -> Vec<([f32; 4], u32)> {
+    let mut numbers = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].0.is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while chars.get(i).is_some_and(|(char, _)| char.is_ascii_digit()) {
+            i += 1;
+        }
+        let digits = &chars[start..i];
+        let value = digits.iter().map(|(char, _)| char).collect::<String>();
+        if let Ok(value) = value.parse() {
+            numbers.push((crossref::bounds(digits.iter().map(|(_, rect)| rect)), value));
+        }
+    }
+    numbers
+}
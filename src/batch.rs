@@ -0,0 +1,55 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::Result;
+
+use crate::exit;
+
+/// Runs the extraction jobs read from `input`, or from stdin for `-`, one after another as their
+/// lines come in, and returns how many of them failed.
+///
+/// Every line holds the command line arguments of a job as a JSON array, as in a job file of the
+/// queue, and blank lines are skipped, so another tool can stream jobs in and close the input
+/// when it is done.
+pub fn run(input: &Path) -> Result<usize> {
+    let lines: Box<dyn BufRead> = if input == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(input)?))
+    };
+    let mut failed = 0;
+    for (i, line) in lines.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let job = i + 1;
+        let args = match sonic_rs::from_str::<Vec<String>>(&line) {
+            Ok(args) => args,
+            Err(error) => {
+                println!("Job {} is invalid: {}", job, error);
+                failed += 1;
+                continue;
+            }
+        };
+        println!("Starting job {}.", job);
+        let status = Command::new(env::current_exe()?)
+            .args(args)
+            // the jobs come in on stdin, which the extraction mustn't read
+            .stdin(Stdio::null())
+            .status()?;
+        // a partial success still wrote the document
+        if status.success() || status.code() == Some(exit::PARTIAL) {
+            println!("Finished job {}.", job);
+        } else {
+            println!("Job {} failed with {}.", job, status);
+            failed += 1;
+        }
+    }
+    Ok(failed)
+}
@@ -0,0 +1,61 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Result;
+use reqwest::{
+    header::{ETAG, IF_NONE_MATCH},
+    RequestBuilder, StatusCode,
+};
+
+use crate::extractor::Fetched;
+
+/// Downloaded assets kept on disk along with their ETags, so unmodified ones are revalidated
+/// with a conditional request instead of being downloaded again.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Makes `request` for the asset stored under `key`, a relative path such as
+    /// `123/uuid/pages/page1`, conditional on the cached copy being outdated.
+    pub fn conditional(&self, key: &str, request: RequestBuilder) -> RequestBuilder {
+        let path = self.dir.join(key);
+        match fs::read_to_string(path.with_extension("etag")) {
+            Ok(etag) if path.exists() => request.header(IF_NONE_MATCH, etag),
+            _ => request,
+        }
+    }
+
+    /// Returns the cached copy of the asset stored under `key`.
+    pub fn load(&self, key: &str) -> Option<Vec<u8>> {
+        fs::read(self.dir.join(key)).ok()
+    }
+
+    /// Returns the body of `resp` and caches it under `key`, or returns the cached body when the
+    /// server reports it unmodified.
+    pub fn store(&self, key: &str, resp: Fetched) -> Result<Vec<u8>> {
+        let path = self.dir.join(key);
+        let etag_path = path.with_extension("etag");
+        if resp.status == StatusCode::NOT_MODIFIED {
+            if let Ok(cached) = fs::read(&path) {
+                return Ok(cached);
+            }
+        }
+        let etag = resp.headers.get(ETAG).cloned();
+        let data = resp.body;
+        if resp.status.is_success() {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, &data)?;
+            match etag.and_then(|etag| etag.to_str().ok().map(str::to_string)) {
+                Some(etag) => fs::write(&etag_path, etag)?,
+                None => {
+                    let _ = fs::remove_file(&etag_path);
+                }
+            }
+        }
+        Ok(data)
+    }
+}
@@ -0,0 +1,93 @@
+use std::{fs, io::Cursor, path::Path};
+
+use anyhow::Result;
+use image::codecs::jpeg::JpegEncoder;
+
+/// What is known about a book beyond its pages.
+#[derive(Default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub isbn: Option<String>,
+    pub series: Option<String>,
+    /// The edition of the book, which Calibre shows as its number in the series.
+    pub edition: Option<f32>,
+    /// The year the book was published.
+    pub year: Option<u16>,
+}
+
+/// Writes a `metadata.opf` and a `cover.jpg` made from the PNG `cover` into `dir`, which Calibre
+/// reads when the directory is added to its library.
+pub fn write_sidecar(
+    dir: &Path,
+    metadata: &Metadata,
+    lang: Option<&str>,
+    product_id: u32,
+    cover: &[u8],
+) -> Result<()> {
+    let mut opf = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<package xmlns=\"http://www.idpf.org/2007/opf\" unique-identifier=\"pearson\"",
+        " version=\"2.0\">\n",
+        "  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\"",
+        " xmlns:opf=\"http://www.idpf.org/2007/opf\">\n",
+    ));
+    let title = metadata.title.as_deref().unwrap_or("Pearson Plus");
+    opf += &format!("    <dc:title>{}</dc:title>\n", escape(title));
+    for author in &metadata.authors {
+        opf += &format!(
+            "    <dc:creator opf:role=\"aut\">{}</dc:creator>\n",
+            escape(author)
+        );
+    }
+    opf += "    <dc:publisher>Pearson</dc:publisher>\n";
+    opf += &format!(
+        "    <dc:identifier id=\"pearson\" opf:scheme=\"pearson\">{}</dc:identifier>\n",
+        product_id
+    );
+    if let Some(isbn) = &metadata.isbn {
+        let isbn = isbn.replace(['-', ' '], "");
+        opf += &format!(
+            "    <dc:identifier opf:scheme=\"ISBN\">{}</dc:identifier>\n",
+            escape(&isbn)
+        );
+    }
+    if let Some(year) = metadata.year {
+        opf += &format!("    <dc:date>{}</dc:date>\n", year);
+    }
+    if let Some(lang) = lang {
+        opf += &format!("    <dc:language>{}</dc:language>\n", escape(lang));
+    }
+    if let Some(series) = &metadata.series {
+        opf += &format!(
+            "    <meta name=\"calibre:series\" content=\"{}\"/>\n",
+            escape(series)
+        );
+    }
+    if let Some(edition) = metadata.edition {
+        opf += &format!(
+            "    <meta name=\"calibre:series_index\" content=\"{}\"/>\n",
+            edition
+        );
+    }
+    opf += concat!(
+        "  </metadata>\n",
+        "  <guide>\n",
+        "    <reference type=\"cover\" title=\"Cover\" href=\"cover.jpg\"/>\n",
+        "  </guide>\n",
+        "</package>\n",
+    );
+    fs::write(dir.join("metadata.opf"), opf)?;
+    let cover = image::load_from_memory(cover)?.into_rgb8();
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(Cursor::new(&mut jpeg), 90).encode_image(&cover)?;
+    fs::write(dir.join("cover.jpg"), jpeg)?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,82 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use tokio::sync::Notify;
+
+/// Cancels a running extraction from elsewhere, such as the cancel button of a GUI or a server
+/// shutting down. Clones share their state, so one is kept to cancel with and another handed to
+/// the extraction, which finishes the document with the pages it has once it sees it cancelled.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<State>);
+
+/// Whether Ctrl-C was pressed, all the signal handler may safely do about it.
+#[cfg(unix)]
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Default)]
+struct State {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Relaxed);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Waits until the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            // a notification only reaches futures created before it
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Cancels the token when Ctrl-C is pressed, so the extraction finishes the document with the
+    /// pages it has instead of leaving none. Pressing it again exits at once. Has to be called
+    /// within a Tokio runtime.
+    #[cfg(unix)]
+    pub fn cancel_on_interrupt(&self) {
+        extern "C" fn handle(_: libc::c_int) {
+            if INTERRUPTED.swap(true, Ordering::Relaxed) {
+                // SAFETY: _exit is async-signal-safe
+                unsafe { libc::_exit(130) };
+            }
+        }
+        // SAFETY: the handler only touches an atomic and calls _exit
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                handle as extern "C" fn(_) as libc::sighandler_t,
+            )
+        };
+        let token = self.clone();
+        // the handler can't wake a task, so the flag is polled
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(std::time::Duration::from_millis(100));
+            while !INTERRUPTED.load(Ordering::Relaxed) {
+                ticks.tick().await;
+            }
+            token.cancel();
+        });
+    }
+
+    /// Leaves Ctrl-C to exit at once, as there is no handler for it on this platform.
+    #[cfg(not(unix))]
+    pub fn cancel_on_interrupt(&self) {}
+}
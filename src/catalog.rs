@@ -0,0 +1,36 @@
+use anyhow::Result;
+use lopdf::{dictionary, Dictionary, Document, Object, StringFormat};
+
+/// Returns the name dictionary of the catalog, creating it if necessary.
+pub fn names_mut(document: &mut Document) -> Result<&mut Dictionary> {
+    let catalog = document.catalog_mut()?;
+    if !catalog.has(b"Names") {
+        catalog.set("Names", Dictionary::new());
+    }
+    Ok(catalog.get_mut(b"Names")?.as_dict_mut()?)
+}
+
+/// Encodes `text` as a PDF text string, in UTF-16BE unless it is plain ASCII.
+pub fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::string_literal(text);
+    }
+    let mut bytes = vec![0xFE, 0xFF];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+/// Sets the natural language of the document to the BCP 47 tag `lang`, along with a right to left
+/// reading direction for languages written that way.
+pub fn set_language(document: &mut Document, lang: &str) -> Result<()> {
+    let primary = lang.split(['-', '_']).next().unwrap_or_default();
+    let rtl = ["ar", "dv", "fa", "he", "iw", "ps", "sd", "ug", "ur", "yi"]
+        .iter()
+        .any(|code| primary.eq_ignore_ascii_case(code));
+    let catalog = document.catalog_mut()?;
+    catalog.set("Lang", text_string(lang));
+    if rtl {
+        catalog.set("ViewerPreferences", dictionary! { "Direction" => "R2L" });
+    }
+    Ok(())
+}
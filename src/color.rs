@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use lopdf::{dictionary, Document, Object, ObjectId, Stream};
+
+use crate::catalog::text_string;
+
+/// The color information a PNG carries about its pixels.
+pub enum PngColor {
+    /// A zlib compressed ICC profile from an iCCP chunk.
+    Icc(Vec<u8>),
+    /// An sRGB chunk.
+    Srgb,
+    /// The decoding exponent from a gAMA chunk.
+    Gamma(f32),
+}
+
+/// Reads the color chunks of a PNG, preferring iCCP and sRGB over gAMA as the PNG specification
+/// requires.
+pub fn read_png_color(png: &[u8]) -> Option<PngColor> {
+    let mut gamma = None;
+    let mut offset = 8;
+    while offset + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+        let kind = &png[offset + 4..offset + 8];
+        let data = png.get(offset + 8..offset + 8 + length)?;
+        match kind {
+            b"iCCP" => {
+                let name = data.iter().position(|&byte| byte == 0)?;
+                // the compression method byte follows the profile name
+                return Some(PngColor::Icc(data.get(name + 2..)?.to_vec()));
+            }
+            b"sRGB" => return Some(PngColor::Srgb),
+            b"gAMA" if length == 4 => {
+                let gamma_times_100000 = u32::from_be_bytes(data.try_into().unwrap());
+                if gamma_times_100000 != 0 {
+                    gamma = Some(PngColor::Gamma(100000.0 / gamma_times_100000 as f32));
+                }
+            }
+            b"IDAT" | b"IEND" => break,
+            _ => {}
+        }
+        offset += length + 12;
+    }
+    gamma
+}
+
+/// Adds an sRGB output intent to the document and assigns each page image the color space
+/// described by `colors`, in page order.
+pub fn apply(document: &mut Document, colors: &[Option<PngColor>]) -> Result<()> {
+    let srgb = document.add_object(Stream::new(dictionary! { "N" => 3 }, srgb_profile()));
+    let output_intent = dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFA1",
+        "OutputConditionIdentifier" => text_string("sRGB IEC61966-2.1"),
+        "Info" => text_string("sRGB IEC61966-2.1"),
+        "DestOutputProfile" => srgb,
+    };
+    document
+        .catalog_mut()?
+        .set("OutputIntents", vec![output_intent.into()]);
+    let pages = document.get_pages();
+    let mut profiles = HashMap::<&[u8], ObjectId>::new();
+    for (page, color) in colors.iter().enumerate() {
+        let (Some(color), Some(&page)) = (color, pages.get(&(page as u32 + 1))) else {
+            continue;
+        };
+        for image in page_images(document, page)? {
+            let dict = &document.get_object(image)?.as_stream()?.dict;
+            let components = match dict.get(b"ColorSpace").and_then(Object::as_name) {
+                Ok(b"DeviceRGB") => 3,
+                Ok(b"DeviceGray") => 1,
+                _ => continue,
+            };
+            let color_space = match color {
+                PngColor::Icc(profile) => {
+                    let profile = *profiles.entry(profile).or_insert_with(|| {
+                        let stream = dictionary! { "N" => components, "Filter" => "FlateDecode" };
+                        document.add_object(Stream::new(stream, profile.clone()))
+                    });
+                    vec!["ICCBased".into(), profile.into()].into()
+                }
+                PngColor::Srgb if components == 3 => vec!["ICCBased".into(), srgb.into()].into(),
+                PngColor::Srgb => calibrated(components, 2.2),
+                PngColor::Gamma(gamma) => calibrated(components, *gamma),
+            };
+            let image = document.get_object_mut(image)?.as_stream_mut()?;
+            image.dict.set("ColorSpace", color_space);
+        }
+    }
+    Ok(())
+}
+
+fn page_images(document: &Document, page: ObjectId) -> Result<Vec<ObjectId>> {
+    let (resources, resource_ids) = document.get_page_resources(page);
+    let resources = resources.into_iter().chain(
+        resource_ids
+            .into_iter()
+            .flat_map(|id| document.get_dictionary(id)),
+    );
+    let mut images = Vec::new();
+    for resources in resources {
+        let Ok(xobjects) = resources.get(b"XObject").and_then(Object::as_dict) else {
+            continue;
+        };
+        for (_, xobject) in xobjects.iter() {
+            let Ok(id) = xobject.as_reference() else {
+                continue;
+            };
+            let is_image = document
+                .get_object(id)
+                .and_then(Object::as_stream)
+                .and_then(|stream| stream.dict.get(b"Subtype"))
+                .and_then(Object::as_name)
+                .is_ok_and(|subtype| subtype == b"Image");
+            if is_image {
+                images.push(id);
+            }
+        }
+    }
+    Ok(images)
+}
+
+/// A CalRGB or CalGray color space with the D65 white point and primaries of sRGB.
+fn calibrated(components: i64, gamma: f32) -> Object {
+    let white_point: Object = vec![0.9505.into(), 1.0.into(), 1.089.into()].into();
+    if components == 1 {
+        let gray = dictionary! { "WhitePoint" => white_point, "Gamma" => gamma };
+        return vec!["CalGray".into(), gray.into()].into();
+    }
+    let matrix = [
+        0.4124, 0.2126, 0.0193, 0.3576, 0.7152, 0.1192, 0.1805, 0.0722, 0.9505,
+    ];
+    let rgb = dictionary! {
+        "WhitePoint" => white_point,
+        "Gamma" => vec![gamma.into(), gamma.into(), gamma.into()],
+        "Matrix" => matrix.into_iter().map(Object::from).collect::<Vec<_>>(),
+    };
+    vec!["CalRGB".into(), rgb.into()].into()
+}
+
+/// Builds a version 2 display profile for sRGB with its D50 adapted primaries and tone curve.
+pub fn srgb_profile() -> Vec<u8> {
+    fn s15f16(value: f64) -> [u8; 4] {
+        ((value * 65536.0).round() as i32).to_be_bytes()
+    }
+    fn xyz([x, y, z]: [f64; 3]) -> Vec<u8> {
+        [
+            b"XYZ \0\0\0\0".as_slice(),
+            &s15f16(x),
+            &s15f16(y),
+            &s15f16(z),
+        ]
+        .concat()
+    }
+    let description = b"sRGB IEC61966-2.1\0";
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend((description.len() as u32).to_be_bytes());
+    desc.extend(description);
+    // empty unicode and scriptcode descriptions
+    desc.extend([0; 4 + 4 + 2 + 1 + 67]);
+    let copyright = [b"text\0\0\0\0".as_slice(), b"No copyright, use freely\0"].concat();
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    curve.extend(1024u32.to_be_bytes());
+    for i in 0..1024 {
+        let encoded = i as f64 / 1023.0;
+        let linear = if encoded <= 0.04045 {
+            encoded / 12.92
+        } else {
+            ((encoded + 0.055) / 1.055).powf(2.4)
+        };
+        curve.extend(((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+    let tags: [(&[u8; 4], Vec<u8>); 7] = [
+        (b"desc", desc),
+        (b"cprt", copyright),
+        (b"wtpt", xyz([0.9642, 1.0, 0.8249])),
+        (b"rXYZ", xyz([0.4360, 0.2225, 0.0139])),
+        (b"gXYZ", xyz([0.3851, 0.7169, 0.0971])),
+        (b"bXYZ", xyz([0.1431, 0.0606, 0.7141])),
+        (b"rTRC", curve),
+    ];
+    // the green and blue curves share the data of the red one
+    let count = tags.len() + 2;
+    let mut table = (count as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    let mut offset = 128 + 4 + 12 * count;
+    let mut curve_entry = Vec::new();
+    for (signature, tag) in &tags {
+        let entry = [
+            signature.as_slice(),
+            &(offset as u32).to_be_bytes(),
+            &(tag.len() as u32).to_be_bytes(),
+        ]
+        .concat();
+        if *signature == b"rTRC" {
+            curve_entry = entry[4..].to_vec();
+        }
+        table.extend(entry);
+        data.extend(tag);
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        offset = 128 + 4 + 12 * count + data.len();
+    }
+    for signature in [b"gTRC", b"bTRC"] {
+        table.extend(signature);
+        table.extend(&curve_entry);
+    }
+    let size = 128 + table.len() + data.len();
+    let mut header = Vec::with_capacity(size);
+    header.extend((size as u32).to_be_bytes());
+    header.extend([0; 4]);
+    header.extend(0x0210_0000u32.to_be_bytes());
+    header.extend(b"mntrRGB XYZ ");
+    // creation date
+    header.extend([0x07, 0xCE, 0, 2, 0, 9, 0, 6, 0, 0x31, 0, 0]);
+    header.extend(b"acsp");
+    header.extend([0; 4 + 4 + 4 + 4 + 8 + 4]);
+    header.extend(s15f16(0.9642));
+    header.extend(s15f16(1.0));
+    header.extend(s15f16(0.8249));
+    header.resize(128, 0);
+    [header, table, data].concat()
+}
@@ -0,0 +1,212 @@
+use std::fmt::Write;
+
+use clap::{Arg, ArgAction, Command, ValueEnum};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// Generates a completion script for `shell` from the flags and subcommands of `command`.
+pub fn generate(shell: Shell, command: &Command) -> String {
+    match shell {
+        Shell::Bash => bash(command),
+        // zsh runs bash completions through bashcompinit
+        Shell::Zsh => format!(
+            "#compdef {0}\nautoload -U +X bashcompinit && bashcompinit\n{1}",
+            command.get_name(),
+            bash(command)
+        ),
+        Shell::Fish => fish(command),
+        Shell::Powershell => powershell(command),
+    }
+}
+
+/// The flags of `command` that take values, along with their possible values if they are known.
+fn options(command: &Command) -> Vec<(String, Vec<String>)> {
+    command
+        .get_arguments()
+        .filter(|arg| takes_value(arg))
+        .flat_map(|arg| {
+            let values = possible_values(arg);
+            flags(arg)
+                .into_iter()
+                .map(move |flag| (flag, values.clone()))
+        })
+        .collect()
+}
+
+fn flags(arg: &Arg) -> Vec<String> {
+    let long = arg.get_long().map(|long| format!("--{}", long));
+    let short = arg.get_short().map(|short| format!("-{}", short));
+    long.into_iter().chain(short).collect()
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::Set | ArgAction::Append)
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
+
+fn subcommands(command: &Command) -> impl Iterator<Item = &Command> {
+    command
+        .get_subcommands()
+        .filter(|subcommand| subcommand.get_name() != "help")
+}
+
+fn bash(command: &Command) -> String {
+    let name = command.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    let mut script = format!(
+        concat!(
+            "{function}() {{\n",
+            "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"",
+            " prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"\n",
+            "    case \"${{COMP_WORDS[1]}}\" in\n",
+        ),
+        function = function
+    );
+    let mut cases = subcommands(command)
+        .map(|subcommand| (format!("{})", subcommand.get_name()), subcommand, false))
+        .collect::<Vec<_>>();
+    cases.push(("*)".to_string(), command, true));
+    for (pattern, command, top_level) in cases {
+        let mut words = command.get_arguments().flat_map(flags).collect::<Vec<_>>();
+        if top_level {
+            words.extend(subcommands(command).map(|subcommand| subcommand.get_name().to_string()));
+        }
+        let _ = writeln!(script, "        {}\n            case \"$prev\" in", pattern);
+        for (flag, values) in options(command) {
+            let completion = if values.is_empty() {
+                "-f -- \"$cur\"".to_string()
+            } else {
+                format!("-W \"{}\" -- \"$cur\"", values.join(" "))
+            };
+            let _ = writeln!(
+                script,
+                "                {}) COMPREPLY=($(compgen {})); return ;;",
+                flag, completion
+            );
+        }
+        let _ = writeln!(
+            script,
+            "            esac\n            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")) ;;",
+            words.join(" ")
+        );
+    }
+    let _ = writeln!(
+        script,
+        "    esac\n}}\ncomplete -o default -F {} {}",
+        function, name
+    );
+    script
+}
+
+fn fish(command: &Command) -> String {
+    let name = command.get_name();
+    let mut script = String::new();
+    for subcommand in subcommands(command) {
+        let _ = writeln!(
+            script,
+            "complete -c {} -n __fish_use_subcommand -f -a {} -d '{}'",
+            name,
+            subcommand.get_name(),
+            fish_escape(&about(subcommand))
+        );
+    }
+    let mut commands = vec![(command, "__fish_use_subcommand".to_string())];
+    commands.extend(subcommands(command).map(|subcommand| {
+        let condition = format!("'__fish_seen_subcommand_from {}'", subcommand.get_name());
+        (subcommand, condition)
+    }));
+    for (command, condition) in commands {
+        for arg in command.get_arguments() {
+            if arg.get_long().is_none() && arg.get_short().is_none() {
+                continue;
+            }
+            let mut line = format!("complete -c {} -n {}", name, condition);
+            if let Some(long) = arg.get_long() {
+                let _ = write!(line, " -l {}", long);
+            }
+            if let Some(short) = arg.get_short() {
+                let _ = write!(line, " -s {}", short);
+            }
+            if takes_value(arg) {
+                line.push_str(" -r");
+                let values = possible_values(arg);
+                if !values.is_empty() {
+                    let _ = write!(line, " -f -a '{}'", values.join(" "));
+                }
+            }
+            let help = arg
+                .get_help()
+                .map(|help| help.to_string())
+                .unwrap_or_default();
+            let _ = writeln!(line, " -d '{}'", fish_escape(first_line(&help)));
+            script.push_str(&line);
+        }
+    }
+    script
+}
+
+fn powershell(command: &Command) -> String {
+    let name = command.get_name();
+    let mut cases = String::new();
+    let mut commands = subcommands(command)
+        .map(|subcommand| (format!("'{}'", subcommand.get_name()), subcommand, false))
+        .collect::<Vec<_>>();
+    commands.push(("default".to_string(), command, true));
+    for (pattern, command, top_level) in commands {
+        let mut words = command.get_arguments().flat_map(flags).collect::<Vec<_>>();
+        if top_level {
+            words.extend(subcommands(command).map(|subcommand| subcommand.get_name().to_string()));
+        }
+        let words = words
+            .iter()
+            .map(|word| format!("'{}'", word))
+            .collect::<Vec<_>>();
+        let _ = writeln!(cases, "        {} {{ @({}) }}", pattern, words.join(", "));
+    }
+    format!(
+        concat!(
+            "Register-ArgumentCompleter -Native -CommandName '{name}' -ScriptBlock {{\n",
+            "    param($wordToComplete, $commandAst, $cursorPosition)\n",
+            "    $subcommand = if ($commandAst.CommandElements.Count -gt 1) {{",
+            " $commandAst.CommandElements[1].ToString() }} else {{ '' }}\n",
+            "    $words = switch ($subcommand) {{\n",
+            "{cases}",
+            "    }}\n",
+            "    $words | Where-Object {{ $_ -like \"$wordToComplete*\" }} | ForEach-Object {{\n",
+            "        [System.Management.Automation.CompletionResult]::new(",
+            "$_, $_, 'ParameterName', $_)\n",
+            "    }}\n",
+            "}}\n",
+        ),
+        name = name,
+        cases = cases
+    )
+}
+
+fn about(command: &Command) -> String {
+    let about = command
+        .get_about()
+        .map(|about| about.to_string())
+        .unwrap_or_default();
+    first_line(&about).to_string()
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
@@ -0,0 +1,90 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+use flate2::write::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use reqwest::header::{HeaderMap, CONTENT_ENCODING, CONTENT_LENGTH};
+
+/// The content codings asked for, the ones flate2 decodes.
+pub const ACCEPT_ENCODING: &str = "gzip, deflate";
+
+/// Decodes a response body as its chunks come in, so the compressed body is never held whole.
+pub enum Decoder {
+    Identity(Vec<u8>),
+    Gzip(GzDecoder<Vec<u8>>),
+    /// Servers send "deflate" both as zlib, as it should be, and as raw deflate data, told apart
+    /// by the first two bytes.
+    Deflate(Vec<u8>),
+    Zlib(ZlibDecoder<Vec<u8>>),
+    RawDeflate(DeflateDecoder<Vec<u8>>),
+}
+
+impl Decoder {
+    /// Returns the decoder for the Content-Encoding of a response.
+    pub fn new(headers: &HeaderMap) -> Result<Self> {
+        let coding = headers.get(CONTENT_ENCODING).map(|value| {
+            value
+                .to_str()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase()
+        });
+        Ok(match coding.as_deref() {
+            None | Some("" | "identity") => Decoder::Identity(Vec::new()),
+            Some("gzip" | "x-gzip") => Decoder::Gzip(GzDecoder::new(Vec::new())),
+            Some("deflate") => Decoder::Deflate(Vec::new()),
+            Some(coding) => bail!(
+                "the response is encoded with {}, which isn't supported",
+                coding
+            ),
+        })
+    }
+
+    /// Whether the body comes compressed.
+    pub fn is_compressed(&self) -> bool {
+        !matches!(self, Decoder::Identity(_))
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        match self {
+            Decoder::Identity(body) => body.extend_from_slice(chunk),
+            Decoder::Gzip(decoder) => decoder.write_all(chunk)?,
+            Decoder::Zlib(decoder) => decoder.write_all(chunk)?,
+            Decoder::RawDeflate(decoder) => decoder.write_all(chunk)?,
+            Decoder::Deflate(start) => {
+                start.extend_from_slice(chunk);
+                if start.len() >= 2 {
+                    let start = std::mem::take(start);
+                    *self = if is_zlib(&start) {
+                        Decoder::Zlib(ZlibDecoder::new(Vec::new()))
+                    } else {
+                        Decoder::RawDeflate(DeflateDecoder::new(Vec::new()))
+                    };
+                    self.write(&start)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the decoded body.
+    pub fn finish(self) -> Result<Vec<u8>> {
+        Ok(match self {
+            Decoder::Identity(body) => body,
+            Decoder::Gzip(decoder) => decoder.finish()?,
+            Decoder::Zlib(decoder) => decoder.finish()?,
+            Decoder::RawDeflate(decoder) => decoder.finish()?,
+            Decoder::Deflate(start) if start.is_empty() => start,
+            Decoder::Deflate(_) => bail!("the deflate data of the response is cut short"),
+        })
+    }
+}
+
+/// Removes the headers that describe the body as it was sent, once it is decoded.
+pub fn strip_encoding(headers: &mut HeaderMap) {
+    headers.remove(CONTENT_ENCODING);
+    headers.remove(CONTENT_LENGTH);
+}
+
+fn is_zlib(start: &[u8]) -> bool {
+    start[0] & 0x0f == 8 && u16::from_be_bytes([start[0], start[1]]).is_multiple_of(31)
+}
@@ -0,0 +1,93 @@
+use std::{env, fs, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use sonic_rs::Deserialize;
+
+/// Settings that rarely change between runs, read from `config.json` in the configuration
+/// directory.
+#[derive(Default, Deserialize)]
+pub struct Config {
+    /// The mail server used by --send-to and the job reports.
+    #[serde(default)]
+    pub smtp: Option<Smtp>,
+    /// Email a report of every job the server finishes.
+    #[serde(default)]
+    pub notify: Option<Notify>,
+    /// The account used by --upload s3://.
+    #[serde(default)]
+    pub s3: Option<S3>,
+    /// The account used by --upload with a WebDAV URL.
+    #[serde(default)]
+    pub webdav: Option<WebDav>,
+}
+
+#[derive(Deserialize)]
+pub struct Smtp {
+    pub host: String,
+    /// 465 for TLS from the start, or 587 with `starttls`.
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Upgrade a plain connection with STARTTLS rather than connecting with TLS.
+    #[serde(default)]
+    pub starttls: bool,
+    pub username: String,
+    pub password: String,
+    /// The sender address, which must be on the approved list of a Kindle.
+    pub from: String,
+}
+
+fn default_port() -> u16 {
+    465
+}
+
+#[derive(Deserialize)]
+pub struct Notify {
+    /// The address the reports go to.
+    pub email: String,
+    /// Attach the document of a job that succeeded if it is at most this many bytes, such as
+    /// 20000000 to stay under the limit of most mail servers.
+    #[serde(default)]
+    pub attach_max: Option<u64>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct S3 {
+    /// The URL of a compatible object storage such as MinIO, or none for Amazon S3.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+#[derive(Clone, Deserialize)]
+pub struct WebDav {
+    pub username: String,
+    pub password: String,
+}
+
+impl Config {
+    /// Returns the saved configuration, or the defaults when there is none.
+    pub fn load() -> Result<Self> {
+        match fs::read_to_string(dir()?.join("config.json")) {
+            Ok(saved) => Ok(sonic_rs::from_str(&saved)?),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+/// The configuration directory of the platform.
+pub fn dir() -> Result<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .or_else(|| env::var_os("APPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .ok_or_else(|| anyhow!("no configuration directory"))?;
+    Ok(config.join("pearson-plus-extractor"))
+}
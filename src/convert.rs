@@ -0,0 +1,43 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::calibre::Metadata;
+
+/// Converts the document at `path` into an AZW3 next to it with Calibre's `ebook-convert`
+/// command, and returns the path of the AZW3.
+pub fn azw3(path: &Path, metadata: &Metadata) -> Result<PathBuf> {
+    let output = path.with_extension("azw3");
+    let mut command = Command::new("ebook-convert");
+    command.arg(path).arg(&output);
+    if let Some(title) = &metadata.title {
+        command.args(["--title", title]);
+    }
+    if !metadata.authors.is_empty() {
+        // ebook-convert separates authors with ampersands
+        command.args(["--authors", &metadata.authors.join(" & ")]);
+    }
+    if let Some(isbn) = &metadata.isbn {
+        command.args(["--isbn", isbn]);
+    }
+    if let Some(series) = &metadata.series {
+        command.args(["--series", series]);
+    }
+    if let Some(edition) = metadata.edition {
+        command.args(["--series-index", &edition.to_string()]);
+    }
+    if let Some(year) = metadata.year {
+        command.args(["--pubdate", &year.to_string()]);
+    }
+    let status = command
+        .stdout(Stdio::null())
+        .status()
+        .map_err(|error| anyhow!("failed to run ebook-convert from Calibre: {}", error))?;
+    if !status.success() {
+        bail!("ebook-convert exited with {}", status);
+    }
+    Ok(output)
+}
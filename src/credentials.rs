@@ -0,0 +1,190 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::{self, OpenOptions},
+    io::{self, IsTerminal, Write},
+    net::{SocketAddr, TcpListener},
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use sonic_rs::{Deserialize, Serialize};
+
+use crate::{config, filename, http};
+
+/// How long `login --listen` waits for the browser.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(300);
+/// The origin of the bookmarklet of `login --listen`.
+const ORIGIN: &str = "https://plus.pearson.com";
+
+/// The headers of a plus.pearson.com session.
+#[derive(Deserialize, Serialize)]
+pub struct Credentials {
+    pub cookie: String,
+    #[serde(default)]
+    pub auth_token: Option<String>,
+}
+
+impl Credentials {
+    /// Returns the credentials saved by `login` under `profile`, if any.
+    pub fn load(profile: Option<&str>) -> Result<Option<Self>> {
+        match fs::read_to_string(path(profile)?) {
+            Ok(saved) => Ok(Some(sonic_rs::from_str(&saved)?)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn save(&self, profile: Option<&str>) -> Result<PathBuf> {
+        let path = path(profile)?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // the session is as good as a password, so nobody else may read it even for a moment
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // a file saved before keeps its permissions when opened
+            if path.exists() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        options
+            .open(&path)?
+            .write_all(sonic_rs::to_string(self)?.as_bytes())?;
+        Ok(path)
+    }
+}
+
+/// Finds the access token the web app sends as X-Authorization among the cookies of the session,
+/// a JSON Web Token such as `eyJhbGciOi...`, and returns the name of its cookie along with it.
+pub fn derive_auth_token(cookie: &str) -> Option<(&str, &str)> {
+    cookie.split(';').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        let value = value.trim().trim_matches('"');
+        let segments = value.split('.').collect::<Vec<_>>();
+        let is_jwt = value.starts_with("eyJ")
+            && segments.len() == 3
+            && segments.iter().all(|segment| {
+                !segment.is_empty()
+                    && segment
+                        .bytes()
+                        .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+            });
+        is_jwt.then_some((name.trim(), value))
+    })
+}
+
+/// The error of a request refused because the session expired.
+#[derive(Debug)]
+pub struct SessionExpired;
+
+impl Display for SessionExpired {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "the session expired")
+    }
+}
+
+impl std::error::Error for SessionExpired {}
+
+/// Asks for a fresh cookie on the terminal, or for the session saved under `profile` once the
+/// login command saved a new one. Fails with `SessionExpired` at once when there is no terminal to
+/// ask on, such as in a queued job.
+pub fn renew(profile: Option<&str>) -> Result<Credentials> {
+    if !io::stdin().is_terminal() {
+        return Err(SessionExpired.into());
+    }
+    println!("The session expired. Paste a fresh value of the Cookie header,");
+    println!("or press enter once a new session was saved with the login command.");
+    let mut line = String::new();
+    if io::stdin().read_line(&mut line)? == 0 {
+        return Err(SessionExpired.into());
+    }
+    let cookie = line.trim();
+    if !cookie.is_empty() {
+        return Ok(Credentials {
+            cookie: cookie.to_string(),
+            auth_token: None,
+        });
+    }
+    // without a saved session the expired one is all there is
+    Credentials::load(profile)?.ok_or_else(|| SessionExpired.into())
+}
+
+/// Whether `origin` is that of a browser extension.
+fn is_extension(origin: &str) -> bool {
+    [
+        "chrome-extension://",
+        "moz-extension://",
+        "safari-web-extension://",
+    ]
+    .iter()
+    .any(|scheme| origin.starts_with(scheme))
+}
+
+/// The credentials file of `profile` in the configuration directory of the platform, such as
+/// `credentials-work.json`, or `credentials.json` for the default profile.
+fn path(profile: Option<&str>) -> Result<PathBuf> {
+    let name = match profile {
+        Some(profile) => format!("credentials-{}.json", filename::sanitize(profile)),
+        None => "credentials.json".to_string(),
+    };
+    Ok(config::dir()?.join(name))
+}
+
+/// Waits on `addr` for a browser extension or bookmarklet on plus.pearson.com to POST the
+/// session headers as `{"cookie": "...", "auth_token": "..."}` to `/<nonce>`, giving up after a
+/// few minutes. The nonce is only good for one login.
+pub fn receive(addr: SocketAddr, nonce: &str) -> Result<Credentials> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let deadline = Instant::now() + LOGIN_TIMEOUT;
+    let cors = [
+        ("Access-Control-Allow-Origin", ORIGIN),
+        ("Access-Control-Allow-Methods", "POST"),
+        ("Access-Control-Allow-Headers", "Content-Type"),
+    ];
+    let path = format!("/{nonce}");
+    while Instant::now() < deadline {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            Err(_) => continue,
+        };
+        // a client that goes away or sends garbage doesn't end the login
+        let Ok(request) = stream
+            .set_nonblocking(false)
+            .and_then(|_| http::read_request(&stream))
+        else {
+            continue;
+        };
+        // extensions send their own origin, and others may not post the session at all
+        let origin = request.header("origin");
+        if request.path != path
+            || origin.is_some_and(|origin| origin != ORIGIN && !is_extension(origin))
+        {
+            let _ = http::write_response(&stream, "403 Forbidden", &cors, b"");
+            continue;
+        }
+        let _ = match request.method.as_str() {
+            "POST" => match sonic_rs::from_slice::<Credentials>(&request.body) {
+                Ok(credentials) => {
+                    let _ = http::write_response(&stream, "200 OK", &cors, b"Logged in.");
+                    return Ok(credentials);
+                }
+                Err(error) => {
+                    let message = format!("Expected {{\"cookie\", \"auth_token\"}}: {error}");
+                    http::write_response(&stream, "400 Bad Request", &cors, message.as_bytes())
+                }
+            },
+            "OPTIONS" => http::write_response(&stream, "204 No Content", &cors, b""),
+            _ => http::write_response(&stream, "405 Method Not Allowed", &cors, b""),
+        };
+    }
+    Err(anyhow!("no credentials were received in time"))
+}
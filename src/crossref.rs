@@ -0,0 +1,147 @@
+use anyhow::Result;
+use lopdf::{dictionary, Document, Object};
+
+use crate::{toc::Toc, Text, TextPageData};
+
+/// The words a reference to a numbered part of the book starts with.
+const KEYWORDS: [&str; 3] = ["section", "chapter", "§"];
+
+/// A link from a rectangle of a page, in points, to another page of the book.
+pub struct Link {
+    pub page: u32,
+    pub rect: [f32; 4],
+    pub target: u32,
+}
+
+/// Finds references such as "see Section 3.2" or "Chapter 4" in the text runs of `page` and
+/// returns a link to the TOC entry numbered alike for each.
+pub fn find(page: u32, texts: &TextPageData, toc: &Toc) -> Vec<Link> {
+    let entries = toc.flatten();
+    let mut links = Vec::new();
+    for text in &texts.data {
+        let chars = chars(text);
+        let mut i = 0;
+        while i < chars.len() {
+            let Some(end) = reference_end(&chars, i) else {
+                i += 1;
+                continue;
+            };
+            let phrase = chars[i..end]
+                .iter()
+                .map(|(char, _)| char)
+                .collect::<String>();
+            let number = phrase.rsplit(' ').next().unwrap_or_default();
+            let number = number.trim_start_matches('§');
+            let target = entries
+                .iter()
+                .find(|(_, entry)| numbered(&entry.title) == Some(number))
+                .map(|(_, entry)| entry.page);
+            // a heading naming its own section isn't worth a link
+            if let Some(target) = target.filter(|&target| target != page) {
+                links.push(Link {
+                    page,
+                    rect: bounds(chars[i..end].iter().map(|(_, rect)| rect)),
+                    target,
+                });
+            }
+            i = end;
+        }
+    }
+    links
+}
+
+/// Returns the characters of a text run along with their bounding boxes in points.
+pub fn chars(text: &Text) -> Vec<(char, [f32; 4])> {
+    text.stream
+        .iter()
+        .filter_map(|&(x, y, w, h, char)| Some((char::from_u32(char)?, [x, y, x + w, y + h])))
+        .collect()
+}
+
+/// Returns the end of the reference starting at `start`, a keyword followed by a number such as
+/// `3` or `3.2`, if there is one.
+fn reference_end(chars: &[(char, [f32; 4])], start: usize) -> Option<usize> {
+    if start > 0 && chars[start - 1].0.is_alphanumeric() {
+        return None;
+    }
+    let head = chars[start..]
+        .iter()
+        .take(7)
+        .map(|(char, _)| char.to_ascii_lowercase())
+        .collect::<String>();
+    let keyword = KEYWORDS.iter().find(|keyword| head.starts_with(*keyword))?;
+    let mut end = start + keyword.chars().count();
+    while chars.get(end).is_some_and(|(char, _)| *char == ' ') {
+        end += 1;
+    }
+    let number_start = end;
+    while let Some((char, _)) = chars.get(end) {
+        let digit_follows = chars
+            .get(end + 1)
+            .is_some_and(|(next, _)| next.is_ascii_digit());
+        if char.is_ascii_digit() || (*char == '.' && end > number_start && digit_follows) {
+            end += 1;
+        } else {
+            break;
+        }
+    }
+    (end > number_start).then_some(end)
+}
+
+/// Returns the number a TOC entry title starts with, such as `3.2` for "3.2 Membranes" or `4`
+/// for "Chapter 4: Cells".
+fn numbered(title: &str) -> Option<&str> {
+    let mut title = title.trim();
+    for keyword in ["Chapter ", "Section ", "§"] {
+        title = title.strip_prefix(keyword).unwrap_or(title);
+    }
+    let end = title
+        .find(|char: char| !char.is_ascii_digit() && char != '.')
+        .unwrap_or(title.len());
+    let number = title[..end].trim_end_matches('.');
+    (!number.is_empty()).then_some(number)
+}
+
+/// Returns the smallest rectangle containing all of `rects`.
+pub fn bounds<'a>(rects: impl Iterator<Item = &'a [f32; 4]>) -> [f32; 4] {
+    rects.fold(
+        [f32::MAX, f32::MAX, f32::MIN, f32::MIN],
+        |[x0, y0, x1, y1], rect| {
+            [
+                x0.min(rect[0]),
+                y0.min(rect[1]),
+                x1.max(rect[2]),
+                y1.max(rect[3]),
+            ]
+        },
+    )
+}
+
+/// Adds a link annotation for every link, with book page `p` as page `p + 1` of `document` as it
+/// is before pages are inserted or removed, skipping links to pages the document leaves out.
+pub fn add_links(document: &mut Document, links: &[Link], removed_pages: &[u32]) -> Result<()> {
+    let pages = document.get_pages();
+    for link in links {
+        if removed_pages.contains(&link.target) {
+            continue;
+        }
+        let (Some(&page), Some(&target)) =
+            (pages.get(&(link.page + 1)), pages.get(&(link.target + 1)))
+        else {
+            continue;
+        };
+        let annotation = document.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => link.rect.iter().map(|&value| value.into()).collect::<Vec<Object>>(),
+            "Border" => vec![0.into(), 0.into(), 0.into()],
+            "Dest" => vec![target.into(), "Fit".into()],
+        });
+        let page = document.get_dictionary_mut(page)?;
+        match page.get_mut(b"Annots").and_then(Object::as_array_mut) {
+            Ok(annotations) => annotations.push(annotation.into()),
+            Err(_) => page.set("Annots", vec![annotation.into()]),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,16 @@
+use std::io::{Result, Write};
+
+/// Writes one CSV record, quoting the fields that need it.
+pub fn write_record(output: &mut impl Write, fields: &[&str]) -> Result<()> {
+    let fields = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    write!(output, "{}\r\n", fields.join(","))
+}
@@ -0,0 +1,74 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use lopdf::{Document, Object, ObjectId};
+
+/// Makes every page drawing an identical image, such as a repeated blank or filler page, use
+/// one shared image object. Returns the number of duplicates removed.
+pub fn dedupe_images(document: &mut Document) -> usize {
+    let mut originals = HashMap::<u64, Vec<ObjectId>>::new();
+    let mut replacements = HashMap::new();
+    for (&id, object) in &document.objects {
+        let Ok(stream) = object.as_stream() else {
+            continue;
+        };
+        if !stream
+            .dict
+            .get(b"Subtype")
+            .and_then(Object::as_name)
+            .is_ok_and(|subtype| subtype == b"Image")
+        {
+            continue;
+        }
+        let mut hasher = DefaultHasher::new();
+        stream.content.hash(&mut hasher);
+        let candidates = originals.entry(hasher.finish()).or_default();
+        let original = candidates.iter().find(|&&candidate| {
+            let candidate = document.objects[&candidate].as_stream().unwrap();
+            // Dictionary doesn't implement PartialEq
+            candidate.content == stream.content
+                && format!("{:?}", candidate.dict) == format!("{:?}", stream.dict)
+        });
+        match original {
+            Some(&original) => {
+                replacements.insert(id, original);
+            }
+            None => candidates.push(id),
+        }
+    }
+    for object in document.objects.values_mut() {
+        replace_references(object, &replacements);
+    }
+    for duplicate in replacements.keys() {
+        document.objects.remove(duplicate);
+    }
+    replacements.len()
+}
+
+fn replace_references(object: &mut Object, replacements: &HashMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => {
+            if let Some(&original) = replacements.get(id) {
+                *id = original;
+            }
+        }
+        Object::Array(array) => {
+            for item in array {
+                replace_references(item, replacements);
+            }
+        }
+        Object::Dictionary(dict) => {
+            for (_, value) in dict.iter_mut() {
+                replace_references(value, replacements);
+            }
+        }
+        Object::Stream(stream) => {
+            for (_, value) in stream.dict.iter_mut() {
+                replace_references(value, replacements);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,23 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+
+/// Copies the document at `path` onto the e-reader mounted at `device`, into the `documents`
+/// directory Kindles read from if there is one, and returns where it was copied.
+pub fn copy_to_device(path: &Path, device: &Path) -> Result<PathBuf> {
+    let documents = device.join("documents");
+    let dir = if documents.is_dir() {
+        documents
+    } else {
+        device.to_path_buf()
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} is not a file", path.display()))?;
+    let destination = dir.join(name);
+    fs::copy(path, &destination)?;
+    Ok(destination)
+}
@@ -0,0 +1,74 @@
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Result};
+
+/// Shows a notification with `title` and `body` on the desktop, with `notify-send` on Linux and
+/// the BSDs, `osascript` on macOS and PowerShell on Windows.
+pub fn notify(title: &str, body: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification {} with title {}",
+            apple_string(body),
+            apple_string(title)
+        );
+        let mut command = Command::new("osascript");
+        command.args(["-e", &script]);
+        command
+    } else if cfg!(windows) {
+        // a balloon tip needs nothing beyond Windows Forms, unlike a toast, but disappears with
+        // the icon, so PowerShell keeps it for as long as the tip shows, without being waited for
+        let script = format!(
+            "Add-Type -AssemblyName System.Windows.Forms; \
+             $icon = New-Object System.Windows.Forms.NotifyIcon; \
+             $icon.Icon = [System.Drawing.SystemIcons]::Information; \
+             $icon.Visible = $true; \
+             $icon.ShowBalloonTip(10000, {}, {}, 'Info'); \
+             Start-Sleep -Seconds 10; \
+             $icon.Dispose()",
+            powershell_string(title),
+            powershell_string(body)
+        );
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-NonInteractive", "-Command", &script]);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["--app-name", "pearson-plus-extractor", "--", title, body]);
+        command
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    command.stdout(Stdio::null()).stderr(Stdio::null());
+    if cfg!(windows) {
+        command
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|error| anyhow!("failed to run {}: {}", program, error))?;
+        return Ok(());
+    }
+    let status = command
+        .status()
+        .map_err(|error| anyhow!("failed to run {}: {}", program, error))?;
+    if !status.success() {
+        bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+/// Quotes `text` as an AppleScript string.
+fn apple_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Quotes `text` as a PowerShell string that nothing is expanded in, doubling every quote that
+/// PowerShell takes as a single one, including the typographic ones.
+fn powershell_string(text: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in text.chars() {
+        if matches!(c, '\'' | '\u{2018}'..='\u{201b}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
@@ -0,0 +1,72 @@
+/// The ordinal words editions are spelled out with, from the first.
+const ORDINALS: [&str; 20] = [
+    "first",
+    "second",
+    "third",
+    "fourth",
+    "fifth",
+    "sixth",
+    "seventh",
+    "eighth",
+    "ninth",
+    "tenth",
+    "eleventh",
+    "twelfth",
+    "thirteenth",
+    "fourteenth",
+    "fifteenth",
+    "sixteenth",
+    "seventeenth",
+    "eighteenth",
+    "nineteenth",
+    "twentieth",
+];
+
+/// Finds the edition printed in the front matter of a book, such as "Eighth Edition",
+/// "8th edition" or "Edition 8".
+pub fn detect(texts: &[String]) -> Option<u32> {
+    for text in texts {
+        let words = text
+            .split(|char: char| !char.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>();
+        for (i, word) in words.iter().enumerate() {
+            if word != "edition" {
+                continue;
+            }
+            let before = i.checked_sub(1).and_then(|i| number(&words[i]));
+            let after = words.get(i + 1).and_then(|word| word.parse().ok());
+            if let Some(edition) = before
+                .or(after)
+                .filter(|edition| (1..100).contains(edition))
+            {
+                return Some(edition);
+            }
+        }
+    }
+    None
+}
+
+/// Reads an ordinal such as `eighth` or `8th`.
+fn number(word: &str) -> Option<u32> {
+    if let Some(i) = ORDINALS.iter().position(|ordinal| *ordinal == word) {
+        return Some(i as u32 + 1);
+    }
+    let digits = word.trim_end_matches(|char: char| char.is_ascii_alphabetic());
+    matches!(&word[digits.len()..], "st" | "nd" | "rd" | "th")
+        .then(|| digits.parse().ok())
+        .flatten()
+}
+
+/// Writes an edition as an ordinal, such as `8th`.
+pub fn ordinal(edition: u32) -> String {
+    let suffix = match (edition % 10, edition % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{edition}{suffix}")
+}
@@ -0,0 +1,82 @@
+use anyhow::Error;
+
+use crate::{credentials::SessionExpired, redact, Intercepted};
+
+/// Any other failure.
+pub const FAILURE: i32 = 1;
+/// There is no session, or it expired and wasn't renewed.
+pub const AUTH: i32 = 2;
+/// A request failed after its retries.
+pub const NETWORK: i32 = 3;
+/// The document was written, but some pages have a damaged text layer or it is over --max-size.
+pub const PARTIAL: i32 = 4;
+/// The output can't be written, because it exists or the disk is too full.
+pub const OUTPUT: i32 = 5;
+/// The command line or configuration is invalid.
+pub const USAGE: i32 = 64;
+
+/// Returns the exit code for a run that failed with `error`.
+pub fn code(error: &Error) -> i32 {
+    if error.is::<SessionExpired>() {
+        AUTH
+    } else if error.is::<Intercepted>() || error.chain().any(|cause| cause.is::<reqwest::Error>()) {
+        NETWORK
+    } else {
+        FAILURE
+    }
+}
+
+/// Returns what to do about a failure whose `message` points at the network rather than the
+/// book or the session.
+fn hint(error: &Error, message: &str) -> Option<&'static str> {
+    let message = message.to_lowercase();
+    Some(match error.downcast_ref::<Intercepted>() {
+        Some(Intercepted::GeoBlocked) => {
+            "Pearson+ isn't available where you are connecting from. Set HTTPS_PROXY to a proxy \
+             in a country it serves, or connect through a VPN."
+        }
+        Some(Intercepted::CaptivePortal) => {
+            "Your network answered with a sign-in page. Open a browser, sign in to the network \
+             and try again."
+        }
+        None if message.contains("dns error") || message.contains("failed to lookup address") => {
+            "plus.pearson.com couldn't be found. Check that you are online and that your DNS \
+             works, or set HTTPS_PROXY if your network only reaches the internet through a proxy."
+        }
+        None if message.contains("hostname mismatch") || message.contains("not valid for") => {
+            "Something on your network answered for plus.pearson.com with a certificate of its \
+             own, such as the sign-in page of a Wi-Fi network. Sign in to the network, or pass \
+             the certificate of its proxy with --cacert."
+        }
+        None if message.contains("certificate verify failed")
+            || message.contains("self signed certificate")
+            || message.contains("unable to get local issuer certificate") =>
+        {
+            "If your network inspects TLS traffic, pass the certificate of its proxy with --cacert."
+        }
+        None if message.contains("connection refused") || message.contains("timed out") => {
+            "plus.pearson.com couldn't be reached. A firewall may be blocking it, or set \
+             HTTPS_PROXY if your network only reaches the internet through a proxy."
+        }
+        None => return None,
+    })
+}
+
+/// Prints `error` without the secrets of the session, with a hint for the failures of the network
+/// it recognizes, and exits with its code.
+pub fn fail(error: Error) -> ! {
+    let code = code(&error);
+    fail_with(code, error)
+}
+
+/// Prints `error` as `fail` does, but exits with `code`, for failures that only the step they
+/// come from tells apart, such as writing the output.
+pub fn fail_with(code: i32, error: impl Into<Error>) -> ! {
+    let error = error.into();
+    let message = format!("{:#}", error);
+    eprintln!("Error: {}", redact::redact_session(&message));
+    if let Some(hint) = hint(&error, &message) {
+        eprintln!("{hint}");
+    }
+    std::process::exit(code);
+}
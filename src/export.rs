@@ -0,0 +1,96 @@
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{
+    filename,
+    toc::{Entry, Toc},
+    Extraction,
+};
+
+/// Writes the text layer of the book to `path`, as Markdown with the TOC entries as headings if
+/// its name ends in `.md`, and returns the path.
+pub fn write_text(path: &Path, extraction: &Extraction, toc: Option<&Toc>) -> Result<PathBuf> {
+    let headings = toc.map(Toc::flatten).unwrap_or_default();
+    let pages = 0..extraction.page_texts.len() as u32;
+    fs::write(
+        path,
+        render(pages, extraction, &headings, is_markdown(path)),
+    )?;
+    Ok(path.to_path_buf())
+}
+
+/// Writes the text layer of the book with one file per top level TOC entry, numbered and named
+/// after the entries, into a directory named like `path` without its extension. The pages before
+/// the first chapter go into a front matter file. Returns the paths of the files.
+pub fn write_chapters(path: &Path, extraction: &Extraction, toc: &Toc) -> Result<Vec<PathBuf>> {
+    let dir = path.with_extension("");
+    fs::create_dir_all(&dir)?;
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let markdown = is_markdown(path);
+    let headings = toc.flatten();
+    let end = extraction.page_texts.len() as u32;
+    let mut chapters = Vec::new();
+    if let Some(first) = toc.entries.first().filter(|first| first.page > 0) {
+        chapters.push(("Front matter".to_string(), 0..first.page));
+    }
+    for (i, chapter) in toc.entries.iter().enumerate() {
+        let next = toc.entries.get(i + 1).map_or(end, |next| next.page);
+        chapters.push((chapter.title.clone(), chapter.page..next.max(chapter.page)));
+    }
+    let mut paths = Vec::new();
+    for (i, (title, pages)) in chapters.into_iter().enumerate() {
+        let name = filename::sanitize(&format!("{:02} {}{}", i, title, extension));
+        let path = dir.join(name);
+        let headings = headings
+            .iter()
+            .filter(|(_, entry)| pages.contains(&entry.page))
+            .copied()
+            .collect::<Vec<_>>();
+        fs::write(&path, render(pages, extraction, &headings, markdown))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "md")
+}
+
+/// Renders the text of `pages`, skipping the ones left out of the document, with each TOC entry
+/// as a heading above the text of its page.
+fn render(
+    pages: Range<u32>,
+    extraction: &Extraction,
+    headings: &[(usize, &Entry)],
+    markdown: bool,
+) -> String {
+    let mut text = String::new();
+    for page in pages {
+        let Some(page_text) = extraction.page_texts.get(page as usize) else {
+            break;
+        };
+        if extraction.removed_pages.contains(&page) {
+            continue;
+        }
+        for (depth, entry) in headings.iter().filter(|(_, entry)| entry.page == page) {
+            if markdown {
+                text += &format!("{} {}\n\n", "#".repeat((depth + 1).min(6)), entry.title);
+            } else {
+                text += &format!("{}\n\n", entry.title);
+            }
+        }
+        if !page_text.trim().is_empty() {
+            text += page_text.trim_end();
+            text += "\n\n";
+        }
+    }
+    text
+}
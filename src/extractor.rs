@@ -0,0 +1,1332 @@
+//! Downloading the pages of a book from plus.pearson.com and building the document from them,
+//! as the command line does, for programs that run extractions of their own.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs,
+    io::{BufWriter, Cursor, Write},
+    mem,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use futures_util::future::join_all;
+use image::codecs::png::PngDecoder;
+use reqwest::{
+    header::{
+        HeaderMap, HeaderValue, ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_TYPE, COOKIE, ETAG,
+        IF_RANGE, LAST_MODIFIED, RANGE, REFERER,
+    },
+    Certificate, Client, Request, RequestBuilder, StatusCode,
+};
+use ring::digest;
+use sonic_rs::Deserialize;
+use tokio::{join, sync::Semaphore};
+
+#[cfg(feature = "ocr")]
+use crate::ocr;
+use crate::{
+    annotation::{self, UnknownFormat},
+    assemble::{self, Layout, IMAGE_SCALE, PAGE_SCALE},
+    attachments,
+    backlinks::IndexLinks,
+    cache::Cache,
+    calibre,
+    cancel::CancelToken,
+    catalog, color, compression,
+    credentials::{self, SessionExpired},
+    crossref, dedupe, figures, garbled, glyphs, linearize, media,
+    metrics::Counters,
+    numbering::Numbering,
+    objstm::{self, Packing},
+    overlay, probe,
+    progress::{self, Event},
+    raster, redact,
+    script::Script,
+    shrink,
+    sink::{DocumentSink, PdfSink, StreamSink},
+    spill::Spill,
+    split,
+    stamp::{self, Stamp},
+    tables, tags,
+    toc::{self, Toc},
+    warc::{Recorder, Replay},
+    TextPageData,
+};
+
+/// How many HEAD requests --probe times.
+const PROBE_ROUND_TRIPS: u32 = 5;
+/// How many pages --probe downloads to measure the throughput.
+const PROBE_PAGES: u32 = 4;
+/// The most pages the free disk space check assumes a book has.
+const MAX_PAGES: u32 = 10_000;
+
+/// How many more times a page is requested after it failed.
+const RETRIES: u32 = 3;
+
+pub struct Extractor {
+    client: Client,
+    cookie: String,
+    auth_token: String,
+    connection: Connection,
+    cache: Option<Cache>,
+    script: Option<Script>,
+    /// The saved session to renew an expired one from.
+    profile: Option<String>,
+    /// Limits the requests in flight at a time.
+    requests: Option<Semaphore>,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    counters: Counters,
+    progress: progress::Callback,
+    /// Whether the session expired during the run and couldn't be renewed.
+    expired: bool,
+}
+
+/// A response along with its body.
+#[derive(Clone)]
+pub struct Fetched {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// How the client connects to plus.pearson.com.
+#[derive(Default)]
+pub struct Connection {
+    pub http: HttpVersion,
+    /// The most idle connections kept open for reuse, none closes them after each request.
+    pub pool_size: Option<usize>,
+    /// How long an idle connection is kept open for reuse.
+    pub pool_idle_timeout: Option<Duration>,
+    /// The interval of TCP keepalive probes on open connections.
+    pub tcp_keepalive: Option<Duration>,
+    /// Certificates to trust in addition to the ones of the system.
+    pub ca_certs: Vec<Certificate>,
+    /// Accept any certificate, even an invalid one.
+    pub insecure: bool,
+}
+
+/// What the first page of the document shows.
+#[derive(Default)]
+pub enum Cover {
+    /// Page 0 of the book.
+    #[default]
+    Page,
+    /// The cover art at this URL, fitted onto a page the size of page 0.
+    Image(String),
+    /// Nothing, the document starts at page 1.
+    None,
+}
+
+#[derive(Default)]
+pub struct Options {
+    pub metadata: calibre::Metadata,
+    pub cover: Cover,
+    pub toc: Option<Toc>,
+    /// Link references to numbered sections in the text to their TOC entries.
+    pub link_references: bool,
+    /// Link the page numbers of the index pages to their pages.
+    pub index_links: bool,
+    /// List the web addresses printed on the pages on pages at the end of the document.
+    pub media_links: bool,
+    /// Embed the annotation data and TOC the document was built from as file attachments.
+    pub attach_sources: bool,
+    /// Put the page images and the text into separate layers that can be toggled in a viewer.
+    pub layers: bool,
+    /// Draw the text in red over the page images instead of invisible under them.
+    pub text_visible: bool,
+    /// Tag the page images as figures and the text runs as paragraphs, with TOC entries as
+    /// headings.
+    pub tagged: bool,
+    /// The language of the book as a BCP 47 tag.
+    pub lang: Option<String>,
+    /// Embed an sRGB output intent and the color information of the page images.
+    pub color_management: bool,
+    pub image_encoding: raster::Encoding,
+    /// Recompress the page images until the document fits in this many bytes.
+    pub max_size: Option<u64>,
+    /// A header or footer drawn on every page but the cover.
+    pub stamp: Option<Stamp>,
+    /// Export the captioned figures of every page into this directory.
+    pub figures: Option<PathBuf>,
+    /// Export the tables of every page into this directory as CSV.
+    pub extract_tables: Option<PathBuf>,
+    /// Save every page image with the boxes of its text drawn on it into this directory.
+    pub debug_overlay: Option<PathBuf>,
+    /// The map from the codes of the book's fonts to Unicode, for pages whose text isn't.
+    pub cmap: Option<glyphs::Cmap>,
+    /// Share one image object between pages with identical images.
+    pub dedupe_images: bool,
+    /// How the objects of the document and the cross-reference to them are written.
+    pub packing: Packing,
+    /// The PDF version the document declares.
+    pub pdf_version: &'static str,
+    /// Lay the document out for fast web view.
+    pub linearize: bool,
+    /// Keep the pages held in memory under this many bytes.
+    pub max_memory: Option<u64>,
+}
+
+impl Options {
+    /// Whether any option requires editing the document once all pages are drawn.
+    fn post_processes(&self) -> bool {
+        self.toc.is_some()
+            || self.index_links
+            || self.media_links
+            || self.attach_sources
+            || self.tagged
+            || self.lang.is_some()
+            || self.color_management
+            || self.max_size.is_some()
+            || self.dedupe_images
+            || self.packing != Packing::Table
+            || self.linearize
+    }
+}
+
+pub struct Extraction {
+    /// The text layer of every downloaded page, indexed by page number.
+    pub page_texts: Vec<String>,
+    /// The number of generated pages inserted in front of the book.
+    pub front_pages: u32,
+    /// The pages of the book left out of the document, in order.
+    pub removed_pages: Vec<u32>,
+    /// The pages with too many garbled characters in their annotation data, along with the
+    /// garbled and total character counts.
+    pub garbled_pages: Vec<(u32, usize, usize)>,
+    /// The cover image, the PNG of page 0 unless other cover art was downloaded.
+    pub cover: Vec<u8>,
+    /// The figures exported with --figures.
+    pub figures: Vec<figures::Figure>,
+    /// The SHA-256 of the image of every downloaded page, indexed by page number.
+    pub page_digests: Vec<Vec<u8>>,
+    /// Whether the extraction was cancelled, leaving out the pages after the last one it
+    /// downloaded.
+    pub cancelled: bool,
+    /// Whether the session expired and wasn't renewed, leaving out the pages after the last one
+    /// downloaded.
+    pub expired: bool,
+}
+
+impl Extraction {
+    /// Returns the page of the document showing `page` of the book, unless it was left out.
+    pub fn pdf_page(&self, page: u32) -> Option<u32> {
+        if self.removed_pages.contains(&page) {
+            return None;
+        }
+        let before = self
+            .removed_pages
+            .iter()
+            .filter(|&&removed| removed < page)
+            .count() as u32;
+        Some(self.front_pages + page - before + 1)
+    }
+}
+
+impl Extractor {
+    pub fn new(cookie: impl AsRef<str>, auth_token: impl AsRef<str>) -> Result<Self> {
+        Ok(Self {
+            client: Self::client(cookie.as_ref(), auth_token.as_ref(), &Connection::default())?,
+            cookie: cookie.as_ref().to_string(),
+            auth_token: auth_token.as_ref().to_string(),
+            connection: Connection::default(),
+            cache: None,
+            script: None,
+            profile: None,
+            requests: None,
+            recorder: None,
+            replay: None,
+            counters: Counters::default(),
+            progress: Arc::new(progress::print),
+            expired: false,
+        })
+    }
+
+    fn client(cookie: &str, auth_token: &str, connection: &Connection) -> Result<Client> {
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert(REFERER, "https://plus.pearson.com/".parse()?);
+        default_headers.insert(
+            ACCEPT_ENCODING,
+            HeaderValue::from_static(compression::ACCEPT_ENCODING),
+        );
+        // sensitive values are left out of the Debug output of requests and errors
+        let mut cookie = HeaderValue::from_str(cookie)?;
+        cookie.set_sensitive(true);
+        let mut auth_token = HeaderValue::from_str(auth_token)?;
+        auth_token.set_sensitive(true);
+        default_headers.insert(COOKIE, cookie);
+        default_headers.insert("X-Authorization", auth_token);
+        let mut builder = Client::builder()
+            .default_headers(default_headers)
+            .tcp_keepalive(connection.tcp_keepalive)
+            .danger_accept_invalid_certs(connection.insecure);
+        for ca_cert in &connection.ca_certs {
+            builder = builder.add_root_certificate(ca_cert.clone());
+        }
+        builder = match connection.http {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+        if let Some(pool_size) = connection.pool_size {
+            builder = builder.pool_max_idle_per_host(pool_size);
+        }
+        if let Some(pool_idle_timeout) = connection.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(pool_idle_timeout);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Replaces an expired session with fresh credentials, keeping the auth token unless new
+    /// one comes with them.
+    fn renew_session(&mut self) -> Result<()> {
+        let credentials = credentials::renew(self.profile.as_deref())?;
+        if let Some(auth_token) = credentials.auth_token {
+            self.auth_token = auth_token;
+        }
+        self.client = Self::client(&credentials.cookie, &self.auth_token, &self.connection)?;
+        self.cookie = credentials.cookie;
+        redact::install_panic_hook(redact::secrets(&self.cookie, &self.auth_token));
+        Ok(())
+    }
+
+    /// Renews expired sessions from the credentials saved under `profile`.
+    pub fn with_profile(mut self, profile: String) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Connects to plus.pearson.com as described by `connection`.
+    pub fn with_connection(mut self, connection: Connection) -> Result<Self> {
+        self.client = Self::client(&self.cookie, &self.auth_token, &connection)?;
+        self.connection = connection;
+        Ok(self)
+    }
+
+    /// Makes at most `max_requests` requests at a time.
+    pub fn with_max_requests(mut self, max_requests: usize) -> Self {
+        self.requests = Some(Semaphore::new(max_requests.max(1)));
+        self
+    }
+
+    /// Archives every request and response with `recorder`. Assets are requested in full even
+    /// with a cache, so the archive holds all of them.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Answers every request with the responses recorded in `replay` instead of sending it.
+    pub fn with_replay(mut self, replay: Replay) -> Self {
+        self.replay = Some(replay);
+        self
+    }
+
+    /// Keeps the downloaded assets in `cache`, revalidating them on later runs.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Keeps what the extraction counts in `counters`.
+    pub fn with_counters(mut self, counters: Counters) -> Self {
+        self.counters = counters;
+        self
+    }
+
+    /// Reports the progress of the extraction to `progress` instead of printing it.
+    pub fn with_progress(mut self, progress: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        self.progress = Arc::new(progress);
+        self
+    }
+
+    /// Returns what the extraction counted so far.
+    pub fn counters(&self) -> &Counters {
+        &self.counters
+    }
+
+    fn report(&self, event: Event) {
+        (self.progress)(&event);
+    }
+
+    /// Asks `script` about every page as the document is built.
+    pub fn with_script(mut self, script: Script) -> Self {
+        self.script = Some(script);
+        self
+    }
+
+    pub async fn run(
+        &mut self,
+        product_id: u32,
+        uuid: impl AsRef<str>,
+        options: &mut Options,
+        output: impl Write,
+    ) -> Result<Extraction> {
+        self.run_with_cancel(product_id, uuid, options, output, &CancelToken::new())
+            .await
+    }
+
+    /// Runs the extraction as `run` does until `cancel` is cancelled, then stops downloading and
+    /// finishes the document with the pages downloaded so far.
+    pub async fn run_with_cancel(
+        &mut self,
+        product_id: u32,
+        uuid: impl AsRef<str>,
+        options: &mut Options,
+        output: impl Write,
+        cancel: &CancelToken,
+    ) -> Result<Extraction> {
+        self.report(Event::PageStarted(0));
+        let image = loop {
+            let image = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => bail!("cancelled before the first page was downloaded"),
+                image = self.get_image(product_id, uuid.as_ref(), 0) => image,
+            };
+            match image {
+                Err(error) if error.is::<SessionExpired>() => self.renew_session()?,
+                image => break image?.ok_or_else(|| anyhow!("the book has no page 0"))?,
+            }
+        };
+        if options.lang.is_none() {
+            options.lang = self.get_language(product_id, uuid.as_ref()).await;
+            if let Some(lang) = &options.lang {
+                self.report(Event::Info(format!(
+                    "Setting the language to {}, as the metadata of the book gives.",
+                    lang
+                )));
+            }
+        }
+        let title = options.metadata.title.as_deref().unwrap_or("Pearson Plus");
+        let cover_art = match &options.cover {
+            Cover::Image(url) => {
+                let cover_art = self.send(self.client.get(url)).await?;
+                if !cover_art.status.is_success() {
+                    bail!("the cover art request failed with {}", cover_art.status);
+                }
+                Some(cover_art.body)
+            }
+            _ => None,
+        };
+        let mut page_colors = Vec::new();
+        if options.color_management {
+            page_colors.push(match cover_art {
+                Some(_) => None,
+                None => color::read_png_color(&image),
+            });
+        }
+        let mut page_digests = vec![sha256(&image)];
+        let cover = cover_art.clone().unwrap_or_else(|| image.clone());
+        let (w, h) = raster::png_dimensions(&image)
+            .ok_or_else(|| anyhow!("the image of page 0000 is corrupt"))?;
+        let size = assemble::page_size(w, h);
+        let authors = options.metadata.authors.join(", ");
+        let author = Some(authors.as_str()).filter(|authors| !authors.is_empty());
+        // pages can only be written out as they come when nothing edits the document afterwards
+        let streams = !options.post_processes()
+            && self.script.is_none()
+            && !matches!(options.cover, Cover::None);
+        let layout = Layout {
+            title,
+            layers: options.layers,
+            tagged: options.tagged,
+            text_visible: options.text_visible,
+        };
+        // half the memory cap is left for the pages being downloaded and decoded
+        let mut spill = match options.max_memory.filter(|_| !streams) {
+            Some(max_memory) => Some(Spill::new(max_memory / 2)?),
+            None => None,
+        };
+        let mut output = BufWriter::new(output);
+        let mut sink: Box<dyn PdfSink + '_> = match &mut spill {
+            _ if streams => Box::new(StreamSink::with_version(
+                &mut output,
+                options.pdf_version,
+                title,
+                author,
+            )?),
+            Some(spill) => Box::new(DocumentSink::spilling(title, author, spill)),
+            None => Box::new(DocumentSink::new(title, author)),
+        };
+        let (image, placement) = match cover_art {
+            Some(cover_art) => {
+                let cover_art = image::load_from_memory(&cover_art)?;
+                let placement = fit(cover_art.width(), cover_art.height(), size);
+                (
+                    raster::encode(cover_art, options.image_encoding)?,
+                    placement,
+                )
+            }
+            None => (
+                raster::decode(&image, options.image_encoding)?,
+                (0.0, 0.0, w as f32 * IMAGE_SCALE, h as f32 * IMAGE_SCALE),
+            ),
+        };
+        sink.add_page(assemble::cover(image, size, placement, &layout))?;
+        self.report(Event::PageFinished(0));
+        let mut page_texts = vec![String::new()];
+        let mut page_runs = vec![0];
+        let mut attachments = Vec::new();
+        let mut figures = Vec::new();
+        let mut tables = 0;
+        let mut links = Vec::new();
+        let mut reference_texts = Vec::new();
+        let mut index_links = IndexLinks::default();
+        let mut garbled_pages = Vec::new();
+        let mut removed_pages = Vec::new();
+        if let Cover::None = options.cover {
+            removed_pages.push(0);
+        }
+        for dir in [
+            &options.figures,
+            &options.extract_tables,
+            &options.debug_overlay,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            fs::create_dir_all(dir)?;
+        }
+        if let Some(toc) = options.toc.as_ref().filter(|_| options.attach_sources) {
+            attachments.push(("toc.json".to_string(), toc.source.clone().into_bytes()));
+        }
+        // the player's character map is only asked for once a page needs it
+        let mut cmap_requested = false;
+        // whether the next page has waited for the one before to stay under the memory cap
+        let mut paused = false;
+        let mut next = self
+            .get_page_unless_cancelled(cancel, product_id, uuid.as_ref(), 1)
+            .await?;
+        for i in 1..u32::MAX {
+            let (Some(bytes), annotation) = next else {
+                break;
+            };
+            self.report(Event::PageDownloaded(i));
+            self.counters.add_page();
+            // a page is held as downloaded and decoded, and the next one about as large is held
+            // as it downloads
+            let page_size = bytes.len() + annotation.as_ref().map_or(0, |(json, _)| json.len());
+            let held = sink.buffered()
+                + attachments
+                    .iter()
+                    .map(|(_, data): &(_, Vec<u8>)| data.len() as u64)
+                    .sum::<u64>();
+            let prefetch = options
+                .max_memory
+                .is_none_or(|max_memory| held + 3 * page_size as u64 <= max_memory);
+            // the next page downloads while this one is decoded on the blocking thread pool
+            let encoding = options.image_encoding;
+            let decoding = tokio::task::spawn_blocking(move || {
+                let image = raster::decode(&bytes, encoding);
+                (bytes, image)
+            });
+            let (decoded, prefetched) = if prefetch {
+                let (decoded, fetched) = join!(
+                    decoding,
+                    self.get_page_unless_cancelled(cancel, product_id, uuid.as_ref(), i + 1)
+                );
+                (decoded, Some(fetched?))
+            } else {
+                if !paused {
+                    paused = true;
+                    self.report(Event::Info(
+                        "Downloading pages one at a time to stay under --max-memory.".into(),
+                    ));
+                }
+                (decoding.await, None)
+            };
+            let (bytes, image) = decoded?;
+            let image = image?;
+            page_digests.push(sha256(&bytes));
+            // get_page made sure the image is a PNG
+            let (w, h) = raster::png_dimensions(&bytes).unwrap();
+            if options.color_management {
+                page_colors.push(color::read_png_color(&bytes));
+            }
+            let texts = match annotation {
+                Some((annotation, texts)) => {
+                    if options.attach_sources {
+                        let name = format!("annotations/page{:04}.json", i);
+                        attachments.push((name, annotation.into_bytes()));
+                    }
+                    texts
+                }
+                None => TextPageData { data: Vec::new() },
+            };
+            let mut texts = texts;
+            if glyphs::is_custom_encoded(&texts) {
+                if options.cmap.is_none() && !cmap_requested {
+                    cmap_requested = true;
+                    options.cmap = self.get_cmap(product_id, uuid.as_ref()).await;
+                }
+                if let Some(cmap) = &options.cmap {
+                    cmap.apply(&mut texts);
+                }
+            }
+            #[cfg(feature = "ocr")]
+            if texts.data.is_empty() {
+                self.report(Event::Info(format!("Recognizing page {:04} with OCR.", i)));
+                texts = ocr::recognize_blocking(&bytes, PAGE_SCALE, h).await?;
+            } else if glyphs::is_custom_encoded(&texts) {
+                self.report(Event::Info(format!(
+                    "Recognizing page {:04} with OCR, its text layer isn't in Unicode.",
+                    i
+                )));
+                // the text layer is still there to fall back on
+                match ocr::recognize_blocking(&bytes, PAGE_SCALE, h).await {
+                    Ok(recognized) => texts = recognized,
+                    Err(error) => self.report(Event::Warning(format!(
+                        "Could not recognize page {:04}: {}",
+                        i, error
+                    ))),
+                }
+            }
+            let mut script_stamp = None;
+            if let Some(script) = &mut self.script {
+                let reply = script.on_page_image(i, (w, h))?;
+                if reply.skip {
+                    removed_pages.push(i);
+                }
+                script_stamp = reply.stamp.map(|template| Stamp {
+                    template,
+                    position: options
+                        .stamp
+                        .as_ref()
+                        .map_or(stamp::Position::Footer, |stamp| stamp.position),
+                });
+                script.on_page_text(i, &mut texts.data)?;
+            }
+            if let Some(dir) = &options.figures {
+                figures.extend(figures::extract(dir, i, &bytes, &texts, PAGE_SCALE)?);
+            }
+            if let Some(dir) = &options.extract_tables {
+                tables += tables::extract(dir, i, &texts)?;
+            }
+            if let Some(dir) = &options.debug_overlay {
+                overlay::write(dir, i, &bytes, &texts, PAGE_SCALE)?;
+            }
+            if options.link_references {
+                // the TOC may only know where its entries are once every page is in
+                reference_texts.push((i, texts.clone()));
+            }
+            if options.index_links {
+                index_links.add_page(i, &texts);
+            }
+            if let Some((garbled, total)) = garbled::check(&texts) {
+                garbled_pages.push((i, garbled, total));
+            }
+            page_runs.push(texts.data.len() as u32);
+            let stamps = [&options.stamp, &script_stamp]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+            let (page, page_text) = assemble::page(i, image, (w, h), texts, &stamps, &layout);
+            sink.add_page(page)?;
+            self.report(Event::PageFinished(i));
+            page_texts.push(page_text);
+            next = match prefetched {
+                Some(next) => next,
+                None => {
+                    self.get_page_unless_cancelled(cancel, product_id, uuid.as_ref(), i + 1)
+                        .await?
+                }
+            };
+        }
+        let cancelled = cancel.is_cancelled();
+        if cancelled {
+            self.report(Event::Info(format!(
+                "Cancelled, finishing the document with the {} pages downloaded.",
+                page_texts.len()
+            )));
+        }
+        if let Some(dir) = &options.figures {
+            figures::write_index(dir, &figures)?;
+            self.report(Event::Info(format!("Exported {} figures.", figures.len())));
+        }
+        if options.extract_tables.is_some() {
+            self.report(Event::Info(format!("Exported {} tables.", tables)));
+        }
+        if let Some(script) = self.script.take() {
+            script.on_finish(page_texts.len() as u32)?;
+        }
+        if let Some(toc) = options.toc.as_mut().filter(|toc| toc.has_printed_pages()) {
+            let mut numbering = Numbering::default();
+            for (page, text) in page_texts.iter().enumerate() {
+                numbering.add_page(page as u32, text);
+            }
+            for (title, page) in toc.resolve(&numbering) {
+                self.report(Event::Warning(format!(
+                    "Found no printed page numbers like the one of \"{}\", \
+                     taking it as page {:04}.",
+                    title, page
+                )));
+            }
+        }
+        if let Some(toc) = options.toc.as_ref().filter(|_| options.link_references) {
+            for (page, texts) in &reference_texts {
+                links.extend(crossref::find(*page, texts, toc));
+            }
+        }
+        self.report(Event::Info(
+            "Saving the document. This make take a while.".into(),
+        ));
+        let mut front_pages = 0;
+        if let Some(mut document) = sink.finish()? {
+            links.extend(index_links.links());
+            crossref::add_links(&mut document, &links, &removed_pages)?;
+            if options.color_management {
+                color::apply(&mut document, &page_colors)?;
+            }
+            if let Some(spill) = spill.as_mut().filter(|_| {
+                options.dedupe_images || options.max_size.is_some() || options.linearize
+            }) {
+                // deduplicating and shrinking compare and recompress the page images themselves,
+                // and linearizing measures every object before writing any
+                self.report(Event::Info(format!(
+                    "Reading {} MB of page images back from disk.",
+                    spill.spilled() / 1_000_000
+                )));
+                spill.restore(&mut document)?;
+            }
+            if options.dedupe_images {
+                let duplicates = dedupe::dedupe_images(&mut document);
+                self.report(Event::Info(format!(
+                    "Removed {} duplicate page images.",
+                    duplicates
+                )));
+            }
+            if options.tagged {
+                tags::add_structure_tree(&mut document, &page_runs, options.toc.as_ref())?;
+            }
+            if let Some(lang) = &options.lang {
+                catalog::set_language(&mut document, lang)?;
+            }
+            if let Some(toc) = &options.toc {
+                toc::add_named_destinations(&mut document, toc)?;
+                front_pages += toc::insert_contents(&mut document, toc)?;
+            }
+            if !removed_pages.is_empty() {
+                let mut pages = document.get_pages().into_values().collect::<Vec<_>>();
+                for page in removed_pages.iter().rev() {
+                    pages.remove((front_pages + page) as usize);
+                }
+                split::keep_pages(&mut document, &pages)?;
+            }
+            if options.media_links {
+                let links = media::find(&page_texts, &removed_pages);
+                media::append_links(&mut document, &links)?;
+            }
+            attachments::attach(&mut document, attachments)?;
+            if let Some(max_size) = options.max_size {
+                shrink::fit(&mut document, max_size)?;
+            }
+            document.version = options.pdf_version.to_string();
+            match &mut spill {
+                _ if options.linearize => linearize::save(&document, &mut output)?,
+                Some(spill) => spill.save(&document, options.packing, &mut output)?,
+                None if options.packing == Packing::Table => document.save_to(&mut output)?,
+                None => objstm::save(&document, options.packing, &mut output)?,
+            }
+        }
+        output.flush()?;
+        Ok(Extraction {
+            page_texts,
+            front_pages,
+            removed_pages,
+            garbled_pages,
+            cover,
+            figures,
+            page_digests,
+            cancelled,
+            expired: mem::take(&mut self.expired),
+        })
+    }
+
+    /// Requests `url` with the session every `interval` in the background, so the session doesn't
+    /// expire during long downloads.
+    pub fn keep_alive(&self, url: String, interval: Duration) {
+        let client = self.client.clone();
+        let progress = self.progress.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            // the first tick completes immediately
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                if let Err(error) = client.get(&url).send().await {
+                    progress(&Event::Warning(format!(
+                        "Failed to keep the session alive: {}",
+                        error
+                    )));
+                }
+            }
+        });
+    }
+
+    /// Estimates the size of the document from the size of the cover and the number of pages,
+    /// found by probing for the last page up to `MAX_PAGES`. Returns the estimate if it is over
+    /// `free` bytes, without probing if even a book of `MAX_PAGES` pages would fit.
+    pub async fn exceeds(
+        &self,
+        product_id: u32,
+        uuid: impl AsRef<str>,
+        free: u64,
+    ) -> Result<Option<u64>> {
+        let uuid = uuid.as_ref();
+        let cover = self
+            .get_image(product_id, uuid, 0)
+            .await?
+            .unwrap_or_default()
+            .len() as u64;
+        if cover.saturating_mul(MAX_PAGES as u64 + 1) <= free {
+            return Ok(None);
+        }
+        let (mut last, mut missing) = (0, 1);
+        while missing <= MAX_PAGES && self.has_page(product_id, uuid, missing).await? {
+            last = missing;
+            missing = missing.saturating_mul(2);
+        }
+        let mut missing = missing.min(MAX_PAGES + 1);
+        while missing - last > 1 {
+            let middle = last + (missing - last) / 2;
+            if self.has_page(product_id, uuid, middle).await? {
+                last = middle;
+            } else {
+                missing = middle;
+            }
+        }
+        let estimate = cover.saturating_mul(last as u64 + 1);
+        Ok(Some(estimate).filter(|&estimate| estimate > free))
+    }
+
+    /// Measures the round trip time to plus.pearson.com with HEAD requests of the first page, and
+    /// the throughput downloading the first pages one after another and then all at once,
+    /// bypassing the cache.
+    pub async fn probe(&self, product_id: u32, uuid: impl AsRef<str>) -> Result<probe::Report> {
+        let uuid = uuid.as_ref();
+        let mut rtts = Vec::new();
+        for _ in 0..PROBE_ROUND_TRIPS {
+            let start = Instant::now();
+            self.has_page(product_id, uuid, 1).await?;
+            rtts.push(start.elapsed());
+        }
+        let rtt = probe::median(rtts);
+        let download = |page: u32| async move {
+            let dest = format!(
+                "https://plus.pearson.com/eplayer/pdfassets/prod1/{product_id}/{uuid}/pages/page{page}"
+            );
+            let resp = self.send(self.client.get(dest)).await?;
+            match resp.status {
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => bail!(SessionExpired),
+                status if status.is_success() => Ok(resp.body.len()),
+                _ => Ok(0),
+            }
+        };
+        let start = Instant::now();
+        let mut bytes = 0;
+        let mut pages = 0;
+        for page in 1..=PROBE_PAGES {
+            let len = download(page).await?;
+            if len == 0 {
+                break;
+            }
+            bytes += len;
+            pages += 1;
+        }
+        let sequential = start.elapsed();
+        if pages == 0 {
+            bail!("the book has no pages to probe with");
+        }
+        let start = Instant::now();
+        let mut concurrent_bytes = 0;
+        for len in join_all((1..=pages).map(download)).await {
+            concurrent_bytes += len?;
+        }
+        let concurrent = concurrent_bytes as f64 / start.elapsed().as_secs_f64();
+        let sequential_throughput = bytes as f64 / sequential.as_secs_f64();
+        Ok(probe::Report {
+            rtt,
+            sequential: sequential_throughput,
+            concurrent,
+            pages: pages as usize,
+            concurrency: probe::concurrency(
+                rtt,
+                sequential / pages,
+                concurrent / sequential_throughput,
+            ),
+        })
+    }
+
+    /// Downloads the images of `pages` again, all at once and bypassing the cache, and returns
+    /// the ones that differ from the images with the SHA-256 `digests`, indexed by page number.
+    pub async fn verify_pages(
+        &self,
+        product_id: u32,
+        uuid: impl AsRef<str>,
+        pages: &[u32],
+        digests: &[Vec<u8>],
+    ) -> Result<Vec<u32>> {
+        let uuid = uuid.as_ref();
+        let downloads = pages.iter().map(|&page| async move {
+            let dest = format!(
+                "https://plus.pearson.com/eplayer/pdfassets/prod1/{product_id}/{uuid}/pages/page{page}"
+            );
+            let resp = self.send(self.client.get(dest)).await?;
+            match resp.status {
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => bail!(SessionExpired),
+                status if !status.is_success() => {
+                    bail!("page {:04} failed to download again with {}", page, status)
+                }
+                _ => Ok((page, sha256(&resp.body) == digests[page as usize])),
+            }
+        });
+        let mut differing = Vec::new();
+        for result in join_all(downloads).await {
+            let (page, same) = result?;
+            if !same {
+                differing.push(page);
+            }
+        }
+        differing.sort_unstable();
+        Ok(differing)
+    }
+
+    /// Returns the text layer of up to `pages` pages after the cover, where books print their
+    /// edition and copyright.
+    pub async fn front_matter(
+        &self,
+        product_id: u32,
+        uuid: impl AsRef<str>,
+        pages: u32,
+    ) -> Result<Vec<String>> {
+        let mut texts = Vec::new();
+        for page in 1..=pages {
+            let Some(annotation) = self.get_annotation(product_id, uuid.as_ref(), page).await?
+            else {
+                continue;
+            };
+            // the page is read again for the book, where an unreadable text layer is reported
+            let Ok(data) = annotation::parse(&annotation) else {
+                continue;
+            };
+            let lines = data.data.iter().map(|text| {
+                text.stream
+                    .iter()
+                    .filter_map(|&(_, _, _, _, char)| char::from_u32(char))
+                    .collect::<String>()
+            });
+            texts.push(lines.collect::<Vec<_>>().join("\n"));
+        }
+        Ok(texts)
+    }
+
+    /// Returns the character map the player has for the fonts of a book, if it has one and it
+    /// can be read. The player isn't known to serve one, so it is looked for next to the page
+    /// assets, and anything going wrong leaves the codes as they are.
+    async fn get_cmap(&self, product_id: u32, uuid: &str) -> Option<glyphs::Cmap> {
+        let source = match self.get(format!("{product_id}/{uuid}/cmap")).await {
+            Ok(source) => source?,
+            Err(error) => {
+                self.report(Event::Warning(format!(
+                    "Couldn't get the character map of the book: {}",
+                    error
+                )));
+                return None;
+            }
+        };
+        match glyphs::Cmap::parse(&String::from_utf8_lossy(&source)) {
+            Ok(cmap) => {
+                self.report(Event::Info(
+                    "Mapping the character codes with the character map of the book.".into(),
+                ));
+                Some(cmap)
+            }
+            Err(error) => {
+                self.report(Event::Warning(format!(
+                    "Ignoring the character map of the book: {}",
+                    error
+                )));
+                None
+            }
+        }
+    }
+
+    /// Returns the language the metadata of a book gives, if it has metadata that gives one. The
+    /// player isn't known to serve metadata, so it is looked for next to the page assets, and
+    /// anything going wrong only costs the language.
+    async fn get_language(&self, product_id: u32, uuid: &str) -> Option<String> {
+        #[derive(Deserialize)]
+        struct Metadata {
+            #[serde(alias = "lang")]
+            language: Option<String>,
+        }
+        let metadata = match self.get(format!("{product_id}/{uuid}/metadata")).await {
+            Ok(metadata) => metadata?,
+            Err(error) => {
+                self.report(Event::Warning(format!(
+                    "Couldn't get the metadata of the book: {}",
+                    error
+                )));
+                return None;
+            }
+        };
+        let language = match sonic_rs::from_slice::<Metadata>(&metadata) {
+            Ok(metadata) => metadata.language,
+            Err(error) => {
+                self.report(Event::Warning(format!(
+                    "Ignoring the metadata of the book: {}",
+                    error
+                )));
+                return None;
+            }
+        };
+        // a BCP 47 tag is letters and digits separated by hyphens
+        language.filter(|language| {
+            !language.is_empty()
+                && language
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_')
+        })
+    }
+
+    async fn has_page(&self, product_id: u32, uuid: &str, page: u32) -> Result<bool> {
+        let dest = format!(
+            "https://plus.pearson.com/eplayer/pdfassets/prod1/{product_id}/{uuid}/pages/page{page}"
+        );
+        Ok(self.send(self.client.head(dest)).await?.status.is_success())
+    }
+
+    /// Downloads a page as `get_page` does, or returns no image, as past the last page, once
+    /// `cancel` is cancelled or when the session expired and couldn't be renewed.
+    async fn get_page_unless_cancelled(
+        &mut self,
+        cancel: &CancelToken,
+        product_id: u32,
+        uuid: &str,
+        page: u32,
+    ) -> Result<(Option<Vec<u8>>, Option<(String, TextPageData)>)> {
+        self.report(Event::PageStarted(page));
+        let fetched = tokio::select! {
+            // a token cancelled already doesn't start another page
+            biased;
+            _ = cancel.cancelled() => Ok((None, None)),
+            fetched = self.get_page(product_id, uuid, page) => fetched,
+        };
+        match fetched {
+            // the pages before are still worth having
+            Err(error) if error.is::<SessionExpired>() => {
+                self.expired = true;
+                self.report(Event::Warning(format!(
+                    "Stopping at page {:04}, the session expired and wasn't renewed.",
+                    page
+                )));
+                Ok((None, None))
+            }
+            fetched => fetched,
+        }
+    }
+
+    /// Downloads the image and annotation data of a page, renewing the session if it expired and
+    /// retrying whichever of the two failed or came back corrupt on its own. Returns no image past
+    /// the last page, and no annotation data for a page without a text layer.
+    async fn get_page(
+        &mut self,
+        product_id: u32,
+        uuid: &str,
+        page: u32,
+    ) -> Result<(Option<Vec<u8>>, Option<(String, TextPageData)>)> {
+        let mut image = None;
+        let mut annotation = None;
+        let mut attempts = 0;
+        loop {
+            let (image_result, annotation_result) = join!(
+                async {
+                    match image {
+                        Some(_) => None,
+                        None => Some(self.get_image(product_id, uuid, page).await),
+                    }
+                },
+                async {
+                    match annotation {
+                        Some(_) => None,
+                        None => Some(self.get_annotation(product_id, uuid, page).await),
+                    }
+                }
+            );
+            let mut errors = Vec::new();
+            match image_result {
+                Some(Ok(Some(bytes))) if PngDecoder::new(Cursor::new(&bytes)).is_err() => {
+                    errors.push(anyhow!("the image of page {:04} is corrupt", page));
+                }
+                Some(Ok(bytes)) => image = Some(bytes),
+                Some(Err(error)) => errors.push(error),
+                None => {}
+            }
+            let mut unreadable = false;
+            // a format it can't read won't change by asking again
+            let mut unknown_format = false;
+            match annotation_result {
+                Some(Ok(Some(data))) => match annotation::parse(&data) {
+                    Ok(texts) => annotation = Some(Some((data, texts))),
+                    Err(error) => {
+                        unknown_format = error.is::<UnknownFormat>();
+                        let error = anyhow!(
+                            "the text layer of page {:04} is unreadable: {}",
+                            page,
+                            error
+                        );
+                        unreadable = true;
+                        errors.push(error);
+                    }
+                },
+                Some(Ok(None)) => annotation = Some(None),
+                Some(Err(error)) => errors.push(error),
+                None => {}
+            }
+            if errors.is_empty() {
+                return Ok((image.unwrap(), annotation.unwrap()));
+            }
+            if let Some(i) = errors.iter().position(|error| error.is::<Intercepted>()) {
+                // asking again won't get past it
+                return Err(errors.remove(i));
+            }
+            if errors.iter().any(|error| error.is::<SessionExpired>()) {
+                // continue from this page with the new session
+                self.renew_session()?;
+                continue;
+            }
+            attempts += 1;
+            self.counters.add_errors(errors.len());
+            let textless = unreadable && errors.len() == 1 && image.is_some();
+            if textless && (attempts > RETRIES || unknown_format) {
+                // the page is still worth having without its text
+                self.report(Event::Warning(format!(
+                    "Leaving out the text layer of page {:04}: {}",
+                    page, errors[0]
+                )));
+                return Ok((image.unwrap(), None));
+            }
+            if attempts > RETRIES {
+                return Err(errors.remove(0));
+            }
+            self.report(Event::Retry {
+                page,
+                error: errors[0].to_string(),
+            });
+            self.counters.add_retry();
+            tokio::time::sleep(Duration::from_secs(1 << attempts)).await;
+        }
+    }
+
+    /// Returns the image of a page, or `None` past the last page.
+    async fn get_image(&self, product_id: u32, uuid: &str, page: u32) -> Result<Option<Vec<u8>>> {
+        self.get(format!("{product_id}/{uuid}/pages/page{page}"))
+            .await
+    }
+
+    /// Returns the annotation data of a page, or `None` for a page without a text layer.
+    async fn get_annotation(
+        &self,
+        product_id: u32,
+        uuid: &str,
+        page: u32,
+    ) -> Result<Option<String>> {
+        let data = self
+            .get(format!("{product_id}/{uuid}/annotations/page{page}"))
+            .await?;
+        // annotation data can run to megabytes, which are only copied if they aren't UTF-8
+        Ok(data
+            .filter(|data| !data.trim_ascii().is_empty())
+            .map(|data| {
+                String::from_utf8(data)
+                    .unwrap_or_else(|error| String::from_utf8_lossy(error.as_bytes()).into_owned())
+            }))
+    }
+
+    /// Downloads an asset, or returns `None` if it doesn't exist.
+    async fn get(&self, asset: String) -> Result<Option<Vec<u8>>> {
+        let dest = format!("https://plus.pearson.com/eplayer/pdfassets/prod1/{asset}");
+        let mut request = self.client.get(dest);
+        if let Some(cache) = self.cache.as_ref().filter(|_| self.recorder.is_none()) {
+            request = cache.conditional(&asset, request);
+        }
+        let resp = self.send(request).await?;
+        let html = resp
+            .headers
+            .get(CONTENT_TYPE)
+            .is_some_and(|value| value.as_bytes().starts_with(b"text/html"));
+        match resp.status {
+            StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS => bail!(Intercepted::GeoBlocked),
+            StatusCode::FORBIDDEN if html && mentions_region(&resp.body) => {
+                bail!(Intercepted::GeoBlocked)
+            }
+            StatusCode::UNAUTHORIZED => bail!(SessionExpired),
+            // a session that is still good for the rest of the book was refused just this asset
+            StatusCode::FORBIDDEN if self.signed_in(&asset).await? => {
+                bail!("the request for {} was refused", asset)
+            }
+            StatusCode::FORBIDDEN => bail!(SessionExpired),
+            StatusCode::NOT_FOUND => return Ok(None),
+            status if status.is_success() && html => bail!(Intercepted::CaptivePortal),
+            _ => {}
+        }
+        match &self.cache {
+            Some(cache) => Ok(Some(cache.store(&asset, resp)?)),
+            None => Ok(Some(resp.body)),
+        }
+    }
+
+    /// Whether the session is still good for the book `asset` belongs to, judging by whether it
+    /// may see the cover.
+    async fn signed_in(&self, asset: &str) -> Result<bool> {
+        let book = asset.splitn(3, '/').take(2).collect::<Vec<_>>().join("/");
+        let dest = format!("https://plus.pearson.com/eplayer/pdfassets/prod1/{book}/pages/page0");
+        let status = self.send(self.client.head(dest)).await?.status;
+        Ok(!matches!(
+            status,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        ))
+    }
+
+    /// Sends `request` within the limit of requests in flight and reads the whole response,
+    /// resuming a body that broke off with range requests, recording the exchange or answering it
+    /// from a replay.
+    async fn send(&self, request: RequestBuilder) -> Result<Fetched> {
+        let request = request.build()?;
+        if let Some(replay) = &self.replay {
+            return replay.respond(&request);
+        }
+        let _permit = match &self.requests {
+            Some(requests) => Some(requests.acquire().await?),
+            None => None,
+        };
+        let recorded = self.recorder.as_ref().and_then(|_| request.try_clone());
+        let retry = request.try_clone();
+        let mut resp = self.client.execute(request).await?;
+        let version = resp.version();
+        let status = resp.status();
+        let mut headers = resp.headers().clone();
+        // a compressed body is decoded as it comes in, counting the bytes received to resume at
+        let mut body = compression::Decoder::new(&headers)?;
+        let mut received = 0;
+        let mut resumes = 0;
+        loop {
+            let error = match resp.chunk().await {
+                Ok(Some(chunk)) => {
+                    received += chunk.len();
+                    self.counters.add_bytes(chunk.len());
+                    body.write(&chunk)?;
+                    continue;
+                }
+                Ok(None) => break,
+                Err(error) => error,
+            };
+            // continue a large body that broke off from where it stopped, rather than from the
+            // start, if the server serves ranges of it
+            let validator = headers
+                .get(ETAG)
+                .or_else(|| headers.get(LAST_MODIFIED))
+                .cloned();
+            let resumable = status == StatusCode::OK
+                && received > 0
+                && headers
+                    .get(ACCEPT_RANGES)
+                    .is_some_and(|value| value == "bytes");
+            let (Some(mut request), Some(validator), true) = (
+                retry.as_ref().and_then(Request::try_clone),
+                validator,
+                resumable && resumes < RETRIES,
+            ) else {
+                return Err(error.into());
+            };
+            resumes += 1;
+            self.counters.add_retry();
+            self.report(Event::Info(format!(
+                "Resuming {} from byte {}: {}",
+                request.url().path(),
+                received,
+                error
+            )));
+            let range = HeaderValue::from_str(&format!("bytes={}-", received))?;
+            request.headers_mut().insert(RANGE, range);
+            request.headers_mut().insert(IF_RANGE, validator);
+            resp = self.client.execute(request).await?;
+            if resp.status() != StatusCode::PARTIAL_CONTENT {
+                // the asset changed since, or the server ignored the range
+                return Err(error.into());
+            }
+        }
+        if body.is_compressed() {
+            // recorded and cached as the decoded body
+            compression::strip_encoding(&mut headers);
+        }
+        let body = body.finish()?;
+        if let (Some(recorder), Some(request)) = (&self.recorder, recorded) {
+            recorder.record(&request, version, status, &headers, &body)?;
+        }
+        Ok(Fetched {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// The HTTP version the client speaks to plus.pearson.com.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum HttpVersion {
+    /// HTTP/2 when the server offers it, HTTP/1.1 otherwise.
+    #[default]
+    Auto,
+    /// HTTP/1.1 only.
+    #[value(name = "1.1")]
+    Http1,
+    /// HTTP/2 only.
+    #[value(name = "2")]
+    Http2,
+}
+
+/// The error of a request that something between here and plus.pearson.com answered instead.
+#[derive(Debug)]
+pub enum Intercepted {
+    /// Refused for the country or region it came from.
+    GeoBlocked,
+    /// Answered with a web page, such as the sign-in page of a Wi-Fi network.
+    CaptivePortal,
+}
+
+impl Display for Intercepted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Intercepted::GeoBlocked => write!(f, "the request was refused for where it came from"),
+            Intercepted::CaptivePortal => write!(f, "a web page came back instead of the book"),
+        }
+    }
+}
+
+impl std::error::Error for Intercepted {}
+
+/// Returns the rectangle an image of `w` by `h` pixels takes when scaled to fit centered on a
+/// page of `page_size` points.
+fn fit(w: u32, h: u32, (page_w, page_h): (f32, f32)) -> (f32, f32, f32, f32) {
+    let scale = (page_w / w as f32).min(page_h / h as f32);
+    let (w, h) = (w as f32 * scale, h as f32 * scale);
+    ((page_w - w) / 2.0, (page_h - h) / 2.0, w, h)
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    digest::digest(&digest::SHA256, data).as_ref().to_vec()
+}
+
+/// Whether the body of a refused request mentions a country, region or geo-blocking, as the pages
+/// of services refusing requests for where they come from do.
+fn mentions_region(body: &[u8]) -> bool {
+    let body = String::from_utf8_lossy(body).to_lowercase();
+    ["country", "region", "geo"]
+        .iter()
+        .any(|word| body.contains(word))
+}
@@ -0,0 +1,119 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use image::load_from_memory;
+
+use crate::{filename, TextPageData};
+
+/// How much of the page a text-free area next to a caption must cover to count as a figure.
+const MIN_HEIGHT: f32 = 0.15;
+
+/// A figure exported from a page.
+pub struct Figure {
+    pub file: String,
+    pub page: u32,
+    pub label: String,
+    pub caption: String,
+}
+
+/// Finds the figures on a page by their "Figure X.Y" captions, taking the text-free band above
+/// each caption, or below it if there is too little room above, as the figure. Every figure is
+/// saved from the page image into `dir`.
+pub fn extract(
+    dir: &Path,
+    page: u32,
+    png: &[u8],
+    texts: &TextPageData,
+    scale: f32,
+) -> Result<Vec<Figure>> {
+    // the text runs as their text and vertical extent in points
+    let runs = texts
+        .data
+        .iter()
+        .filter(|text| !text.stream.is_empty())
+        .map(|text| {
+            let text_string = text
+                .stream
+                .iter()
+                .filter_map(|&(_, _, _, _, char)| char::from_u32(char))
+                .collect::<String>();
+            let bottom = text.stream.iter().map(|c| c.1).fold(f32::MAX, f32::min);
+            let top = text
+                .stream
+                .iter()
+                .map(|c| c.1 + c.3)
+                .fold(f32::MIN, f32::max);
+            (text_string, bottom, top)
+        })
+        .collect::<Vec<_>>();
+    let mut figures = Vec::new();
+    let mut image = None;
+    for (caption, bottom, top) in &runs {
+        let Some(label) = label(caption) else {
+            continue;
+        };
+        let image = match &mut image {
+            Some(image) => image,
+            None => image.insert(load_from_memory(png)?),
+        };
+        let height = image.height() as f32 * scale;
+        let above = runs
+            .iter()
+            .map(|run| run.1)
+            .filter(|run_bottom| run_bottom > top)
+            .fold(height, f32::min);
+        let below = runs
+            .iter()
+            .map(|run| run.2)
+            .filter(|run_top| run_top < bottom)
+            .fold(0.0, f32::max);
+        let (from, to) = if above - top >= MIN_HEIGHT * height {
+            (*top, above)
+        } else if bottom - below >= MIN_HEIGHT * height {
+            (below, *bottom)
+        } else {
+            continue;
+        };
+        // image rows count down from the top of the page
+        let y = ((height - to) / scale) as u32;
+        let rows = (((to - from) / scale) as u32).min(image.height() - y);
+        let file = filename::sanitize(&format!("page{:04}-figure-{}.png", page, label));
+        image
+            .crop_imm(0, y, image.width(), rows)
+            .save(dir.join(&file))?;
+        figures.push(Figure {
+            file,
+            page,
+            label,
+            caption: caption.trim().to_string(),
+        });
+    }
+    Ok(figures)
+}
+
+/// Returns the number of a caption such as "Figure 3.2 The cell" or "Fig. 3-2".
+pub fn label(caption: &str) -> Option<String> {
+    let caption = caption.trim_start();
+    let rest = ["Figure ", "FIGURE ", "Fig. "]
+        .iter()
+        .find_map(|prefix| caption.strip_prefix(prefix))?;
+    let label = rest
+        .chars()
+        .take_while(|char| char.is_ascii_alphanumeric() || matches!(char, '.' | '-'))
+        .collect::<String>();
+    let label = label.trim_end_matches(['.', '-']);
+    (label.starts_with(|char: char| char.is_ascii_digit())).then(|| label.to_string())
+}
+
+/// Writes `captions.csv` listing the file, page, number and caption of every figure.
+pub fn write_index(dir: &Path, figures: &[Figure]) -> Result<()> {
+    let mut index = Vec::new();
+    crate::csv::write_record(&mut index, &["file", "page", "label", "caption"])?;
+    for figure in figures {
+        let page = figure.page.to_string();
+        let fields = [&figure.file, &page, &figure.label, &figure.caption];
+        crate::csv::write_record(&mut index, &fields.map(String::as_str))?;
+    }
+    fs::write(dir.join("captions.csv"), index)?;
+    Ok(())
+}
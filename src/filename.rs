@@ -0,0 +1,87 @@
+use std::path::{Component, Path, PathBuf};
+
+/// The longest file name most filesystems accept, in bytes.
+const MAX_LENGTH: usize = 200;
+
+/// Turns `name`, such as a book or chapter title, into a file name that is valid on Windows,
+/// macOS and Linux alike.
+pub fn sanitize(name: &str) -> String {
+    let mut sanitized = name
+        .chars()
+        .map(|char| match char {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            char if char.is_control() => '_',
+            char => char,
+        })
+        .collect::<String>();
+    if sanitized.len() > MAX_LENGTH {
+        let mut end = MAX_LENGTH;
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+    }
+    // Windows drops trailing dots and spaces
+    let mut sanitized = sanitized
+        .trim_end_matches(['.', ' '])
+        .trim_start()
+        .to_string();
+    let stem = sanitized.split('.').next().unwrap_or_default();
+    let reserved = ["CON", "PRN", "AUX", "NUL"]
+        .iter()
+        .any(|name| stem.eq_ignore_ascii_case(name))
+        || (stem.is_ascii()
+            && stem.len() == 4
+            && ["COM", "LPT"]
+                .iter()
+                .any(|name| stem[..3].eq_ignore_ascii_case(name))
+            && stem.as_bytes()[3].is_ascii_digit());
+    if reserved {
+        sanitized.insert(0, '_');
+    }
+    if sanitized.is_empty() {
+        sanitized.push_str("untitled");
+    }
+    sanitized
+}
+
+/// Replaces every `{name}` of `placeholders` in the components of `template`, such as
+/// `{author} - {title} ({year}).{ext}`, with its value made safe for a file name.
+pub fn expand(template: &Path, placeholders: &[(&str, &str)]) -> PathBuf {
+    template
+        .components()
+        .map(|component| {
+            let Component::Normal(name) = component else {
+                return component.as_os_str().to_owned();
+            };
+            let Some(name) = name.to_str().filter(|name| name.contains('{')) else {
+                return name.to_owned();
+            };
+            let expanded = placeholders
+                .iter()
+                .fold(name.to_string(), |name, (key, value)| {
+                    let value = if value.is_empty() {
+                        String::new()
+                    } else {
+                        sanitize(value)
+                    };
+                    name.replace(&format!("{{{}}}", key), &value)
+                });
+            expanded.into()
+        })
+        .collect()
+}
+
+/// Returns `path` in the extended form Windows needs for paths over 260 characters.
+pub fn long_path(path: &Path) -> PathBuf {
+    if cfg!(windows) && path.as_os_str().len() >= 260 {
+        if let Ok(absolute) = std::path::absolute(path) {
+            if !absolute.starts_with(r"\\?\") {
+                let mut extended = std::ffi::OsString::from(r"\\?\");
+                extended.push(absolute);
+                return extended.into();
+            }
+        }
+    }
+    path.to_path_buf()
+}
@@ -0,0 +1,20 @@
+use crate::{glyphs, TextPageData};
+
+/// The share of garbled characters above which a page is reported.
+const MAX_RATIO: f32 = 0.05;
+
+/// Counts the replacement characters and the codes of a font's own, such as control codes and
+/// private use or invalid code points, in the annotation data of a page, returning the garbled and
+/// total character counts when there are too many.
+pub fn check(texts: &TextPageData) -> Option<(usize, usize)> {
+    let mut garbled = 0;
+    let mut total = 0;
+    for &(_, _, _, _, code) in texts.data.iter().flat_map(|text| &text.stream) {
+        total += 1;
+        let is_garbled = glyphs::is_custom(code) || code == char::REPLACEMENT_CHARACTER as u32;
+        if is_garbled {
+            garbled += 1;
+        }
+    }
+    (garbled > 0 && garbled as f32 > total as f32 * MAX_RATIO).then_some((garbled, total))
+}
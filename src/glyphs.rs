@@ -0,0 +1,256 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use sonic_rs::{JsonContainerTrait, JsonValueTrait, Value};
+
+use crate::TextPageData;
+
+/// The share of characters in a font's own codes above which the text of a page isn't taken to
+/// be Unicode.
+const MAX_CUSTOM_RATIO: f32 = 0.3;
+
+/// Whether a character code is no Unicode text, but most likely the number of a glyph in a font
+/// that was embedded without a way back to Unicode: a private use code point, a control code or
+/// no code point at all.
+pub fn is_custom(code: u32) -> bool {
+    match char::from_u32(code) {
+        Some(char) => {
+            matches!(code, 0xe000..=0xf8ff | 0xf0000..=0xffffd | 0x100000..=0x10fffd)
+                || (char.is_control() && !char.is_whitespace())
+        }
+        None => true,
+    }
+}
+
+/// Whether so many characters of a page are in a font's own codes that its text is gibberish.
+pub fn is_custom_encoded(texts: &TextPageData) -> bool {
+    let mut custom = 0;
+    let mut total = 0;
+    for &(_, _, _, _, code) in texts.data.iter().flat_map(|text| &text.stream) {
+        total += 1;
+        if is_custom(code) {
+            custom += 1;
+        }
+    }
+    custom > 0 && custom as f32 > total as f32 * MAX_CUSTOM_RATIO
+}
+
+/// A map from the character codes of a book's fonts to the text they show.
+#[derive(Clone)]
+pub struct Cmap(HashMap<u32, String>);
+
+impl Cmap {
+    /// Reads a character map from a file, see `parse`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Reads a character map, either a PDF ToUnicode CMap with `bfchar` and `bfrange` sections or
+    /// a JSON object such as `{"0xE001": "fi", "57346": 66}` from codes, in decimal, `0x` or
+    /// `U+` hex, to text or code points.
+    pub fn parse(source: &str) -> Result<Self> {
+        let map = if source.trim_start().starts_with('{') {
+            parse_json(source)?
+        } else {
+            parse_cmap(source)?
+        };
+        if map.is_empty() {
+            bail!("the character map maps no codes");
+        }
+        Ok(Self(map))
+    }
+
+    /// Replaces the characters of a page that the map knows with their text, splitting the box of
+    /// a character that maps to several, such as a ligature, evenly between them.
+    pub fn apply(&self, texts: &mut TextPageData) {
+        for text in &mut texts.data {
+            let mut stream = Vec::with_capacity(text.stream.len());
+            for &(x, y, w, h, code) in &text.stream {
+                let Some(mapped) = self.0.get(&code) else {
+                    stream.push((x, y, w, h, code));
+                    continue;
+                };
+                let advance = w / mapped.chars().count() as f32;
+                for (i, char) in mapped.chars().enumerate() {
+                    stream.push((x + advance * i as f32, y, advance, h, char as u32));
+                }
+            }
+            text.stream = stream;
+        }
+    }
+}
+
+fn parse_json(source: &str) -> Result<HashMap<u32, String>> {
+    let value = sonic_rs::from_str::<Value>(source)?;
+    let object = value
+        .as_object()
+        .ok_or_else(|| anyhow!("the character map isn't a JSON object"))?;
+    let mut map = HashMap::new();
+    for (key, value) in object.iter() {
+        let code = parse_code(key).ok_or_else(|| anyhow!("{} is no character code", key))?;
+        let text = match (value.as_str(), value.as_u64()) {
+            (Some(text), _) => text.to_string(),
+            (_, Some(code)) => u32::try_from(code)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| anyhow!("{} is no code point", code))?
+                .to_string(),
+            _ => bail!("{} maps to neither text nor a code point", key),
+        };
+        map.insert(code, text);
+    }
+    Ok(map)
+}
+
+fn parse_code(code: &str) -> Option<u32> {
+    let code = code.trim();
+    match code
+        .strip_prefix("0x")
+        .or_else(|| code.strip_prefix("U+"))
+        .or_else(|| code.strip_prefix("u+"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => code.parse().ok(),
+    }
+}
+
+/// A token of a CMap: a hex string, a bracket of an array or anything else.
+enum Token<'a> {
+    Hex(Vec<u8>),
+    Open,
+    Close,
+    Word(&'a str),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    loop {
+        rest = rest.trim_start();
+        let Some(c) = rest.chars().next() else {
+            return Ok(tokens);
+        };
+        match c {
+            '%' => rest = rest.split_once('\n').map_or("", |(_, rest)| rest),
+            '<' if !rest.starts_with("<<") => {
+                let end = rest
+                    .find('>')
+                    .ok_or_else(|| anyhow!("a hex string in the CMap isn't closed"))?;
+                let hex = rest[1..end]
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .collect::<String>();
+                if hex.len() % 2 == 1 {
+                    bail!("<{}> in the CMap has an odd number of digits", hex);
+                }
+                let bytes = (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| anyhow!("<{}> in the CMap isn't hex", hex))?;
+                tokens.push(Token::Hex(bytes));
+                rest = &rest[end + 1..];
+            }
+            '[' => {
+                tokens.push(Token::Open);
+                rest = &rest[1..];
+            }
+            ']' => {
+                tokens.push(Token::Close);
+                rest = &rest[1..];
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || "<>[]%".contains(c))
+                    .unwrap_or(rest.len())
+                    .max(c.len_utf8());
+                tokens.push(Token::Word(&rest[..end]));
+                rest = &rest[end..];
+            }
+        }
+    }
+}
+
+/// Reads the `bfchar` and `bfrange` sections of a ToUnicode CMap, ignoring the rest.
+fn parse_cmap(source: &str) -> Result<HashMap<u32, String>> {
+    let tokens = tokenize(source)?;
+    let mut map = HashMap::new();
+    let mut tokens = tokens.iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word("beginbfchar") => loop {
+                let code = match tokens.next() {
+                    Some(Token::Hex(code)) => code_of(code)?,
+                    Some(Token::Word("endbfchar")) => break,
+                    _ => bail!("a bfchar section of the CMap is malformed"),
+                };
+                let Some(Token::Hex(text)) = tokens.next() else {
+                    bail!("a bfchar section of the CMap is malformed");
+                };
+                map.insert(code, utf16(text)?);
+            },
+            Token::Word("beginbfrange") => loop {
+                let low = match tokens.next() {
+                    Some(Token::Hex(low)) => code_of(low)?,
+                    Some(Token::Word("endbfrange")) => break,
+                    _ => bail!("a bfrange section of the CMap is malformed"),
+                };
+                let Some(Token::Hex(high)) = tokens.next() else {
+                    bail!("a bfrange section of the CMap is malformed");
+                };
+                let high = code_of(high)?;
+                if high < low || high - low > 0xffff {
+                    bail!("the CMap has a range from {:x} to {:x}", low, high);
+                }
+                match tokens.next() {
+                    // the last code unit counts up along the range
+                    Some(Token::Hex(text)) => {
+                        let mut units = utf16_units(text)?;
+                        for code in low..=high {
+                            map.insert(code, String::from_utf16_lossy(&units));
+                            let last = units.last_mut().unwrap();
+                            *last = last.wrapping_add(1);
+                        }
+                    }
+                    Some(Token::Open) => {
+                        for code in low..=high {
+                            match tokens.next() {
+                                Some(Token::Hex(text)) => {
+                                    map.insert(code, utf16(text)?);
+                                }
+                                _ => bail!("a bfrange array of the CMap is too short"),
+                            }
+                        }
+                        if !matches!(tokens.next(), Some(Token::Close)) {
+                            bail!("a bfrange array of the CMap is too long");
+                        }
+                    }
+                    _ => bail!("a bfrange section of the CMap is malformed"),
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(map)
+}
+
+fn code_of(bytes: &[u8]) -> Result<u32> {
+    if bytes.is_empty() || bytes.len() > 4 {
+        bail!("the CMap has a code of {} bytes", bytes.len());
+    }
+    Ok(bytes.iter().fold(0, |code, &byte| code << 8 | byte as u32))
+}
+
+fn utf16_units(bytes: &[u8]) -> Result<Vec<u16>> {
+    if bytes.is_empty() || bytes.len() % 2 == 1 {
+        bail!("the CMap maps a code to {} bytes of UTF-16", bytes.len());
+    }
+    Ok(bytes
+        .chunks(2)
+        .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+        .collect())
+}
+
+fn utf16(bytes: &[u8]) -> Result<String> {
+    Ok(String::from_utf16_lossy(&utf16_units(bytes)?))
+}
@@ -0,0 +1,111 @@
+use std::{ffi::OsString, fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use sonic_rs::Deserialize;
+
+use crate::interactive;
+
+#[derive(Deserialize)]
+struct Har {
+    log: Log,
+}
+
+#[derive(Deserialize)]
+struct Log {
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    request: Request,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    url: String,
+    #[serde(default)]
+    headers: Vec<Pair>,
+    #[serde(default)]
+    cookies: Vec<Pair>,
+}
+
+#[derive(Deserialize)]
+struct Pair {
+    name: String,
+    value: String,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|header| header.name.eq_ignore_ascii_case(name))
+            .map(|header| header.value.as_str())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// The Cookie header, or the cookies listed on their own by browsers that leave it out.
+    fn cookie(&self) -> Option<String> {
+        self.header("cookie").map(str::to_string).or_else(|| {
+            let cookies = self
+                .cookies
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+                .collect::<Vec<_>>();
+            (!cookies.is_empty()).then(|| cookies.join("; "))
+        })
+    }
+}
+
+/// Reads the session and the book last open in a HAR file saved from the network tab of the
+/// developer tools of a browser, and returns the arguments to extract it next to the file with.
+pub fn arguments(path: &Path) -> Result<Vec<OsString>> {
+    let har = sonic_rs::from_str::<Har>(&fs::read_to_string(path)?)?;
+    let requests = har
+        .log
+        .entries
+        .iter()
+        .rev()
+        .map(|entry| &entry.request)
+        .filter(|request| request.url.starts_with("https://plus.pearson.com/"));
+    let mut book = None;
+    let mut cookie = None;
+    let mut auth_token = None;
+    for request in requests {
+        if book.is_none() && request.url.contains("/pdfassets/") {
+            book = interactive::parse_book(&request.url);
+        }
+        cookie = cookie.or_else(|| request.cookie());
+        auth_token = auth_token.or_else(|| request.header("x-authorization").map(str::to_string));
+    }
+    let (product_id, uuid) = book.ok_or_else(|| {
+        anyhow!(
+            "{} has no pages of a book, save it while a book is open",
+            path.display()
+        )
+    })?;
+    let cookie = cookie.ok_or_else(|| {
+        anyhow!(
+            "{} has no cookies, save it with \"Export HAR (with sensitive data)\"",
+            path.display()
+        )
+    })?;
+    println!(
+        "Extracting the book {product_id} with the session in {}.",
+        path.display()
+    );
+    let mut args = vec!["-c".into(), cookie.into()];
+    if let Some(auth_token) = auth_token {
+        args.extend(["-a".into(), auth_token.into()]);
+    }
+    let dir = path.parent().unwrap_or(Path::new("."));
+    args.extend([
+        "-p".into(),
+        product_id.into(),
+        "-u".into(),
+        uuid.into(),
+        "-o".into(),
+        dir.join("Book {product_id}.pdf").into(),
+    ]);
+    Ok(args)
+}
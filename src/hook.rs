@@ -0,0 +1,52 @@
+use std::process::{Command, ExitStatus};
+
+use anyhow::{bail, Result};
+
+/// Runs `command_line` with every `{name}` of `placeholders` replaced by its value.
+///
+/// The command is split into words like a shell would, honoring quotes, before the placeholders
+/// are replaced, and run without a shell, so a title with spaces or semicolons stays one argument.
+pub fn run(command_line: &str, placeholders: &[(&str, &str)]) -> Result<ExitStatus> {
+    let mut words = split_words(command_line)?.into_iter().map(|word| {
+        placeholders.iter().fold(word, |word, (name, value)| {
+            word.replace(&format!("{{{}}}", name), value)
+        })
+    });
+    let Some(program) = words.next() else {
+        bail!("the command is empty");
+    };
+    Ok(Command::new(program).args(words).status()?)
+}
+
+/// Splits `command_line` into words at unquoted white space, much as a POSIX shell does without
+/// expanding anything. Single quotes keep everything up to the next one as it is, double quotes
+/// keep white space and single quotes, and a backslash outside single quotes keeps the next
+/// character, even a quote or another backslash. Quotes join the word around them, so `''` is an
+/// empty word, and an unterminated quote is an error.
+pub fn split_words(command_line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut quote = None;
+    let mut chars = command_line.chars();
+    while let Some(char) = chars.next() {
+        match (quote, char) {
+            (None, '\'' | '"') => {
+                quote = Some(char);
+                word.get_or_insert_with(String::new);
+            }
+            (Some(open), _) if char == open => quote = None,
+            (None | Some('"'), '\\') => {
+                if let Some(next) = chars.next() {
+                    word.get_or_insert_with(String::new).push(next);
+                }
+            }
+            (None, _) if char.is_whitespace() => words.extend(word.take()),
+            _ => word.get_or_insert_with(String::new).push(char),
+        }
+    }
+    if quote.is_some() {
+        bail!("unterminated quote in {}", command_line);
+    }
+    words.extend(word);
+    Ok(words)
+}
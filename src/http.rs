@@ -0,0 +1,107 @@
+use std::{
+    io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// The most bytes the request line and headers of a request may take together.
+const MAX_HEAD: u64 = 16 * 1024;
+/// The most bytes the body of a request may take.
+const MAX_BODY: usize = 1024 * 1024;
+/// How long to wait for a client to send its request or take the response.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A request read by the small HTTP/1.1 servers of `serve --listen` and `login --listen`.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// The headers, with their names in lowercase.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Returns the value of the first header called `name`, in lowercase.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads a request from `stream`, responding 413 and failing if it is too large to handle.
+pub fn read_request(stream: &TcpStream) -> Result<Request> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream).take(MAX_HEAD);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut headers = Vec::new();
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        if !header.ends_with('\n') {
+            if reader.limit() == 0 {
+                return Err(too_large(stream, "the request headers are too large"));
+            }
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let (name, value) = (name.trim().to_ascii_lowercase(), value.trim());
+            if name == "content-length" {
+                content_length = value.parse().unwrap_or_default();
+            }
+            headers.push((name, value.to_string()));
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(too_large(stream, "the request body is too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.into_inner().read_exact(&mut body)?;
+    let mut parts = request_line.split_whitespace();
+    Ok(Request {
+        method: parts.next().unwrap_or_default().to_string(),
+        path: parts.next().unwrap_or_default().to_string(),
+        headers,
+        body,
+    })
+}
+
+/// Responds 413 and returns an error saying `message`.
+fn too_large(stream: &TcpStream, message: &str) -> Error {
+    let _ = write_response(stream, "413 Content Too Large", &[], message.as_bytes());
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Returns a random string that is safe in a URL, for a client to prove it was given.
+pub fn random_token() -> String {
+    let mut bytes = [0; 24];
+    SystemRandom::new().fill(&mut bytes).unwrap();
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Writes a complete response and asks the client to close the connection.
+pub fn write_response(
+    mut stream: &TcpStream,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<()> {
+    let mut head = format!("HTTP/1.1 {status}\r\nContent-Length: {}\r\n", body.len());
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+use anyhow::Result;
+use rusqlite::{params, Connection};
+
+pub struct Index {
+    connection: Connection,
+}
+
+impl Index {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS pages USING fts5(page UNINDEXED, text);",
+        )?;
+        Ok(Self { connection })
+    }
+
+    /// Replaces the indexed text of every page with `texts`, where the position is the page number.
+    pub fn write(&mut self, texts: &[String]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute("DELETE FROM pages", [])?;
+        {
+            let mut insert =
+                transaction.prepare("INSERT INTO pages (page, text) VALUES (?1, ?2)")?;
+            for (page, text) in texts.iter().enumerate() {
+                if !text.is_empty() {
+                    insert.execute(params![page as u32, text])?;
+                }
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Returns the page number and a short snippet of every page matching `query`, best match
+    /// first.
+    pub fn search(&self, query: &str) -> Result<Vec<(u32, String)>> {
+        let mut select = self.connection.prepare(
+            "SELECT page, snippet(pages, 1, '[', ']', '...', 12) FROM pages \
+             WHERE pages MATCH ?1 ORDER BY rank",
+        )?;
+        let rows = select.query_map(params![query], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Returns the page number and text of every indexed page, in page order.
+    pub fn pages(&self) -> Result<Vec<(u32, String)>> {
+        let mut select = self
+            .connection
+            .prepare("SELECT page, text FROM pages ORDER BY CAST(page AS INTEGER)")?;
+        let rows = select.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    credentials::{self, Credentials},
+    exit,
+};
+
+/// Extracts with the arguments `args` returns and keeps the console window open until the output
+/// was read, for a binary double-clicked on Windows or with a file dropped on it, whose window
+/// would otherwise close right away.
+pub fn run(args: impl FnOnce() -> Result<Vec<OsString>>) -> ! {
+    let status = args().and_then(|args| {
+        Ok(std::process::Command::new(env::current_exe()?)
+            .args(args)
+            .status()?)
+    });
+    let code = match status {
+        Ok(status) => status.code().unwrap_or(exit::FAILURE),
+        Err(error) => {
+            eprintln!("Error: {:#}", error);
+            exit::FAILURE
+        }
+    };
+    println!();
+    print!("Press enter to close this window.");
+    let _ = io::stdout().flush();
+    let _ = io::stdin().read_line(&mut String::new());
+    std::process::exit(code);
+}
+
+/// Asks on the terminal for the address of the book, the session and the folder to save it in,
+/// for a binary started without arguments such as by double-clicking it, and returns the
+/// arguments to extract it with. A pasted session is saved like with the login command.
+pub fn prompt() -> Result<Vec<OsString>> {
+    println!("Pearson+ extractor");
+    println!();
+    let (product_id, uuid) = loop {
+        println!("Paste the address of a page image of the book, found in the network tab of the");
+        println!("developer tools of your browser, such as");
+        println!(
+            "https://plus.pearson.com/eplayer/pdfassets/prod1/<product id>/<uuid>/pages/page1"
+        );
+        let line = read_line("Address: ")?;
+        match parse_book(&line) {
+            Some(book) => break book,
+            None => println!("There is no product id and uuid in that address.\n"),
+        }
+    };
+    println!();
+    let saved = Credentials::load(None)?.is_some();
+    loop {
+        let line = if saved {
+            read_line("Press enter to use the saved session, or paste the Cookie header: ")?
+        } else {
+            read_line("Paste the value of the Cookie header: ")?
+        };
+        let cookie = line.trim().trim_start_matches("Cookie:").trim();
+        if !cookie.is_empty() {
+            let credentials = Credentials {
+                cookie: cookie.to_string(),
+                auth_token: credentials::derive_auth_token(cookie)
+                    .map(|(_, token)| token.to_string()),
+            };
+            let path = credentials.save(None)?;
+            println!("Saved the session to {} for next time.", path.display());
+            break;
+        } else if saved {
+            break;
+        }
+    }
+    println!();
+    let default = default_folder();
+    let line = read_line(&format!(
+        "Folder to save the book in, or enter for {}: ",
+        default.display()
+    ))?;
+    // Windows' "Copy as path" quotes the path
+    let folder = line.trim().trim_matches('"');
+    let folder = if folder.is_empty() {
+        default
+    } else {
+        PathBuf::from(folder)
+    };
+    fs::create_dir_all(&folder)?;
+    println!();
+    Ok(vec![
+        "-p".into(),
+        product_id.into(),
+        "-u".into(),
+        uuid.into(),
+        "-o".into(),
+        folder.join("Book {product_id}.pdf").into(),
+    ])
+}
+
+/// Finds the product id and uuid of the book in an address, a number followed by a uuid among
+/// its path segments.
+pub fn parse_book(address: &str) -> Option<(String, String)> {
+    let path = address.trim().split(['?', '#']).next()?;
+    let segments = path.split('/').collect::<Vec<_>>();
+    segments.windows(2).find_map(|pair| {
+        let is_id = !pair[0].is_empty() && pair[0].bytes().all(|byte| byte.is_ascii_digit());
+        let hex = pair[1].bytes().filter(u8::is_ascii_hexdigit).count();
+        let is_uuid = hex == 32
+            && pair[1]
+                .bytes()
+                .all(|byte| byte.is_ascii_hexdigit() || byte == b'-');
+        (is_id && is_uuid).then(|| (pair[0].to_string(), pair[1].to_string()))
+    })
+}
+
+/// The Downloads folder of the user, or the working directory without one.
+fn default_folder() -> PathBuf {
+    env::var_os("USERPROFILE")
+        .or_else(|| env::var_os("HOME"))
+        .map(|home| PathBuf::from(home).join("Downloads"))
+        .filter(|downloads| downloads.is_dir())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn read_line(prompt: &str) -> Result<String> {
+    print!("{prompt}");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().read_line(&mut line)? == 0 {
+        bail!("the terminal closed");
+    }
+    Ok(line)
+}
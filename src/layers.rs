@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use lopdf::{dictionary, Document, Object, ObjectId};
+
+/// Merges the optional content groups of the same name, such as the ones of documents merged into
+/// one, into one group per layer name, so a viewer toggles a layer for the whole document at once.
+pub fn merge_layers(document: &mut Document) -> Result<()> {
+    let catalog = document.catalog()?;
+    let groups = catalog
+        .get(b"OCProperties")?
+        .as_dict()?
+        .get(b"OCGs")?
+        .as_array()?
+        .iter()
+        .map(Object::as_reference)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut merged = Vec::<ObjectId>::new();
+    let mut names = HashMap::new();
+    let mut replacements = HashMap::new();
+    for group in groups {
+        let name = document
+            .get_dictionary(group)?
+            .get(b"Name")?
+            .as_str()?
+            .to_vec();
+        let target = *names.entry(name).or_insert_with(|| {
+            merged.push(group);
+            group
+        });
+        if target != group {
+            replacements.insert(group, target);
+        }
+    }
+    for page in document.get_pages().into_values() {
+        let resources = match document.get_dictionary(page)?.get(b"Resources")? {
+            Object::Reference(resources) => document.get_dictionary_mut(*resources)?,
+            Object::Dictionary(_) => document
+                .get_dictionary_mut(page)?
+                .get_mut(b"Resources")?
+                .as_dict_mut()?,
+            _ => continue,
+        };
+        if let Ok(properties) = resources
+            .get_mut(b"Properties")
+            .and_then(Object::as_dict_mut)
+        {
+            for (_, property) in properties.iter_mut() {
+                if let Some(&target) = property
+                    .as_reference()
+                    .ok()
+                    .and_then(|id| replacements.get(&id))
+                {
+                    *property = target.into();
+                }
+            }
+        }
+    }
+    for group in replacements.into_keys() {
+        document.objects.remove(&group);
+    }
+    let merged = merged.into_iter().map(Object::from).collect::<Vec<_>>();
+    document.catalog_mut()?.set(
+        "OCProperties",
+        dictionary! {
+            "OCGs" => merged.clone(),
+            "D" => dictionary! {
+                "Order" => merged.clone(),
+                "RBGroups" => Vec::<Object>::new(),
+                "ON" => merged,
+            },
+        },
+    );
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! Downloading the pages of a book from Pearson+ and laying them out as a PDF with their text
+//! layers. The command line runs an [`extractor::Extractor`], which other programs can run as it
+//! does, and the layout can be tested apart from downloading.
+
+pub mod annotation;
+pub mod assemble;
+pub mod attachments;
+pub mod backlinks;
+pub mod cache;
+pub mod calibre;
+pub mod cancel;
+pub mod catalog;
+pub mod color;
+pub mod compression;
+pub mod config;
+pub mod credentials;
+pub mod crossref;
+pub mod csv;
+pub mod dedupe;
+pub mod extractor;
+pub mod figures;
+pub mod filename;
+pub mod garbled;
+pub mod glyphs;
+pub mod hook;
+pub mod http;
+pub mod linearize;
+pub mod manifest;
+pub mod media;
+pub mod metrics;
+pub mod numbering;
+pub mod objstm;
+#[cfg(feature = "ocr")]
+pub mod ocr;
+pub mod overlay;
+pub mod probe;
+pub mod progress;
+pub mod raster;
+pub mod redact;
+pub mod script;
+pub mod shrink;
+pub mod sink;
+pub mod spill;
+pub mod split;
+pub mod stamp;
+pub mod tables;
+pub mod tags;
+pub mod toc;
+pub mod warc;
+
+/// Writes `bytes` as lowercase hexadecimal digits.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// The text layer of a page, read with [`annotation::parse`].
+#[derive(Clone)]
+pub struct TextPageData {
+    /// The `texts` of the page.
+    pub data: Vec<Text>,
+}
+
+/// A run of text, every character with its position and size in points.
+#[derive(Clone)]
+pub struct Text {
+    /// The `mt` of the run.
+    pub matrix: [f32; 6],
+    /// The `cs` of the run, the position, width, height and code point of every character.
+    pub stream: Vec<(f32, f32, f32, f32, u32)>,
+}
@@ -0,0 +1,523 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+};
+
+use anyhow::{bail, Result};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+
+use crate::objstm;
+
+/// The room the linearization parameter dictionary and the first-page trailer leave for their
+/// numbers, which are only known once everything after them is laid out.
+const NUMBER_SLACK: usize = 10;
+
+/// Saves `document` linearized for fast web view: the first page and everything showing it
+/// takes come first, with a cross-reference section of their own, then every other page in order
+/// with the objects only it uses, along with hint tables telling a reader where every page
+/// starts, so it can show the first pages of a file served over HTTP before the rest arrives.
+pub fn save(document: &Document, output: &mut impl Write) -> Result<()> {
+    let pages = document.get_pages().into_values().collect::<Vec<_>>();
+    let Some(&first_page) = pages.first() else {
+        bail!("the document has no pages");
+    };
+    let catalog = document.trailer.get(b"Root")?.as_reference()?;
+    let mut stop = pages.iter().copied().collect::<HashSet<_>>();
+    stop.insert(catalog);
+    // the objects every page uses, in the order they are reached from it
+    let mut users = HashMap::<ObjectId, usize>::new();
+    let mut reached = Vec::new();
+    for &page in &pages {
+        let mut seen = HashSet::new();
+        let mut order = vec![page];
+        reach(
+            document,
+            document.get_object(page)?,
+            &stop,
+            &mut seen,
+            &mut order,
+        );
+        for &id in &order[1..] {
+            *users.entry(id).or_default() += 1;
+        }
+        reached.push(order);
+    }
+    let first_section = reached[0].clone();
+    let placed_first = first_section.iter().copied().collect::<HashSet<_>>();
+    let mut page_sections = vec![first_section];
+    let mut shared = Vec::new();
+    let mut shared_seen = HashSet::new();
+    for order in &reached[1..] {
+        let mut section = vec![order[0]];
+        for &id in &order[1..] {
+            if placed_first.contains(&id) {
+                continue;
+            }
+            if users[&id] == 1 {
+                section.push(id);
+            } else if shared_seen.insert(id) {
+                shared.push(id);
+            }
+        }
+        page_sections.push(section);
+    }
+    let mut placed = page_sections
+        .iter()
+        .flatten()
+        .chain(&shared)
+        .copied()
+        .collect::<HashSet<_>>();
+    placed.insert(catalog);
+    let others = document
+        .objects
+        .keys()
+        .filter(|id| !placed.contains(id))
+        .copied()
+        .collect::<Vec<_>>();
+
+    // the rest of the file is numbered from 1 in the order it is written, and the first-page
+    // section after it, so each cross-reference section covers one range of numbers
+    let mut numbers = HashMap::new();
+    let main = page_sections[1..]
+        .iter()
+        .flatten()
+        .chain(&shared)
+        .chain(&others)
+        .copied()
+        .collect::<Vec<_>>();
+    for (i, &id) in main.iter().enumerate() {
+        numbers.insert(id, i as u32 + 1);
+    }
+    let first_id = main.len() as u32 + 1;
+    let linearization_id = first_id;
+    let catalog_id = first_id + 1;
+    let hint_id = first_id + 2;
+    numbers.insert(catalog, catalog_id);
+    for (i, &id) in page_sections[0].iter().enumerate() {
+        numbers.insert(id, hint_id + 1 + i as u32);
+    }
+    let size = hint_id + 1 + page_sections[0].len() as u32;
+
+    let mut buffer = Vec::new();
+    let mut length = |id: ObjectId| {
+        buffer.clear();
+        write_renumbered(&mut buffer, document, id, &numbers);
+        buffer.len()
+    };
+    let main_lengths = main.iter().map(|&id| length(id)).collect::<Vec<_>>();
+    let first_lengths = page_sections[0]
+        .iter()
+        .map(|&id| length(id))
+        .collect::<Vec<_>>();
+    let catalog_length = length(catalog);
+
+    // what the hint tables describe: every page, then every shared object as a group of one
+    let mut page_counts = Vec::new();
+    let mut page_lengths = Vec::new();
+    page_counts.push(first_lengths.len() as u64);
+    page_lengths.push(first_lengths.iter().sum::<usize>() as u64);
+    let mut at = 0;
+    for section in &page_sections[1..] {
+        page_counts.push(section.len() as u64);
+        page_lengths.push(main_lengths[at..at + section.len()].iter().sum::<usize>() as u64);
+        at += section.len();
+    }
+    let shared_lengths = first_lengths
+        .iter()
+        .chain(&main_lengths[at..at + shared.len()])
+        .map(|&length| length as u64)
+        .collect::<Vec<_>>();
+    let groups = page_sections[0]
+        .iter()
+        .chain(&shared)
+        .enumerate()
+        .map(|(i, &id)| (id, i as u64))
+        .collect::<HashMap<_, _>>();
+    let shared_references = reached
+        .iter()
+        .enumerate()
+        .map(|(page, order)| match page {
+            // everything the first page uses is part of it
+            0 => Vec::new(),
+            _ => order[1..]
+                .iter()
+                .filter_map(|id| groups.get(id).copied())
+                .collect::<Vec<_>>(),
+        })
+        .collect::<Vec<_>>();
+    let hints = Hints {
+        page_counts: &page_counts,
+        page_lengths: &page_lengths,
+        shared_references: &shared_references,
+        shared_lengths: &shared_lengths,
+        first_shared_id: if shared.is_empty() {
+            0
+        } else {
+            numbers[&shared[0]]
+        },
+        first_page_count: first_lengths.len() as u64,
+    };
+
+    let header = objstm::header(&document.version);
+    let linearization_length = linearization(linearization_id, &[0; 7]).len();
+    let first_xref_length = first_xref(document, first_id, size, &numbers, &[], 0).len();
+    let (hint_table, shared_offset) = hints.encode(0, 0);
+    let hint_length = hint_stream(hint_id, &hint_table, shared_offset).len();
+    let hint_offset = header.len() + linearization_length + first_xref_length + catalog_length;
+    let first_page_offset = hint_offset + hint_length;
+    let first_page_end = first_page_offset + first_lengths.iter().sum::<usize>();
+    let mut main_offsets = Vec::new();
+    let mut offset = first_page_end;
+    for length in &main_lengths {
+        main_offsets.push(offset);
+        offset += length;
+    }
+    let main_xref_offset = offset;
+    let main_xref_head = format!("xref\n0 {}\n", first_id);
+    let main_trailer = main_trailer(first_id);
+    let file_length = main_xref_offset
+        + main_xref_head.len()
+        + 20 * first_id as usize
+        + main_trailer.len()
+        + startxref(header.len() + linearization_length).len();
+
+    // the hint tables give offsets as though the hint stream weren't there
+    let shared_location = match main_offsets.get(at) {
+        Some(&offset) if !shared.is_empty() => offset - hint_length,
+        _ => 0,
+    };
+    let (hint_table, shared_offset) =
+        hints.encode(first_page_offset - hint_length, shared_location);
+    let mut first_offsets = vec![header.len(), hint_offset - catalog_length, hint_offset];
+    let mut offset = first_page_offset;
+    for length in &first_lengths {
+        first_offsets.push(offset);
+        offset += length;
+    }
+
+    output.write_all(&header)?;
+    let parameters = [
+        file_length,
+        hint_offset,
+        hint_length,
+        numbers[&first_page] as usize,
+        first_page_end,
+        pages.len(),
+        main_xref_offset + main_xref_head.len() - 1,
+    ];
+    output.write_all(&pad(
+        linearization(linearization_id, &parameters),
+        linearization_length,
+        b"\nendobj\n",
+    ))?;
+    output.write_all(&pad(
+        first_xref(
+            document,
+            first_id,
+            size,
+            &numbers,
+            &first_offsets,
+            main_xref_offset,
+        ),
+        first_xref_length,
+        b"\nstartxref\n0\n%%EOF\n",
+    ))?;
+    let mut buffer = Vec::new();
+    write_renumbered(&mut buffer, document, catalog, &numbers);
+    output.write_all(&buffer)?;
+    output.write_all(&hint_stream(hint_id, &hint_table, shared_offset))?;
+    for &id in page_sections[0].iter().chain(&main) {
+        buffer.clear();
+        write_renumbered(&mut buffer, document, id, &numbers);
+        output.write_all(&buffer)?;
+    }
+    output.write_all(main_xref_head.as_bytes())?;
+    output.write_all(b"0000000000 65535 f \n")?;
+    for offset in main_offsets {
+        output.write_all(format!("{:010} 00000 n \n", offset).as_bytes())?;
+    }
+    output.write_all(&main_trailer)?;
+    output.write_all(&startxref(header.len() + linearization_length))?;
+    Ok(())
+}
+
+/// Adds the objects `object` refers to, directly or through others, to `order` in the order they
+/// are reached, stopping at pages, whose objects are theirs, and at the objects in `stop`.
+fn reach(
+    document: &Document,
+    object: &Object,
+    stop: &HashSet<ObjectId>,
+    seen: &mut HashSet<ObjectId>,
+    order: &mut Vec<ObjectId>,
+) {
+    let dict = match object {
+        Object::Reference(id) => {
+            if stop.contains(id) || !seen.insert(*id) {
+                return;
+            }
+            let Ok(referenced) = document.get_object(*id) else {
+                return;
+            };
+            let kind = match referenced {
+                Object::Dictionary(dict) => dict.get(b"Type").and_then(Object::as_name).ok(),
+                _ => None,
+            };
+            if let Some(b"Page" | b"Pages") = kind {
+                return;
+            }
+            order.push(*id);
+            return reach(document, referenced, stop, seen, order);
+        }
+        Object::Array(array) => {
+            for item in array {
+                reach(document, item, stop, seen, order);
+            }
+            return;
+        }
+        Object::Dictionary(dict) => dict,
+        Object::Stream(stream) => &stream.dict,
+        _ => return,
+    };
+    for (key, value) in dict.iter() {
+        // the page tree is reached from the catalog
+        if key != b"Parent" {
+            reach(document, value, stop, seen, order);
+        }
+    }
+}
+
+/// Writes the object `id` with the references in it to the numbers in `numbers`, and to null
+/// for objects the document doesn't have.
+fn write_renumbered(
+    file: &mut Vec<u8>,
+    document: &Document,
+    id: ObjectId,
+    numbers: &HashMap<ObjectId, u32>,
+) {
+    let object = document.get_object(id).unwrap_or(&Object::Null);
+    objstm::write_indirect(file, numbers[&id], 0, |file| match object {
+        // the contents of a stream, such as a page image, aren't copied to renumber it
+        Object::Stream(stream) => {
+            let dict = renumber(&Object::Dictionary(stream.dict.clone()), numbers);
+            objstm::write_stream_content(file, dict.as_dict().unwrap(), &stream.content);
+        }
+        object => objstm::write_object(file, &renumber(object, numbers)),
+    });
+}
+
+fn renumber(object: &Object, numbers: &HashMap<ObjectId, u32>) -> Object {
+    match object {
+        Object::Reference(id) => match numbers.get(id) {
+            Some(&number) => Object::Reference((number, 0)),
+            None => Object::Null,
+        },
+        Object::Array(array) => {
+            Object::Array(array.iter().map(|item| renumber(item, numbers)).collect())
+        }
+        Object::Dictionary(dict) => Object::Dictionary(renumber_dict(dict, numbers)),
+        Object::Stream(stream) => {
+            let mut stream = stream.clone();
+            stream.dict = renumber_dict(&stream.dict, numbers);
+            Object::Stream(stream)
+        }
+        object => object.clone(),
+    }
+}
+
+fn renumber_dict(dict: &Dictionary, numbers: &HashMap<ObjectId, u32>) -> Dictionary {
+    let mut renumbered = Dictionary::new();
+    for (key, value) in dict.iter() {
+        renumbered.set(key.clone(), renumber(value, numbers));
+    }
+    renumbered
+}
+
+/// The linearization parameter dictionary with the values of L, H, O, E, N and T.
+fn linearization(id: u32, parameters: &[usize; 7]) -> Vec<u8> {
+    let [length, hint_offset, hint_length, first_page, first_page_end, pages, main_xref] =
+        parameters;
+    let mut text = format!(
+        "{} 0 obj\n<< /Linearized 1 /L {} /H [ {} {} ] /O {} /E {} /N {} /T {} >>",
+        id, length, hint_offset, hint_length, first_page, first_page_end, pages, main_xref
+    );
+    text.extend(std::iter::repeat_n(' ', NUMBER_SLACK * parameters.len()));
+    text.push_str("\nendobj\n");
+    text.into_bytes()
+}
+
+/// The cross-reference section of the first page, with `offsets` for the objects numbered from
+/// `first_id`, and its trailer pointing on to the main cross-reference table at `prev`.
+fn first_xref(
+    document: &Document,
+    first_id: u32,
+    size: u32,
+    numbers: &HashMap<ObjectId, u32>,
+    offsets: &[usize],
+    prev: usize,
+) -> Vec<u8> {
+    let count = (size - first_id) as usize;
+    let mut xref = format!("xref\n{} {}\n", first_id, count).into_bytes();
+    for i in 0..count {
+        let offset = offsets.get(i).copied().unwrap_or_default();
+        xref.extend(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    let mut trailer = dictionary! { "Size" => size as i64 };
+    for key in [b"Root".as_slice(), b"Info", b"ID"] {
+        if let Ok(value) = document.trailer.get(key) {
+            trailer.set(key, renumber(value, numbers));
+        }
+    }
+    trailer.set("Prev", prev as i64);
+    xref.extend(b"trailer\n");
+    objstm::write_object(&mut xref, &Object::Dictionary(trailer));
+    xref.extend(std::iter::repeat_n(b' ', NUMBER_SLACK));
+    xref.extend(b"\nstartxref\n0\n%%EOF\n");
+    xref
+}
+
+fn main_trailer(size: u32) -> Vec<u8> {
+    format!("trailer\n<< /Size {} >>\n", size).into_bytes()
+}
+
+fn startxref(first_xref: usize) -> Vec<u8> {
+    format!("startxref\n{}\n%%EOF\n", first_xref).into_bytes()
+}
+
+/// Pads `bytes` to `length` with spaces in front of `end`, which they finish with.
+fn pad(mut bytes: Vec<u8>, length: usize, end: &[u8]) -> Vec<u8> {
+    let trimmed = bytes.len() - end.len();
+    bytes.truncate(trimmed);
+    while bytes.last() == Some(&b' ') {
+        bytes.pop();
+    }
+    bytes.resize(length - end.len(), b' ');
+    bytes.extend(end);
+    bytes
+}
+
+/// The primary hint stream, uncompressed so its length is known before its offsets are.
+fn hint_stream(id: u32, table: &[u8], shared_offset: usize) -> Vec<u8> {
+    let dict = dictionary! { "S" => shared_offset as i64 };
+    let mut bytes = Vec::new();
+    objstm::write_indirect(&mut bytes, id, 0, |file| {
+        objstm::write_stream_content(file, &dict, table)
+    });
+    bytes
+}
+
+/// What the page offset and shared object hint tables list.
+struct Hints<'a> {
+    /// The objects of every page.
+    page_counts: &'a [u64],
+    /// The bytes of every page.
+    page_lengths: &'a [u64],
+    /// The shared objects every page uses, by their index among all of them.
+    shared_references: &'a [Vec<u64>],
+    /// The bytes of the objects of the first page, then of the shared objects of the others.
+    shared_lengths: &'a [u64],
+    first_shared_id: u32,
+    first_page_count: u64,
+}
+
+impl Hints<'_> {
+    /// Encodes the page offset hint table and the shared object hint table after it, whose
+    /// offset in the stream it returns along with them.
+    fn encode(&self, first_page: usize, first_shared: usize) -> (Vec<u8>, usize) {
+        let mut bits = Bits::default();
+        let (least_count, count_bits) = range(self.page_counts);
+        let (least_length, length_bits) = range(self.page_lengths);
+        let most_references = self.shared_references.iter().map(Vec::len).max();
+        let reference_bits = width(most_references.unwrap_or_default() as u64);
+        let greatest_reference = self.shared_references.iter().flatten().max();
+        let identifier_bits = width(greatest_reference.copied().unwrap_or_default());
+        bits.write(least_count, 32);
+        bits.write(first_page as u64, 32);
+        bits.write(count_bits as u64, 16);
+        bits.write(least_length, 32);
+        bits.write(length_bits as u64, 16);
+        // the content streams are described as spanning their pages, as Acrobat does
+        bits.write(0, 32);
+        bits.write(0, 16);
+        bits.write(least_length, 32);
+        bits.write(length_bits as u64, 16);
+        bits.write(reference_bits as u64, 16);
+        bits.write(identifier_bits as u64, 16);
+        bits.write(0, 16);
+        bits.write(1, 16);
+        for count in self.page_counts {
+            bits.write(count - least_count, count_bits);
+        }
+        bits.align();
+        for length in self.page_lengths {
+            bits.write(length - least_length, length_bits);
+        }
+        bits.align();
+        for references in self.shared_references {
+            bits.write(references.len() as u64, reference_bits);
+        }
+        bits.align();
+        for &reference in self.shared_references.iter().flatten() {
+            bits.write(reference, identifier_bits);
+        }
+        bits.align();
+        for length in self.page_lengths {
+            bits.write(length - least_length, length_bits);
+        }
+        bits.align();
+        let shared_offset = bits.bytes.len();
+        let (least_group, group_bits) = range(self.shared_lengths);
+        bits.write(self.first_shared_id as u64, 32);
+        bits.write(first_shared as u64, 32);
+        bits.write(self.first_page_count, 32);
+        bits.write(self.shared_lengths.len() as u64, 32);
+        bits.write(0, 16);
+        bits.write(least_group, 32);
+        bits.write(group_bits as u64, 16);
+        for length in self.shared_lengths {
+            bits.write(length - least_group, group_bits);
+        }
+        bits.align();
+        for _ in self.shared_lengths {
+            bits.write(0, 1);
+        }
+        bits.align();
+        (bits.bytes, shared_offset)
+    }
+}
+
+/// Bits written from the most significant one down, as the hint tables are.
+#[derive(Default)]
+struct Bits {
+    bytes: Vec<u8>,
+    /// The bits of the last byte in use, 0 when it is full.
+    used: u32,
+}
+
+impl Bits {
+    fn write(&mut self, value: u64, width: u32) {
+        for i in (0..width).rev() {
+            if self.used == 0 {
+                self.bytes.push(0);
+            }
+            let bit = (value >> i & 1) as u8;
+            *self.bytes.last_mut().unwrap() |= bit << (7 - self.used);
+            self.used = (self.used + 1) % 8;
+        }
+    }
+
+    /// Starts the next value on a byte of its own.
+    fn align(&mut self) {
+        self.used = 0;
+    }
+}
+
+/// The least of `values` and the bits the differences from it take.
+fn range(values: &[u64]) -> (u64, u32) {
+    let least = values.iter().copied().min().unwrap_or_default();
+    let greatest = values.iter().copied().max().unwrap_or_default();
+    (least, width(greatest - least))
+}
+
+fn width(value: u64) -> u32 {
+    u64::BITS - value.leading_zeros()
+}
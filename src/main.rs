@@ -1,152 +1,215 @@
 use std::{
-    fs::File,
-    io::{BufWriter, Cursor, Write},
-    path::PathBuf,
+    fs::{self, File},
+    io::{self, IsTerminal},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
-use anyhow::Result;
-use clap::Parser;
-use printpdf::{
-    image_crate::{codecs::png::PngDecoder, ImageDecoder},
-    BuiltinFont, Image, ImageTransform, Mm, PdfDocument, TextMatrix, TextRenderingMode,
+use anyhow::{bail, Result};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use index::Index;
+use pearson_plus_extractor::{
+    cache::Cache,
+    calibre,
+    cancel::CancelToken,
+    config::{self, Config},
+    credentials::{self, Credentials},
+    extractor::{Connection, Cover, Extraction, Extractor, HttpVersion, Intercepted, Options},
+    figures, filename, glyphs, hex, hook, http, manifest, media,
+    metrics::{self, Counters},
+    numbering::Numbering,
+    objstm::Packing,
+    progress, raster, redact,
+    script::Script,
+    split,
+    stamp::{self, Stamp},
+    toc::{self, Toc},
+    warc::{self, Recorder, Replay},
 };
-use reqwest::{
-    header::{HeaderMap, COOKIE, REFERER},
-    Client,
-};
-use serde::{de::Error, Deserializer};
-use sonic_rs::Deserialize;
-use tokio::join;
-
-#[derive(Deserialize)]
-struct Annotation {
-    #[serde(
-        rename = "TextPageData",
-        deserialize_with = "deserialize_text_page_data"
-    )]
-    data: TextPageData,
-}
-
-fn deserialize_text_page_data<'de, D>(deserializer: D) -> Result<TextPageData, D::Error>
-where
-    D: Deserializer<'de>,
-    D::Error: Error,
-{
-    let text_page_data = String::deserialize(deserializer)?;
-    Ok(sonic_rs::from_str(&text_page_data).map_err(|error| D::Error::custom(error))?)
-}
-
-#[derive(Deserialize)]
-struct TextPageData {
-    #[serde(rename = "texts")]
-    data: Vec<Text>,
-}
-
-#[derive(Deserialize)]
-struct Text {
-    #[serde(rename = "mt")]
-    matrix: [f32; 6],
-    #[serde(rename = "cs")]
-    stream: Vec<(f32, f32, f32, f32, u32)>,
-}
-
-struct Extractor {
-    client: Client,
-}
-
-impl Extractor {
-    pub fn new(cookie: impl AsRef<str>, auth_token: impl AsRef<str>) -> Result<Self> {
-        let mut default_headers = HeaderMap::new();
-        default_headers.insert(REFERER, "https://plus.pearson.com/".parse()?);
-        default_headers.insert(COOKIE, cookie.as_ref().parse()?);
-        default_headers.insert("X-Authorization", auth_token.as_ref().parse()?);
-        let client = Client::builder().default_headers(default_headers).build()?;
-        Ok(Self { client })
-    }
-
-    pub async fn run(
-        self,
-        product_id: u32,
-        uuid: impl AsRef<str>,
-        output: impl Write,
-    ) -> Result<()> {
-        let image = self.get_image(product_id, uuid.as_ref(), 0).await?;
-        let title = "Pearson Plus";
-        let image = PngDecoder::new(Cursor::new(image)).unwrap();
-        let (w, h) = image.dimensions();
-        let (w, h) = (Mm(w as f32 / 12.0), Mm(h as f32 / 12.0));
-        let (document, page, layer) = PdfDocument::new(title, w, h, "layer");
-        let image_transform = ImageTransform {
-            dpi: Some(300.0),
-            ..Default::default()
-        };
-        let font = &document.add_builtin_font(BuiltinFont::TimesRoman).unwrap();
-        let layer = document.get_page(page).get_layer(layer);
-        let image = Image::try_from(image).unwrap();
-        image.add_to_layer(layer, image_transform);
-        for i in 1..u32::MAX {
-            println!("Downloaded page {:04}.", i);
-            let (image, texts) = join!(
-                self.get_image(product_id, uuid.as_ref(), i),
-                self.get_texts(product_id, uuid.as_ref(), i)
-            );
-            if let Ok(image) = PngDecoder::new(Cursor::new(image?)) {
-                let (w, h) = image.dimensions();
-                let (w, h) = (Mm(w as f32 / 12.0), Mm(h as f32 / 12.0));
-                let (page, layer) = document.add_page(w, h, "layer");
-                let layer = document.get_page(page).get_layer(layer);
-                let image = Image::try_from(image)?;
-                image.add_to_layer(layer.clone(), image_transform);
-                layer.begin_text_section();
-                layer.set_font(font, 1.0);
-                layer.set_text_rendering_mode(TextRenderingMode::Invisible);
-                for data in texts?.data {
-                    let mut matrix = data.matrix;
-                    for (x, y, _, _, char) in data.stream {
-                        matrix[4] = x;
-                        matrix[5] = y;
-                        layer.set_text_matrix(TextMatrix::Raw(matrix));
-                        if let Some(char) = char::from_u32(char) {
-                            layer.write_text(char, font);
-                        }
-                    }
-                }
-                layer.end_text_section();
-            } else {
-                break;
-            }
-        }
-        println!("Saving the document. This make take a while.");
-        document.save(&mut BufWriter::new(output))?;
-        Ok(())
-    }
+use reqwest::Certificate;
+use ring::rand::{SecureRandom, SystemRandom};
 
-    async fn get_image(&self, product_id: u32, uuid: &str, page: u32) -> Result<Vec<u8>> {
-        let dest = format!(
-            "https://plus.pearson.com/eplayer/pdfassets/prod1/{product_id}/{uuid}/pages/page{page}"
-        );
-        let resp = self.client.get(dest).send().await?;
-        let data = resp.bytes().await?;
-        Ok(Vec::from(data))
-    }
+mod anki;
+mod api;
+mod batch;
+mod completions;
+mod convert;
+mod deliver;
+mod desktop;
+mod disk;
+mod edition;
+mod exit;
+mod export;
+mod har;
+mod index;
+mod interactive;
+mod layers;
+mod merge;
+mod notes;
+mod notify;
+mod questions;
+mod queue;
+mod smtp;
+mod stats;
+mod storage;
+mod update;
+mod verify;
 
-    async fn get_texts(&self, product_id: u32, uuid: &str, page: u32) -> Result<TextPageData> {
-        let dest = format!(
-            "https://plus.pearson.com/eplayer/pdfassets/prod1/{product_id}/{uuid}/annotations/page{page}"
-        );
-        let resp = self.client.get(dest).send().await?;
-        let text = resp.text().await?;
-        Ok(sonic_rs::from_str::<Annotation>(&text)?.data)
-    }
-}
+/// How many pages after the cover are searched for the edition of the book.
+const FRONT_MATTER_PAGES: u32 = 6;
+/// The memory cap of --low-resource, which leaves room on a board with 512 MB.
+const LOW_RESOURCE_MEMORY: u64 = 256_000_000;
 
 #[derive(Parser)]
+#[command(
+    args_conflicts_with_subcommands = true,
+    after_help = "Exit codes: 1 failure, 2 no session or an expired one, 3 network failure, \
+                  4 partial success with damaged pages, 5 the output can't be written, \
+                  64 invalid usage."
+)]
+struct Cli {
+    /// Print the supported formats, platform and features as JSON, for programs built on top.
+    #[arg(long, exclusive = true)]
+    capabilities: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    args: Option<Args>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Search the text of a book indexed with --index.
+    Search {
+        /// Index file path.
+        #[clap(default_value = "out.sqlite")]
+        #[arg(short, long)]
+        index: PathBuf,
+        /// Full-text query, using SQLite FTS5 syntax.
+        query: String,
+    },
+    /// Report the word count, reading time and figure count of every chapter of a book
+    /// indexed with --index.
+    Stats {
+        /// Index file path.
+        #[clap(default_value = "out.sqlite")]
+        #[arg(short, long)]
+        index: PathBuf,
+        /// Table of contents as with --toc, whose top level entries are the chapters.
+        #[arg(long)]
+        toc: Option<PathBuf>,
+        /// Reading speed used for the estimated reading time.
+        #[clap(default_value = "238")]
+        #[arg(long)]
+        words_per_minute: f32,
+    },
+    /// Run the extraction jobs put into a directory as JSON arrays of command line arguments,
+    /// such as ["-c", "...", "-p", "123", "-u", "...", "-o", "book.pdf"] in book.json.
+    Serve {
+        /// Job directory, also the working directory of the jobs.
+        #[arg(long)]
+        queue: PathBuf,
+        /// How many jobs to run at a time.
+        #[clap(default_value = "2")]
+        #[arg(long)]
+        concurrency: usize,
+        /// The most requests to plus.pearson.com the running jobs make at a time,
+        /// as a fixed share for each of the jobs --concurrency runs at once.
+        /// A job's own --max-requests can only lower its share.
+        #[arg(long)]
+        max_requests: Option<usize>,
+        /// Also serve an HTTP API on this address, such as 127.0.0.1:8080,
+        /// to submit jobs, poll their progress and download the results,
+        /// with Prometheus metrics at /metrics.
+        #[arg(long)]
+        listen: Option<SocketAddr>,
+        /// The bearer token clients of the HTTP API authenticate with,
+        /// a random one printed at startup by default.
+        #[arg(long, requires = "listen")]
+        api_token: Option<String>,
+        /// POST a JSON report of every job that finishes or fails to this URL,
+        /// or a message to a Discord or Slack webhook URL.
+        #[arg(long)]
+        webhook: Option<String>,
+    },
+    /// Run the extraction jobs in a file, or streamed in on stdin with -, one JSON array of
+    /// command line arguments per line, one after another.
+    Batch {
+        /// File of jobs, or - for stdin.
+        input: PathBuf,
+    },
+    /// Check a document against the manifest written with --manifest, and the images
+    /// downloaded for it against the hashes they had then, to catch corruption after the fact.
+    Verify {
+        /// The document to check.
+        document: PathBuf,
+        /// The manifest written along with it.
+        manifest: PathBuf,
+        /// Hash the images kept in this cache directory again.
+        #[arg(long, conflicts_with = "replay")]
+        cache: Option<PathBuf>,
+        /// Hash the images recorded in this WARC file with --record again.
+        #[arg(long)]
+        replay: Option<PathBuf>,
+    },
+    /// Save the session headers sent by a browser extension or bookmarklet, so --cookie
+    /// and --auth-token can be left out. A bookmarklet can't read the HttpOnly cookies of
+    /// the session, so it asks for the Cookie header to be pasted by hand.
+    Login {
+        /// Address to wait on for a POST of {"cookie", "auth_token"}.
+        #[clap(default_value = "127.0.0.1:8765")]
+        #[arg(long)]
+        listen: SocketAddr,
+        /// Save the session under this name, such as personal or university,
+        /// to pick it with --profile.
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Concatenate documents, such as the parts written with --split-every or a book
+    /// and newly extracted chapters, joining their outlines and page labels.
+    Merge {
+        /// Output file path.
+        #[clap(default_value = "out.pdf")]
+        #[arg(short, long)]
+        output_path: PathBuf,
+        /// Overwrite the output file if it already exists.
+        #[arg(long, conflicts_with = "backup")]
+        force: bool,
+        /// Keep an existing output file by renaming it to out.pdf.bak.
+        #[arg(long)]
+        backup: bool,
+        /// The documents to concatenate, in order.
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Print a completion script for the flags and subcommands, such as
+    /// `source <(pearson-plus-extractor completions bash)` in ~/.bashrc.
+    Completions {
+        #[arg(value_enum)]
+        shell: completions::Shell,
+    },
+    /// Replace this binary with the latest release from GitHub, after verifying its checksum.
+    SelfUpdate {
+        /// Only report whether a newer release is out.
+        #[arg(long)]
+        check: bool,
+    },
+}
+
+#[derive(clap::Args)]
 struct Args {
     /// Copy and paste the value of the Cookie header.
+    /// Defaults to the one saved with the login command.
     #[arg(short, long)]
-    cookie: String,
+    cookie: Option<String>,
+    /// Use the session saved with login --profile under this name.
+    #[arg(long, conflicts_with = "cookie")]
+    profile: Option<String>,
     /// This is only necessary when you want to download links.
     /// Copy and paste the value of the X-Authorization header.
+    /// Defaults to the access token among the cookies, if there is one.
     #[arg(short, long)]
     auth_token: Option<String>,
     /// Copy and paste the product id of the book.
@@ -155,19 +218,1233 @@ struct Args {
     /// Copy and paste the uuid of the book.
     #[arg(short, long)]
     uuid: String,
-    /// Output file path.
+    /// The title of the book, used in the document properties and --stamp.
+    #[arg(long)]
+    title: Option<String>,
+    /// An author of the book, repeated for each author.
+    #[arg(long)]
+    author: Vec<String>,
+    /// The ISBN of the book, written with --calibre.
+    #[arg(long)]
+    isbn: Option<String>,
+    /// The series of the book, written with --calibre.
+    #[arg(long)]
+    series: Option<String>,
+    /// The edition of the book, written with --calibre as its number in the --series.
+    #[arg(long)]
+    edition: Option<f32>,
+    /// Look for the edition in the front matter of the book when --edition isn't
+    /// given, as is done anyway when the output path has an {edition}.
+    #[arg(long, conflicts_with = "edition")]
+    detect_edition: bool,
+    /// The year the book was published.
+    #[arg(long)]
+    year: Option<u16>,
+    /// What to use as the first page of the document and the --calibre cover.
+    #[clap(default_value = "page0")]
+    #[arg(long, value_enum)]
+    cover: CoverSource,
+    /// Copy and paste the URL of the cover image shown in the catalog or on the bookshelf,
+    /// used with --cover catalog.
+    #[arg(long, required_if_eq("cover", "catalog"))]
+    cover_url: Option<String>,
+    /// Also write a metadata.opf and a cover.jpg next to the output, so adding the
+    /// directory to Calibre imports the book with its title, authors and ISBN.
+    #[arg(long)]
+    calibre: bool,
+    /// Output file path, which can name the book like "{author} - {title} ({year}).{ext}"
+    /// with {title}, {author}, {year}, {isbn}, {edition}, {product_id} and {ext} replaced.
     #[clap(default_value = "out.pdf")]
     #[arg(short, long)]
     output_path: PathBuf,
+    /// The format of the document.
+    #[clap(default_value = "pdf")]
+    #[arg(long, value_enum)]
+    format: Format,
+    /// Also write the text of every page into a searchable SQLite index.
+    #[arg(short, long)]
+    index: Option<PathBuf>,
+    /// Also write the text of every page into this file, as Markdown with the TOC
+    /// entries as headings if its name ends in .md.
+    #[arg(long)]
+    export_text: Option<PathBuf>,
+    /// Divide the --export-text file into one file per chapter of the TOC, written
+    /// into a directory named like it without its extension.
+    #[arg(long, requires_all = ["export_text", "toc"])]
+    split_by: Option<SplitBy>,
+    /// Also write a Markdown vault for Obsidian into this directory, with a note per
+    /// TOC entry holding its text and figures and linking to its page in a copy of the
+    /// document.
+    #[arg(long, requires = "toc", conflicts_with_all = ["split_every", "split_size"])]
+    notes: Option<PathBuf>,
+    /// Also write the terms of the glossary of the book as Anki decks into this
+    /// directory, one tab separated file per chapter of the TOC, if there is one.
+    #[arg(long)]
+    anki: Option<PathBuf>,
+    /// Also write the web addresses of the videos and interactive content the pages
+    /// point to into this JSON file, and list them on pages at the end of the document.
+    #[arg(long)]
+    media_manifest: Option<PathBuf>,
+    /// Also export the numbered questions under the review questions or exercises
+    /// heading of every chapter, as JSON or, if the name ends in .pdf, as a document of
+    /// just the pages they are on.
+    #[arg(long)]
+    questions: Option<PathBuf>,
+    /// Download a random sample of pages again once the document is saved and check they
+    /// match the images it was built from, to catch corruption on flaky networks.
+    #[arg(long)]
+    verify: bool,
+    /// How many pages --verify downloads again.
+    #[clap(default_value = "10")]
+    #[arg(long)]
+    verify_sample: u32,
+    /// Check the text layer of the saved document against the annotation data.
+    #[arg(long)]
+    verify_text: bool,
+    /// Write the SHA-256 hashes of the document, of the images embedded in its pages and
+    /// of the images downloaded for them into this JSON file, to check the document
+    /// against later with the verify subcommand.
+    #[arg(long, conflicts_with_all = ["split_every", "split_size"])]
+    manifest: Option<PathBuf>,
+    /// Table of contents as a JSON array of { "title", "page", "children" } entries,
+    /// rendered as a linked contents page at the front of the document
+    /// and named destinations such as chapter.3.2.
+    #[arg(long)]
+    toc: Option<PathBuf>,
+    /// Link references such as "see Section 3.2" in the text to the page of the
+    /// TOC entry numbered alike.
+    #[arg(long, requires = "toc")]
+    link_references: bool,
+    /// Link the page numbers listed on the index pages at the back of the book to
+    /// those pages.
+    #[arg(long)]
+    index_links: bool,
+    /// Embed the raw annotation data and TOC as file attachments of the document.
+    #[arg(long)]
+    attach_sources: bool,
+    /// Put the page images and the text into separate layers, with the text drawn
+    /// under the images so it shows when the image layer is toggled off.
+    #[arg(long)]
+    layers: bool,
+    /// Draw the text layer in red over the page images instead of invisibly, to check
+    /// how it lines up with them.
+    #[arg(long, conflicts_with = "layers")]
+    text_visible: bool,
+    /// Generate a tagged structure tree for screen readers and other assistive technology.
+    #[arg(long)]
+    tagged: bool,
+    /// The language of the book as a BCP 47 tag such as en-US,
+    /// used by viewers and assistive technology. Arabic, Hebrew and other
+    /// right to left languages also set the reading direction. Taken from
+    /// the metadata of the book when it gives one.
+    #[arg(long)]
+    lang: Option<String>,
+    /// Embed an sRGB output intent and keep the ICC profile or gamma of the page images.
+    #[arg(long)]
+    color_management: bool,
+    /// How to compress the page images. jpeg typically shrinks the document
+    /// several times over with little visible loss on scanned text.
+    #[clap(default_value = "flate")]
+    #[arg(long)]
+    image_codec: ImageCodec,
+    /// JPEG quality from 1 to 100, used with --image-codec jpeg.
+    #[clap(default_value = "80")]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    jpeg_quality: u8,
+    /// Shrink the page images until the document fits in this size, such as 300MB,
+    /// by lowering the JPEG quality and then the resolution of each page as needed.
+    #[arg(long, value_parser = parse_size)]
+    max_size: Option<u64>,
+    /// Split the document into parts of this many pages,
+    /// written next to the output as out.part1.pdf, out.part2.pdf and so on.
+    #[arg(long)]
+    split_every: Option<u32>,
+    /// Split the document into parts of about this size, such as 500MB,
+    /// written next to the output as out.part1.pdf, out.part2.pdf and so on.
+    #[arg(long, value_parser = parse_size)]
+    split_size: Option<u64>,
+    /// Keep the pages held in memory under this size, such as 1G, for machines with little memory,
+    /// by downloading a page only once the one before is done and moving page images to a
+    /// temporary file.
+    #[arg(long, value_parser = parse_size)]
+    max_memory: Option<u64>,
+    /// Go easy on a Raspberry Pi or a small VPS: make one request at a time over a single
+    /// connection, keep the pages held in memory under 256MB unless --max-memory says otherwise,
+    /// and embed the page images as downloaded instead of recompressing them.
+    #[arg(long, conflicts_with_all = ["image_codec", "max_size"])]
+    low_resource: bool,
+    /// Draw a small header or footer on every page such as "{title} — p. {page}",
+    /// with {title} and {page} replaced by the title and page number.
+    #[arg(long)]
+    stamp: Option<String>,
+    /// Where to draw the --stamp.
+    #[clap(default_value = "footer")]
+    #[arg(long, value_enum)]
+    stamp_position: stamp::Position,
+    /// Export every figure with a "Figure X.Y" caption as a PNG into this directory,
+    /// along with a captions.csv index of them.
+    #[arg(long)]
+    figures: Option<PathBuf>,
+    /// Detect tables from the layout of the text and export each one as CSV
+    /// into this directory, named like page0012-table-1.csv.
+    #[arg(long)]
+    extract_tables: Option<PathBuf>,
+    /// Save every page image with boxes drawn around its text runs and characters into
+    /// this directory, named like page0012.png, to find where the text layer is off.
+    #[arg(long)]
+    debug_overlay: Option<PathBuf>,
+    /// Map the character codes of a book whose text comes out as gibberish, because its
+    /// fonts number their glyphs in codes of their own, with this ToUnicode CMap or JSON
+    /// object of codes to text such as {"0xE001": "fi"}. Without it, the character map the
+    /// player has for the book is used if there is one, and pages that stay gibberish are
+    /// recognized with OCR in builds with the ocr feature.
+    #[arg(long)]
+    cmap: Option<PathBuf>,
+    /// Store identical page images, such as repeated blank pages, only once.
+    #[arg(long)]
+    dedupe_images: bool,
+    /// Write a PDF 1.5 document with its page dictionaries and other small objects
+    /// in compressed object streams and a cross-reference stream, which makes long books
+    /// smaller.
+    #[arg(long)]
+    object_streams: bool,
+    /// Write a PDF 1.5 document with a compressed cross-reference stream in place of the
+    /// cross-reference table, but every object on its own, unlike --object-streams.
+    #[arg(long)]
+    xref_stream: bool,
+    /// The PDF version the document declares, by default the lowest one that has
+    /// everything it uses: 1.4, or 1.5 with --layers, --object-streams or --xref-stream.
+    /// Leave the last two out for the most compatible file, which old readers and print
+    /// shops may need.
+    #[arg(long, value_enum)]
+    pdf_version: Option<PdfVersion>,
+    /// Write the document linearized for fast web view, so a browser shows the first pages
+    /// of a document served from a course site while the rest is still downloading.
+    #[arg(
+        long,
+        conflicts_with_all = ["object_streams", "xref_stream", "split_every", "split_size"]
+    )]
+    linearize: bool,
+    /// The most requests to make at a time. Each page takes two, its image and its text.
+    #[arg(long)]
+    max_requests: Option<usize>,
+    /// The HTTP version to talk to plus.pearson.com with, try 1.1 on networks that break HTTP/2.
+    #[clap(default_value = "auto")]
+    #[arg(long, value_enum)]
+    http: HttpVersion,
+    /// How many idle connections to keep open for reuse, 0 closes each one after its request.
+    #[arg(long)]
+    pool_size: Option<usize>,
+    /// Seconds an idle connection is kept open for reuse.
+    #[arg(long)]
+    pool_idle_timeout: Option<u64>,
+    /// Seconds between TCP keepalive probes, for networks that drop quiet connections.
+    #[arg(long)]
+    tcp_keepalive: Option<u64>,
+    /// Trust the certificates in this PEM file, such as the one of a TLS-intercepting
+    /// proxy on a school or work network.
+    #[arg(long)]
+    cacert: Option<PathBuf>,
+    /// Accept any certificate plus.pearson.com presents. Anyone on the network can read
+    /// the session then, prefer --cacert.
+    #[arg(long)]
+    insecure: bool,
+    /// Archive every request and response in this WARC file, gzipped if it ends in .gz,
+    /// to reproduce a problem offline or render the book again later.
+    #[arg(long)]
+    record: Option<PathBuf>,
+    /// Answer every request with the responses archived in this --record file instead of
+    /// downloading anything, to render a recorded book again or reproduce a problem offline.
+    #[arg(long, conflicts_with_all = ["record", "keep_alive"])]
+    replay: Option<PathBuf>,
+    /// Keep the downloaded pages in this directory and only download the ones
+    /// that changed on the next run.
+    #[arg(long)]
+    cache: Option<PathBuf>,
+    /// Overwrite the output file if it already exists.
+    #[arg(long, conflicts_with = "backup")]
+    force: bool,
+    /// Keep an existing output file by renaming it to out.pdf.bak.
+    #[arg(long)]
+    backup: bool,
+    /// Copy and paste the URL of a request the web app repeats to keep the session alive,
+    /// such as its heartbeat, to request it during long downloads.
+    #[arg(long)]
+    keep_alive: Option<String>,
+    /// Seconds between --keep-alive requests.
+    #[clap(default_value = "300")]
+    #[arg(long)]
+    keep_alive_interval: u64,
+    /// Run this command after a successful extraction, such as "ocrmypdf {output} {output}",
+    /// with {output}, {title}, {author}, {isbn} and {product_id} replaced.
+    #[arg(long)]
+    post_cmd: Option<String>,
+    /// Email the finished document to this address, such as the Send to Kindle address
+    /// of a device, through the smtp server of config.json in the configuration directory.
+    #[arg(long)]
+    send_to: Option<String>,
+    /// Copy the finished document onto the e-reader mounted at this directory,
+    /// such as /Volumes/KOBO.
+    #[arg(long)]
+    send_to_device: Option<PathBuf>,
+    /// Upload the finished document to s3://bucket/prefix, to a WebDAV collection such as
+    /// https://cloud.example.com/remote.php/dav/files/me/Books, or copy it into a directory,
+    /// with the accounts of config.json in the configuration directory.
+    #[arg(long)]
+    upload: Option<String>,
+    /// Show a desktop notification when the extraction finishes or fails,
+    /// since a whole book takes over an hour.
+    #[arg(long)]
+    notify: bool,
+    /// Print the progress of the download as JSON lines, such as
+    /// {"event":"page_finished","page":3}, in place of the plain messages about it,
+    /// for a program running the extractor to follow.
+    #[arg(long)]
+    progress_json: bool,
+    /// Run this command while the document is built, such as "python3 hooks.py",
+    /// and ask it over its standard input and output whether to skip, stamp or rewrite
+    /// each page. The protocol is one line of JSON per hook: on_page_image, on_page_text
+    /// and on_finish.
+    #[arg(long)]
+    script: Option<String>,
+    /// Start even if the estimated size of the document exceeds the free disk space.
+    #[arg(long)]
+    no_space_check: bool,
+    /// Before downloading, measure the round trip time and throughput to plus.pearson.com
+    /// and recommend a --max-requests for it.
+    #[arg(long)]
+    probe: bool,
+}
+
+/// Reads every certificate in the PEM file at `path`.
+fn read_certificates(path: &Path) -> Result<Vec<Certificate>> {
+    let certificates = Certificate::from_pem_bundle(&fs::read(path)?)?;
+    if certificates.is_empty() {
+        bail!("there are no PEM certificates in it");
+    }
+    Ok(certificates)
+}
+
+/// Parses a size such as 300MB, 1.5GB or 512KiB into bytes.
+fn parse_size(size: &str) -> Result<u64, String> {
+    let split = size
+        .find(|char: char| !char.is_ascii_digit() && char != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number = number.parse::<f64>().map_err(|error| error.to_string())?;
+    let unit = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1000,
+        "M" | "MB" => 1_000_000,
+        "G" | "GB" => 1_000_000_000,
+        "KIB" => 1 << 10,
+        "MIB" => 1 << 20,
+        "GIB" => 1 << 30,
+        unit => return Err(format!("unknown unit {unit}")),
+    };
+    Ok((number * unit as f64) as u64)
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CoverSource {
+    /// Page 0 of the book.
+    Page0,
+    /// The cover art of the catalog, downloaded from --cover-url.
+    Catalog,
+    /// No cover, start with page 1.
+    None,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Pdf,
+    /// For older Kindles, converted from the PDF with ebook-convert from Calibre
+    /// and written next to it.
+    Azw3,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SplitBy {
+    /// One file per top level TOC entry.
+    Chapter,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PdfVersion {
+    #[value(name = "1.5")]
+    V1_5,
+    #[value(name = "1.7")]
+    V1_7,
+}
+
+impl PdfVersion {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::V1_5 => "1.5",
+            Self::V1_7 => "1.7",
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ImageCodec {
+    /// Lossless, the page images as downloaded.
+    Flate,
+    /// Lossy, DCT encoded.
+    Jpeg,
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
-    let args = Args::parse();
-    let extractor = Extractor::new(args.cookie, args.auth_token.unwrap_or_default()).unwrap();
-    let output = File::create(args.output_path).unwrap();
-    extractor
-        .run(args.product_id, args.uuid, output)
+    let mut args = std::env::args_os().skip(1);
+    match (args.next(), args.next()) {
+        (None, _) if io::stdin().is_terminal() => interactive::run(interactive::prompt),
+        // a HAR file dropped on the binary
+        (Some(path), None)
+            if Path::new(&path)
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("har")) =>
+        {
+            interactive::run(|| har::arguments(Path::new(&path)))
+        }
+        _ => {}
+    }
+    let cli = Cli::try_parse().unwrap_or_else(|error| {
+        let code = if error.use_stderr() { exit::USAGE } else { 0 };
+        let _ = error.print();
+        std::process::exit(code);
+    });
+    if cli.capabilities {
+        println!("{}", capabilities());
+        return;
+    }
+    match cli.command {
+        Some(Command::Search { index, query }) => {
+            let index =
+                Index::open(index).unwrap_or_else(|error| exit::fail_with(exit::USAGE, error));
+            let results = index
+                .search(&query)
+                .unwrap_or_else(|error| exit::fail(error));
+            for (page, snippet) in results {
+                println!("Page {:04}: {}", page, snippet.replace('\n', " "));
+            }
+        }
+        Some(Command::Stats {
+            index,
+            toc,
+            words_per_minute,
+        }) => {
+            let pages = Index::open(index)
+                .unwrap_or_else(|error| exit::fail_with(exit::USAGE, error))
+                .pages()
+                .unwrap_or_else(|error| exit::fail(error));
+            let mut toc = toc.map(|toc| {
+                Toc::load(toc).unwrap_or_else(|error| exit::fail_with(exit::USAGE, error))
+            });
+            if let Some(toc) = toc.as_mut().filter(|toc| toc.has_printed_pages()) {
+                let mut numbering = Numbering::default();
+                for (page, text) in &pages {
+                    numbering.add_page(*page, text);
+                }
+                for (title, page) in toc.resolve(&numbering) {
+                    println!(
+                        "Found no printed page numbers like the one of \"{}\", \
+                         taking it as page {:04}.",
+                        title, page
+                    );
+                }
+            }
+            let stats = stats::compute(&pages, toc.as_ref(), words_per_minute);
+            println!("Pages      Words  Minutes  Figures  Title");
+            for chapter in &stats {
+                println!(
+                    "{:04}-{:04} {:>6} {:>8.1} {:>8}  {}",
+                    chapter.pages.0,
+                    chapter.pages.1,
+                    chapter.words,
+                    chapter.minutes,
+                    chapter.figures,
+                    chapter.title
+                );
+            }
+            let words = stats.iter().map(|chapter| chapter.words).sum::<usize>();
+            let figures = stats.iter().map(|chapter| chapter.figures).sum::<usize>();
+            println!(
+                "Total     {:>6} {:>8.1} {:>8}",
+                words,
+                words as f32 / words_per_minute,
+                figures
+            );
+        }
+        Some(Command::Serve {
+            queue,
+            concurrency,
+            max_requests,
+            listen,
+            api_token,
+            webhook,
+        }) => {
+            let jobs = Arc::new(metrics::Jobs::default());
+            if let Some(addr) = listen {
+                let token = api_token.unwrap_or_else(|| {
+                    let token = http::random_token();
+                    println!("Authenticate with the bearer token {}.", token);
+                    token
+                });
+                api::listen(addr, queue.clone(), jobs.clone(), token)
+                    .unwrap_or_else(|error| exit::fail_with(exit::USAGE, error));
+            }
+            let config = Config::load().unwrap_or_else(|error| exit::fail_with(exit::USAGE, error));
+            let email = match (config.notify, config.smtp) {
+                (Some(notify), Some(smtp)) => Some((smtp, notify)),
+                (Some(_), None) => {
+                    eprintln!("Add an smtp server to config.json to email the job reports.");
+                    std::process::exit(exit::USAGE);
+                }
+                (None, _) => None,
+            };
+            let targets = Arc::new(notify::Targets { webhook, email });
+            queue::serve(&queue, concurrency, max_requests, jobs, targets)
+                .unwrap_or_else(|error| exit::fail(error));
+        }
+        Some(Command::Batch { input }) => {
+            let failed = batch::run(&input).unwrap_or_else(|error| exit::fail(error));
+            if failed > 0 {
+                eprintln!("{} of the jobs failed.", failed);
+                std::process::exit(exit::FAILURE);
+            }
+        }
+        Some(Command::Verify {
+            document,
+            manifest,
+            cache,
+            replay,
+        }) => {
+            let cache = cache.map(Cache::new);
+            let replay = replay.map(|replay| {
+                Replay::load(&replay).unwrap_or_else(|error| {
+                    eprintln!("Couldn't read {}: {}", replay.display(), error);
+                    std::process::exit(exit::USAGE);
+                })
+            });
+            let from_cache = |asset: &str| cache.as_ref()?.load(asset);
+            let from_replay = |asset: &str| {
+                let url = format!("https://plus.pearson.com/eplayer/pdfassets/prod1/{asset}");
+                replay.as_ref()?.body(&url)
+            };
+            let source: Option<manifest::Source> = match (&cache, &replay) {
+                (Some(_), _) => Some(&from_cache),
+                (_, Some(_)) => Some(&from_replay),
+                _ => None,
+            };
+            let mismatches = manifest::verify(&document, &manifest, source)
+                .unwrap_or_else(|error| exit::fail_with(exit::USAGE, error));
+            for mismatch in &mismatches {
+                println!("{}", mismatch);
+            }
+            if !mismatches.is_empty() {
+                eprintln!("{} doesn't match the manifest.", document.display());
+                std::process::exit(exit::FAILURE);
+            }
+            println!("{} matches the manifest.", document.display());
+        }
+        Some(Command::Login { listen, profile }) => {
+            let nonce = http::random_token();
+            println!(
+                "Waiting on http://{}/{} for the session headers.",
+                listen, nonce
+            );
+            println!(
+                "Run this bookmarklet on plus.pearson.com and paste the Cookie header of a \
+                 request of the web app from the network tab of the developer tools, or POST the \
+                 headers from a browser extension:"
+            );
+            println!(
+                "javascript:fetch('http://{}/{}',{{method:'POST',\
+                 body:JSON.stringify({{cookie:prompt('Cookie header')}})}})",
+                listen, nonce
+            );
+            let credentials =
+                credentials::receive(listen, &nonce).unwrap_or_else(|error| exit::fail(error));
+            let path = credentials
+                .save(profile.as_deref())
+                .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+            println!("Saved the session to {}.", path.display());
+        }
+        Some(Command::Merge {
+            output_path,
+            force,
+            backup,
+            inputs,
+        }) => {
+            let output_path = filename::long_path(&output_path);
+            check_overwrite(&output_path, force, backup);
+            let mut document = merge::merge(&inputs).unwrap_or_else(|error| exit::fail(error));
+            let temporary_path = temporary_path(&output_path);
+            let file = document
+                .save(&temporary_path)
+                .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+            file.sync_all()
+                .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+            if backup {
+                back_up(&output_path).unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+            }
+            rename_synced(&temporary_path, &output_path)
+                .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+        }
+        Some(Command::Completions { shell }) => {
+            print!("{}", completions::generate(shell, &Cli::command()));
+        }
+        Some(Command::SelfUpdate { check }) => {
+            let update = update::self_update(check)
+                .await
+                .unwrap_or_else(|error| exit::fail(error));
+            match update {
+                Some(version) if check => println!("Version {} is out.", version),
+                Some(version) => println!("Updated to version {}.", version),
+                None => println!("Version {} is the latest.", env!("CARGO_PKG_VERSION")),
+            }
+        }
+        None => extract(cli.args.unwrap()).await,
+    }
+}
+
+/// Describes what this build supports as JSON.
+fn capabilities() -> String {
+    fn values<T: ValueEnum>() -> Vec<String> {
+        T::value_variants()
+            .iter()
+            .filter_map(|value| Some(value.to_possible_value()?.get_name().to_string()))
+            .collect()
+    }
+    let subcommands = Cli::command()
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect::<Vec<_>>();
+    let capabilities = sonic_rs::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "platform": { "os": std::env::consts::OS, "arch": std::env::consts::ARCH },
+        "formats": values::<Format>(),
+        "image_codecs": values::<ImageCodec>(),
+        "http_versions": values::<HttpVersion>(),
+        "covers": values::<CoverSource>(),
+        "stamp_positions": values::<stamp::Position>(),
+        "completions": values::<completions::Shell>(),
+        "subcommands": subcommands,
+        "features": { "ocr": cfg!(feature = "ocr") },
+        "exit_codes": {
+            "failure": exit::FAILURE,
+            "auth": exit::AUTH,
+            "network": exit::NETWORK,
+            "partial": exit::PARTIAL,
+            "output": exit::OUTPUT,
+            "usage": exit::USAGE,
+        },
+    });
+    sonic_rs::to_string(&capabilities).unwrap()
+}
+
+/// Returns the path a document is written to before it is renamed to `path`, such as
+/// `out.pdf.tmp`.
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    temporary_path.into()
+}
+
+/// Renames the written and synced `temporary_path` to `path`, syncing the directory on Unix too so
+/// that the rename outlives a crash.
+fn rename_synced(temporary_path: &Path, path: &Path) -> io::Result<()> {
+    fs::rename(temporary_path, path)?;
+    #[cfg(unix)]
+    File::open(output_dir(path))?.sync_all()?;
+    Ok(())
+}
+
+/// Picks up to `count` of the pages `0..pages` at random.
+fn sample_pages(pages: u32, count: u32) -> Vec<u32> {
+    let mut all = (0..pages).collect::<Vec<_>>();
+    let random = SystemRandom::new();
+    let count = count.min(pages) as usize;
+    for i in 0..count {
+        let mut bytes = [0; 4];
+        random.fill(&mut bytes).unwrap();
+        let j = i + u32::from_le_bytes(bytes) as usize % (all.len() - i);
+        all.swap(i, j);
+    }
+    all.truncate(count);
+    all.sort_unstable();
+    all
+}
+
+/// Returns the directory `path` is in.
+fn output_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+async fn extract(mut args: Args) {
+    let start = Instant::now();
+    let (cookie, auth_token) = match args.cookie {
+        Some(cookie) => (cookie, args.auth_token),
+        None => match Credentials::load(args.profile.as_deref())
+            .unwrap_or_else(|error| exit::fail_with(exit::USAGE, error))
+        {
+            Some(saved) => (saved.cookie, args.auth_token.or(saved.auth_token)),
+            // a replay doesn't need a session
+            None if args.replay.is_some() => (String::new(), args.auth_token),
+            None if args.profile.is_some() => {
+                eprintln!("No session is saved under that profile, save one with login --profile.");
+                std::process::exit(exit::AUTH);
+            }
+            None => {
+                eprintln!("Pass --cookie or save a session with the login command first.");
+                std::process::exit(exit::AUTH);
+            }
+        },
+    };
+    if args.low_resource {
+        // the runtime already runs on a single thread
+        args.max_requests.get_or_insert(1);
+        args.pool_size.get_or_insert(1);
+        args.max_memory.get_or_insert(LOW_RESOURCE_MEMORY);
+    }
+    let config = Config::load().unwrap_or_else(|error| exit::fail_with(exit::USAGE, error));
+    if args.send_to.is_some() && config.smtp.is_none() {
+        eprintln!("Add an smtp server to config.json to use --send-to.");
+        std::process::exit(exit::USAGE);
+    }
+    let storage = args.upload.as_ref().map(|destination| {
+        storage::open(destination, &config).unwrap_or_else(|error| {
+            eprintln!("Error: {:#}", error);
+            std::process::exit(exit::USAGE);
+        })
+    });
+    let auth_token = auth_token
+        .or_else(|| {
+            let (name, auth_token) = credentials::derive_auth_token(&cookie)?;
+            println!("Using the {} cookie as the auth token.", name);
+            Some(auth_token.to_string())
+        })
+        .unwrap_or_default();
+    redact::install_panic_hook(redact::secrets(&cookie, &auth_token));
+    let connection = Connection {
+        http: args.http,
+        pool_size: args.pool_size,
+        pool_idle_timeout: args.pool_idle_timeout.map(Duration::from_secs),
+        tcp_keepalive: args.tcp_keepalive.map(Duration::from_secs),
+        ca_certs: match &args.cacert {
+            Some(cacert) => read_certificates(cacert).unwrap_or_else(|error| {
+                eprintln!(
+                    "Couldn't read the certificates in {}: {}",
+                    cacert.display(),
+                    error
+                );
+                std::process::exit(exit::USAGE);
+            }),
+            None => Vec::new(),
+        },
+        insecure: args.insecure,
+    };
+    let mut extractor = Extractor::new(cookie, auth_token)
+        .and_then(|extractor| extractor.with_connection(connection))
+        .unwrap_or_else(|error| exit::fail(error));
+    if let Some(profile) = args.profile {
+        extractor = extractor.with_profile(profile);
+    }
+    if let Some(cache) = args.cache {
+        extractor = extractor.with_cache(Cache::new(cache));
+    }
+    if let Some(max_requests) = args.max_requests {
+        extractor = extractor.with_max_requests(max_requests);
+    }
+    if let Some(path) = std::env::var_os(metrics::ENV_VAR) {
+        extractor = extractor.with_counters(Counters::new(Some(path.into())));
+    }
+    if args.progress_json {
+        extractor = extractor.with_progress(|event| println!("{}", progress::to_json(event)));
+    }
+    if let Some(record) = &args.record {
+        let recorder =
+            Recorder::create(record).unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+        extractor = extractor.with_recorder(recorder);
+    }
+    if let Some(replay) = &args.replay {
+        let replay = Replay::load(replay).unwrap_or_else(|error| {
+            eprintln!("Couldn't read {}: {}", replay.display(), error);
+            std::process::exit(exit::USAGE);
+        });
+        extractor = extractor.with_replay(replay);
+    }
+    if let Some(script) = &args.script {
+        let script =
+            Script::spawn(script).unwrap_or_else(|error| exit::fail_with(exit::USAGE, error));
+        extractor = extractor.with_script(script);
+    }
+    if let Some(url) = args.keep_alive {
+        extractor.keep_alive(url, Duration::from_secs(args.keep_alive_interval));
+    }
+    // reading the front matter takes a few requests that only a name with the edition needs
+    let named_by_edition = args.output_path.to_string_lossy().contains("{edition}");
+    if args.edition.is_none() && (args.detect_edition || named_by_edition) {
+        let front_matter = extractor
+            .front_matter(args.product_id, &args.uuid, FRONT_MATTER_PAGES)
+            .await
+            .unwrap_or_else(|error| exit::fail(error));
+        if let Some(edition) = edition::detect(&front_matter) {
+            println!("Detected the {} edition.", edition::ordinal(edition));
+            args.edition = Some(edition as f32);
+        }
+    }
+    // editions of the same book shouldn't overwrite each other under the default name
+    if let Some(edition) = args.edition.filter(|edition| edition.fract() == 0.0) {
+        if args.output_path == Path::new("out.pdf") {
+            let name = format!("out ({} edition).pdf", edition::ordinal(edition as u32));
+            args.output_path = PathBuf::from(name);
+        }
+    }
+    let placeholders = [
+        ("title", args.title.clone().unwrap_or_default()),
+        ("author", args.author.join(", ")),
+        (
+            "year",
+            args.year.map(|year| year.to_string()).unwrap_or_default(),
+        ),
+        ("isbn", args.isbn.clone().unwrap_or_default()),
+        (
+            "edition",
+            args.edition
+                .map(|edition| edition.to_string())
+                .unwrap_or_default(),
+        ),
+        ("product_id", args.product_id.to_string()),
+        ("ext", "pdf".to_string()),
+    ];
+    let placeholders = placeholders
+        .each_ref()
+        .map(|(key, value)| (*key, value.as_str()));
+    args.output_path = filename::expand(&args.output_path, &placeholders);
+    args.output_path = filename::long_path(&args.output_path);
+    check_overwrite(&args.output_path, args.force, args.backup);
+    let splits = args.split_every.is_some() || args.split_size.is_some();
+    if splits
+        && args
+            .questions
+            .as_ref()
+            .is_some_and(|path| path.extension().is_some_and(|extension| extension == "pdf"))
+    {
+        eprintln!("--questions can't write a document of the questions from a split one.");
+        std::process::exit(exit::USAGE);
+    }
+    if args.probe {
+        let report = extractor
+            .probe(args.product_id, &args.uuid)
+            .await
+            .unwrap_or_else(|error| exit::fail(error));
+        println!("{}", report);
+    }
+    if !args.no_space_check {
+        // a document capped to fit needs no probing
+        let free = disk::free_space(output_dir(&args.output_path))
+            .filter(|&free| args.max_size.is_none_or(|max_size| max_size > free));
+        if let Some(free) = free {
+            let estimate = extractor
+                .exceeds(args.product_id, &args.uuid, free)
+                .await
+                .unwrap_or_else(|error| exit::fail(error));
+            if let Some(estimate) = estimate {
+                let estimate = args.max_size.map_or(estimate, |max| estimate.min(max));
+                eprintln!(
+                    "The document needs about {} MB but only {} MB are free, \
+                     pass --no-space-check to start anyway.",
+                    estimate / 1_000_000,
+                    free / 1_000_000
+                );
+                std::process::exit(exit::OUTPUT);
+            }
+        }
+    }
+    let mut options = Options {
+        metadata: calibre::Metadata {
+            title: args.title,
+            authors: args.author,
+            isbn: args.isbn,
+            series: args.series,
+            edition: args.edition,
+            year: args.year,
+        },
+        cover: match args.cover {
+            CoverSource::Page0 => Cover::Page,
+            CoverSource::Catalog => Cover::Image(args.cover_url.unwrap()),
+            CoverSource::None => Cover::None,
+        },
+        toc: args
+            .toc
+            .map(|toc| Toc::load(toc).unwrap_or_else(|error| exit::fail_with(exit::USAGE, error))),
+        link_references: args.link_references,
+        index_links: args.index_links,
+        media_links: args.media_manifest.is_some(),
+        attach_sources: args.attach_sources,
+        layers: args.layers,
+        text_visible: args.text_visible,
+        tagged: args.tagged,
+        lang: args.lang.clone(),
+        color_management: args.color_management,
+        image_encoding: match args.image_codec {
+            ImageCodec::Flate => raster::Encoding::Lossless,
+            ImageCodec::Jpeg => raster::Encoding::Jpeg {
+                quality: args.jpeg_quality,
+            },
+        },
+        max_size: args.max_size,
+        max_memory: args.max_memory,
+        stamp: args.stamp.map(|template| Stamp {
+            template,
+            position: args.stamp_position,
+        }),
+        // the notes embed the figures, exported into the vault unless they go elsewhere
+        figures: args
+            .figures
+            .or_else(|| args.notes.as_ref().map(|notes| notes.join("figures"))),
+        extract_tables: args.extract_tables,
+        debug_overlay: args.debug_overlay,
+        cmap: args.cmap.map(|path| {
+            glyphs::Cmap::load(path).unwrap_or_else(|error| exit::fail_with(exit::USAGE, error))
+        }),
+        dedupe_images: args.dedupe_images,
+        packing: match (args.object_streams, args.xref_stream) {
+            (true, _) => Packing::ObjectStreams,
+            (false, true) => Packing::XrefStream,
+            (false, false) => Packing::Table,
+        },
+        pdf_version: match args.pdf_version {
+            Some(version) => version.as_str(),
+            // marked content, output intents and the language of the document are from 1.4,
+            // optional content and cross-reference streams from 1.5
+            None if args.object_streams || args.xref_stream || args.layers => "1.5",
+            None => "1.4",
+        },
+        linearize: args.linearize,
+    };
+    // a failed run leaves any previous document in place rather than a truncated one
+    let temporary_path = temporary_path(&args.output_path);
+    // a template such as {author}/{title}.pdf can name directories that don't exist yet
+    fs::create_dir_all(output_dir(&args.output_path))
+        .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+    let output =
+        File::create(&temporary_path).unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+    // Ctrl-C finishes the document with the pages downloaded so far
+    let cancel = CancelToken::new();
+    cancel.cancel_on_interrupt();
+    let extraction = extractor
+        .run_with_cancel(args.product_id, &args.uuid, &mut options, &output, &cancel)
         .await
-        .unwrap();
+        .unwrap_or_else(|error| {
+            let _ = fs::remove_file(&temporary_path);
+            extractor.counters().save();
+            if args.notify {
+                let message = redact::redact_session(&format!("{:#}", error));
+                notify_desktop("The extraction failed", &message);
+            }
+            exit::fail(error)
+        });
+    extractor.counters().save();
+    output
+        .sync_all()
+        .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+    if args.backup {
+        back_up(&args.output_path).unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+    }
+    rename_synced(&temporary_path, &args.output_path)
+        .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+    let mut partial =
+        !extraction.garbled_pages.is_empty() || extraction.cancelled || extraction.expired;
+    for (page, garbled, total) in &extraction.garbled_pages {
+        println!(
+            "Page {:04} has a degraded text layer: {} of {} characters are garbled.",
+            page, garbled, total
+        );
+    }
+    if let Some(max_size) = args.max_size {
+        let size = fs::metadata(&args.output_path)
+            .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error))
+            .len();
+        if size > max_size {
+            partial = true;
+            println!(
+                "The document is {} bytes, over the limit of {}.",
+                size, max_size
+            );
+        }
+    }
+    // the parts take the place of the document for everything after
+    let documents = if splits {
+        println!("Splitting the document.");
+        let parts = split::split(
+            &args.output_path,
+            args.split_every,
+            args.split_size,
+            |parts| {
+                for part in parts {
+                    check_overwrite(part, args.force, args.backup);
+                    if args.backup {
+                        back_up(part)?;
+                    }
+                }
+                Ok(())
+            },
+        )
+        .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+        fs::remove_file(&args.output_path)
+            .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+        for part in &parts {
+            println!("Wrote {}.", part.display());
+        }
+        parts
+    } else {
+        vec![args.output_path.clone()]
+    };
+    if args.calibre {
+        calibre::write_sidecar(
+            output_dir(&args.output_path),
+            &options.metadata,
+            options.lang.as_deref(),
+            args.product_id,
+            &extraction.cover,
+        )
+        .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+    }
+    if let Some(index) = args.index {
+        println!("Writing the search index.");
+        Index::open(index)
+            .and_then(|mut index| index.write(&extraction.page_texts))
+            .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+    }
+    if let Some(path) = &args.export_text {
+        println!("Exporting the text.");
+        let paths = match args.split_by {
+            Some(SplitBy::Chapter) => {
+                export::write_chapters(path, &extraction, options.toc.as_ref().unwrap())
+            }
+            None => {
+                export::write_text(path, &extraction, options.toc.as_ref()).map(|path| vec![path])
+            }
+        }
+        .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+        for path in paths {
+            println!("Wrote {}.", path.display());
+        }
+    }
+    if let Some(dir) = &args.notes {
+        println!("Writing the study notes.");
+        let notes = notes::write_vault(
+            dir,
+            &args.output_path,
+            &extraction,
+            options.toc.as_ref().unwrap(),
+            options.figures.as_ref().unwrap(),
+        )
+        .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+        println!("Wrote {} notes into {}.", notes, dir.display());
+    }
+    if let Some(dir) = &args.anki {
+        println!("Writing the Anki decks.");
+        let (decks, cards) = anki::write_decks(dir, &extraction, options.toc.as_ref())
+            .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+        for deck in decks {
+            println!("Wrote {}.", deck.display());
+        }
+        println!("Found {} glossary terms.", cards);
+    }
+    if let Some(path) = &args.media_manifest {
+        let links = media::find(&extraction.page_texts, &extraction.removed_pages);
+        media::write_manifest(path, &links)
+            .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+        println!("Listed {} media links.", links.len());
+    }
+    if let Some(path) = &args.questions {
+        let (questions, pages) = questions::find(&extraction, options.toc.as_ref());
+        if path.extension().is_some_and(|extension| extension == "pdf") {
+            questions::write_pdf(path, &args.output_path, &extraction, &pages)
+        } else {
+            questions::write_json(path, &questions)
+        }
+        .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+        println!(
+            "Exported {} questions from {} pages.",
+            questions.len(),
+            pages.len()
+        );
+    }
+    if args.verify {
+        let pages = sample_pages(extraction.page_digests.len() as u32, args.verify_sample);
+        println!("Downloading {} sampled pages again.", pages.len());
+        let differing = extractor
+            .verify_pages(
+                args.product_id,
+                &args.uuid,
+                &pages,
+                &extraction.page_digests,
+            )
+            .await
+            .unwrap_or_else(|error| exit::fail(error));
+        for page in &differing {
+            println!(
+                "Page {:04} came back different from the image in the document.",
+                page
+            );
+        }
+        println!(
+            "{} of {} sampled pages differ.",
+            differing.len(),
+            pages.len()
+        );
+        partial |= !differing.is_empty();
+    }
+    if args.verify_text {
+        println!("Verifying the text layer.");
+        let damaged =
+            verify::verify_text(&documents, &extraction).unwrap_or_else(|error| exit::fail(error));
+        for (page, lost) in &damaged {
+            let lost = lost.iter().collect::<String>();
+            println!(
+                "Page {:04} lost {} characters: {}",
+                page,
+                lost.chars().count(),
+                lost
+            );
+        }
+        println!("{} pages have a damaged text layer.", damaged.len());
+        partial |= !damaged.is_empty();
+    }
+    if let Some(path) = &args.manifest {
+        let pages = extraction
+            .page_digests
+            .iter()
+            .enumerate()
+            .filter_map(|(page, digest)| {
+                Some(manifest::Page {
+                    page: page as u32,
+                    pdf_page: extraction.pdf_page(page as u32)?,
+                    digest,
+                })
+            })
+            .collect::<Vec<_>>();
+        let pages = manifest::write(path, &args.output_path, &pages, args.product_id, &args.uuid)
+            .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+        println!(
+            "Listed the hashes of {} pages in {}.",
+            pages,
+            path.display()
+        );
+    }
+    if let Some(post_cmd) = &args.post_cmd {
+        for document in &documents {
+            let output = document.to_string_lossy();
+            let metadata = &options.metadata;
+            let placeholders = [
+                ("output", output.as_ref()),
+                ("title", metadata.title.as_deref().unwrap_or_default()),
+                ("author", &metadata.authors.join(", ")),
+                ("isbn", metadata.isbn.as_deref().unwrap_or_default()),
+                ("product_id", &args.product_id.to_string()),
+            ];
+            let status = hook::run(post_cmd, &placeholders)
+                .unwrap_or_else(|error| exit::fail_with(exit::USAGE, error));
+            if !status.success() {
+                eprintln!("The --post-cmd exited with {}.", status);
+                std::process::exit(exit::FAILURE);
+            }
+        }
+    }
+    let documents = match args.format {
+        Format::Pdf => documents,
+        Format::Azw3 => {
+            println!("Converting the document to AZW3.");
+            documents
+                .iter()
+                .map(|document| convert::azw3(document, &options.metadata))
+                .collect::<Result<Vec<_>>>()
+                .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error))
+        }
+    };
+    if let Some(to) = &args.send_to {
+        println!("Sending the document to {}.", to);
+        let smtp = config.smtp.as_ref().unwrap();
+        for document in &documents {
+            let name = document.file_name().unwrap().to_string_lossy();
+            let content =
+                fs::read(document).unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+            let message = smtp::Message {
+                to,
+                subject: options.metadata.title.as_deref().unwrap_or(&name),
+                body: "",
+                attachment: Some((&name, &content)),
+            };
+            smtp::send(smtp, &message)
+                .unwrap_or_else(|error| exit::fail_with(exit::NETWORK, error));
+        }
+    }
+    if let Some(device) = &args.send_to_device {
+        for document in &documents {
+            let copy = deliver::copy_to_device(document, device)
+                .unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+            println!("Copied the document to {}.", copy.display());
+        }
+    }
+    if let Some(storage) = &storage {
+        for document in &documents {
+            let name = document.file_name().unwrap().to_string_lossy();
+            let content =
+                fs::read(document).unwrap_or_else(|error| exit::fail_with(exit::OUTPUT, error));
+            let location = storage
+                .store(&name, content)
+                .await
+                .unwrap_or_else(|error| exit::fail_with(exit::NETWORK, error));
+            println!("Uploaded the document to {}.", location);
+        }
+    }
+    if args.notify {
+        let name = args.output_path.file_name().unwrap().to_string_lossy();
+        let pages = extraction.page_texts.len();
+        let minutes = start.elapsed().as_secs() / 60;
+        let body = match partial {
+            true => format!("{pages} pages in {minutes} minutes, some of them damaged."),
+            false => format!("{pages} pages in {minutes} minutes."),
+        };
+        notify_desktop(&format!("Extracted {}", name), &body);
+    }
+    if partial {
+        std::process::exit(exit::PARTIAL);
+    }
+}
+
+/// Exits if `path` already exists, unless `--force` or `--backup` lets it be replaced.
+fn check_overwrite(path: &Path, force: bool, backup: bool) {
+    if path.exists() && !force && !backup {
+        eprintln!(
+            "{} already exists, pass --force to overwrite it or --backup to keep a copy.",
+            path.display()
+        );
+        std::process::exit(exit::OUTPUT);
+    }
+}
+
+/// Moves `path` aside to `path.bak` if it exists, for `--backup`.
+fn back_up(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        let mut backup_path = path.as_os_str().to_owned();
+        backup_path.push(".bak");
+        fs::rename(path, backup_path)?;
+    }
+    Ok(())
+}
+
+/// Shows a desktop notification, which is only a courtesy, so failing to is merely printed.
+fn notify_desktop(title: &str, body: &str) {
+    if let Err(error) = desktop::notify(title, body) {
+        println!("Couldn't show a desktop notification: {}", error);
+    }
 }
@@ -0,0 +1,185 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use lopdf::{Document, Object, ObjectId};
+use ring::digest::{self, Context};
+use sonic_rs::{Deserialize, Serialize};
+
+use crate::hex;
+
+/// The SHA-256 hashes of a document and of every image embedded in it, along with the hashes of
+/// the downloaded images they were built from.
+#[derive(Deserialize, Serialize)]
+pub struct Manifest {
+    product_id: u32,
+    uuid: String,
+    /// The hash of the whole document.
+    document: String,
+    pages: Vec<Entry>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Entry {
+    /// The page of the book.
+    page: u32,
+    /// The page of the document showing it.
+    pdf_page: u32,
+    /// The hash of the image downloaded for the page, the asset under
+    /// `{product_id}/{uuid}/pages/page{page}`.
+    asset: String,
+    /// The hash of the image streams of the page of the document, in the order of their names.
+    embedded: String,
+}
+
+/// A page of the book shown in the document.
+pub struct Page<'a> {
+    pub page: u32,
+    /// The page of the document showing it.
+    pub pdf_page: u32,
+    /// The SHA-256 of the image downloaded for it.
+    pub digest: &'a [u8],
+}
+
+/// Returns the image downloaded for an asset such as `{product_id}/{uuid}/pages/page12`, for
+/// `verify` to hash again, if it was kept.
+pub type Source<'a> = &'a dyn Fn(&str) -> Option<Vec<u8>>;
+
+/// Hashes the document at `document_path` and the images embedded in its `pages`, and writes
+/// them to `path` as JSON along with the hashes of the downloaded images. Returns how many pages
+/// it lists.
+pub fn write(
+    path: &Path,
+    document_path: &Path,
+    pages: &[Page],
+    product_id: u32,
+    uuid: &str,
+) -> Result<usize> {
+    let bytes = fs::read(document_path)?;
+    let document = Document::load_mem(&bytes)?;
+    let ids = document.get_pages();
+    let mut entries = Vec::new();
+    for page in pages {
+        let id = *ids
+            .get(&page.pdf_page)
+            .ok_or_else(|| anyhow!("the document has no page {}", page.pdf_page))?;
+        entries.push(Entry {
+            page: page.page,
+            pdf_page: page.pdf_page,
+            asset: hex(page.digest),
+            embedded: embedded_digest(&document, id)?,
+        });
+    }
+    let manifest = Manifest {
+        product_id,
+        uuid: uuid.to_string(),
+        document: hex(digest::digest(&digest::SHA256, &bytes).as_ref()),
+        pages: entries,
+    };
+    fs::write(path, sonic_rs::to_string(&manifest)?)?;
+    Ok(manifest.pages.len())
+}
+
+/// Checks the document at `document_path` against the manifest at `manifest_path`, and the
+/// downloaded images in `source` against the hashes they had when the document was built.
+/// Returns a description of every mismatch, empty if the document is intact.
+pub fn verify(
+    document_path: &Path,
+    manifest_path: &Path,
+    source: Option<Source>,
+) -> Result<Vec<String>> {
+    let manifest = sonic_rs::from_str::<Manifest>(&fs::read_to_string(manifest_path)?)?;
+    let bytes = fs::read(document_path)?;
+    let mut mismatches = Vec::new();
+    if hex(digest::digest(&digest::SHA256, &bytes).as_ref()) == manifest.document {
+        // every image in it is then as it was
+        return verify_sources(&manifest, source);
+    }
+    mismatches.push("The document differs from the one the manifest was written for.".into());
+    let document = Document::load_mem(&bytes)?;
+    let pages = document.get_pages();
+    for entry in &manifest.pages {
+        let embedded = match pages.get(&entry.pdf_page) {
+            Some(&id) => embedded_digest(&document, id)?,
+            None => {
+                mismatches.push(format!(
+                    "Page {:04} is missing from the document.",
+                    entry.page
+                ));
+                continue;
+            }
+        };
+        if embedded != entry.embedded {
+            mismatches.push(format!(
+                "The image of page {:04} on page {} of the document was altered.",
+                entry.page, entry.pdf_page
+            ));
+        }
+    }
+    mismatches.extend(verify_sources(&manifest, source)?);
+    Ok(mismatches)
+}
+
+/// Hashes the downloaded images of every page listed in `manifest` again.
+fn verify_sources(manifest: &Manifest, source: Option<Source>) -> Result<Vec<String>> {
+    let Some(source) = source else {
+        return Ok(Vec::new());
+    };
+    let mut mismatches = Vec::new();
+    for entry in &manifest.pages {
+        let asset = format!(
+            "{}/{}/pages/page{}",
+            manifest.product_id, manifest.uuid, entry.page
+        );
+        match source(&asset) {
+            Some(image) => {
+                if hex(digest::digest(&digest::SHA256, &image).as_ref()) != entry.asset {
+                    mismatches.push(format!(
+                        "The downloaded image of page {:04} differs from the one in the document.",
+                        entry.page
+                    ));
+                }
+            }
+            None => mismatches.push(format!(
+                "The downloaded image of page {:04} is missing.",
+                entry.page
+            )),
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Hashes the contents of the image XObjects of the page `id`, as they are stored, in the order
+/// of their names.
+fn embedded_digest(document: &Document, id: ObjectId) -> Result<String> {
+    let page = document.get_dictionary(id)?;
+    let mut context = Context::new(&digest::SHA256);
+    let resources = match page.get(b"Resources") {
+        Ok(Object::Reference(id)) => document.get_dictionary(*id)?,
+        Ok(resources) => resources.as_dict()?,
+        Err(_) => return Ok(hex(context.finish().as_ref())),
+    };
+    let xobjects = match resources.get(b"XObject") {
+        Ok(Object::Reference(id)) => document.get_dictionary(*id)?,
+        Ok(xobjects) => xobjects.as_dict()?,
+        Err(_) => return Ok(hex(context.finish().as_ref())),
+    };
+    let mut names = xobjects.iter().collect::<Vec<_>>();
+    names.sort_by(|a, b| a.0.cmp(b.0));
+    for (_, xobject) in names {
+        let Ok(stream) = document
+            .dereference(xobject)
+            .and_then(|(_, xobject)| xobject.as_stream())
+        else {
+            continue;
+        };
+        if stream
+            .dict
+            .get(b"Subtype")
+            .and_then(Object::as_name)
+            .is_ok_and(|subtype| subtype == b"Image")
+        {
+            context.update(&stream.content);
+        }
+    }
+    Ok(hex(context.finish().as_ref()))
+}
@@ -0,0 +1,158 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use lopdf::{
+    content::{Content, Operation},
+    dictionary, Document, Object, Stream,
+};
+use sonic_rs::Serialize;
+
+use crate::toc::write_line;
+
+const MARGIN: f32 = 54.0;
+const FONT_SIZE: f32 = 10.0;
+const LINE_HEIGHT: f32 = 14.0;
+
+/// A video, widget or other online resource a page points to.
+#[derive(Serialize)]
+pub struct MediaLink {
+    pub page: u32,
+    pub title: String,
+    pub url: String,
+}
+
+/// Finds the web addresses printed in the text of every page, along with the rest of the line
+/// they are on as their title, skipping the pages left out of the document.
+pub fn find(page_texts: &[String], removed_pages: &[u32]) -> Vec<MediaLink> {
+    let mut links = Vec::new();
+    for (page, text) in page_texts.iter().enumerate() {
+        let page = page as u32;
+        if removed_pages.contains(&page) {
+            continue;
+        }
+        for line in text.lines() {
+            for word in line.split_whitespace() {
+                let word = word.trim_start_matches(['(', '<', '"', '“']);
+                let word = word.trim_end_matches(['.', ',', ';', ':', ')', '>', '"', '”']);
+                let url = if word.starts_with("https://") || word.starts_with("http://") {
+                    word.to_string()
+                } else if word.starts_with("www.") && word.len() > 4 {
+                    format!("https://{word}")
+                } else {
+                    continue;
+                };
+                let title = line.replace(word, "");
+                let title = title.trim_matches(|char: char| {
+                    char.is_whitespace() || matches!(char, ':' | '.' | ',' | '-' | '–' | '—')
+                });
+                links.push(MediaLink {
+                    page,
+                    title: if title.is_empty() {
+                        url.clone()
+                    } else {
+                        title.to_string()
+                    },
+                    url,
+                });
+            }
+        }
+    }
+    links
+}
+
+/// Writes the links as a JSON array of `{ "page", "title", "url" }` objects.
+pub fn write_manifest(path: &Path, links: &[MediaLink]) -> Result<()> {
+    fs::write(path, sonic_rs::to_string(links)?)?;
+    Ok(())
+}
+
+/// Appends pages listing every link by its title and address, each opening the address.
+pub fn append_links(document: &mut Document, links: &[MediaLink]) -> Result<()> {
+    if links.is_empty() {
+        return Ok(());
+    }
+    let pages = document.get_pages();
+    let (w, h) = match pages.get(&1) {
+        Some(&page) => {
+            let media_box = document
+                .get_dictionary(page)?
+                .get(b"MediaBox")?
+                .as_array()?;
+            (media_box[2].as_float()?, media_box[3].as_float()?)
+        }
+        None => (612.0, 792.0),
+    };
+    let font = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let resources = document.add_object(dictionary! {
+        "Font" => dictionary! { "F1" => font },
+    });
+    let pages_id = document.catalog()?.get(b"Pages")?.as_reference()?;
+    // every link takes a line for its title and one for its address, and a gap
+    let per_page = ((h - 2.0 * MARGIN - 2.0 * LINE_HEIGHT) / (3.0 * LINE_HEIGHT)).max(1.0) as usize;
+    let mut new_pages = Vec::new();
+    for (i, chunk) in links.chunks(per_page).enumerate() {
+        let mut operations = Vec::<Operation>::new();
+        let mut annotations = Vec::new();
+        let mut y = h - MARGIN;
+        if i == 0 {
+            write_line(
+                &mut operations,
+                18.0,
+                MARGIN,
+                y,
+                "Media and interactive content",
+            );
+        }
+        y -= 2.0 * LINE_HEIGHT;
+        for link in chunk {
+            let title = format!("Page {}: {}", link.page, link.title);
+            write_line(&mut operations, FONT_SIZE, MARGIN, y, &title);
+            write_line(
+                &mut operations,
+                FONT_SIZE,
+                MARGIN,
+                y - LINE_HEIGHT,
+                &link.url,
+            );
+            let rect = vec![
+                MARGIN.into(),
+                (y - LINE_HEIGHT - 4.0).into(),
+                (w - MARGIN).into(),
+                (y + FONT_SIZE).into(),
+            ];
+            let action = dictionary! {
+                "S" => "URI",
+                "URI" => Object::string_literal(link.url.as_str()),
+            };
+            annotations.push(Object::Reference(document.add_object(dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "Link",
+                "Rect" => rect,
+                "Border" => vec![0.into(), 0.into(), 0.into()],
+                "A" => action,
+            })));
+            y -= 3.0 * LINE_HEIGHT;
+        }
+        let content = Content { operations }.encode()?;
+        let content = document.add_object(Stream::new(dictionary! {}, content));
+        new_pages.push(document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), w.into(), h.into()],
+            "Contents" => content,
+            "Resources" => resources,
+            "Annots" => annotations,
+        }));
+    }
+    let pages = document.get_dictionary_mut(pages_id)?;
+    let kids = pages.get_mut(b"Kids")?.as_array_mut()?;
+    kids.extend(new_pages.into_iter().map(Object::Reference));
+    let count = kids.len() as i64;
+    pages.set("Count", count);
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+
+use crate::layers;
+
+/// Concatenates the documents at `paths` into one, joining their outlines, page labels, named
+/// destinations, attachments and layers. Structure trees aren't carried over.
+pub fn merge(paths: &[PathBuf]) -> Result<Document> {
+    let mut merged = Document::with_version("1.5");
+    let pages_id = merged.new_object_id();
+    let outlines_id = merged.new_object_id();
+    let mut kids = Vec::<Object>::new();
+    let mut outlines = Vec::<ObjectId>::new();
+    let mut outline_count = 0;
+    let mut labels = Vec::new();
+    let mut dests = Vec::new();
+    let mut embedded_files = Vec::new();
+    let mut groups = Vec::new();
+    let mut catalog = None;
+    for path in paths {
+        let mut document = Document::load(path)?;
+        document.renumber_objects_with(merged.max_id + 1);
+        let first_page = kids.len() as i64;
+        let pages = document.get_pages();
+        for &page in pages.values() {
+            document.get_dictionary_mut(page)?.set("Parent", pages_id);
+            kids.push(page.into());
+        }
+        let source = document.catalog()?.clone();
+        match source
+            .get(b"PageLabels")
+            .and_then(|labels| resolve(&document, labels))
+        {
+            Ok(page_labels) => {
+                let nums = page_labels.as_dict()?.get(b"Nums")?.as_array()?;
+                for pair in nums.chunks(2) {
+                    labels.push((first_page + pair[0].as_i64()?).into());
+                    labels.push(pair[1].clone());
+                }
+            }
+            // keep counting from where the previous document left off
+            Err(_) => {
+                labels.push(first_page.into());
+                labels.push(dictionary! { "S" => "D", "St" => first_page + 1 }.into());
+            }
+        }
+        if let Ok(names) = source
+            .get(b"Names")
+            .and_then(|names| resolve(&document, names))
+        {
+            let names = names.as_dict()?;
+            let leaves = name_tree_leaves(&document, names.get(b"Dests").ok())?;
+            // destinations into pages split off into another document point nowhere
+            dests.extend(leaves.into_iter().filter(|(_, dest)| {
+                !matches!(
+                    dest.as_array().map(|dest| dest.first()),
+                    Ok(Some(Object::Null))
+                )
+            }));
+            embedded_files.extend(name_tree_leaves(
+                &document,
+                names.get(b"EmbeddedFiles").ok(),
+            )?);
+        }
+        if let Ok(properties) = source
+            .get(b"OCProperties")
+            .and_then(|p| resolve(&document, p))
+        {
+            groups.extend(properties.as_dict()?.get(b"OCGs")?.as_array()?.clone());
+        }
+        if let Ok(document_outlines) = source.get(b"Outlines").and_then(|o| resolve(&document, o)) {
+            let document_outlines = document_outlines.as_dict()?;
+            let mut item = document_outlines
+                .get(b"First")
+                .and_then(Object::as_reference);
+            while let Ok(id) = item {
+                let entry = document.get_dictionary_mut(id)?;
+                entry.set("Parent", outlines_id);
+                outline_count += 1;
+                outlines.push(id);
+                item = entry.get(b"Next").and_then(Object::as_reference);
+            }
+        }
+        catalog.get_or_insert(source);
+        merged.max_id = document.max_id;
+        merged.objects.extend(document.objects);
+    }
+    let Some(mut catalog) = catalog else {
+        bail!("no documents to merge");
+    };
+    for pair in outlines.windows(2) {
+        merged.get_dictionary_mut(pair[0])?.set("Next", pair[1]);
+        merged.get_dictionary_mut(pair[1])?.set("Prev", pair[0]);
+    }
+    if let (Some(&first), Some(&last)) = (outlines.first(), outlines.last()) {
+        merged.get_dictionary_mut(first)?.remove(b"Prev");
+        merged.get_dictionary_mut(last)?.remove(b"Next");
+        let outlines = dictionary! {
+            "Type" => "Outlines",
+            "First" => first,
+            "Last" => last,
+            "Count" => outline_count,
+        };
+        merged.objects.insert(outlines_id, outlines.into());
+        catalog.set("Outlines", outlines_id);
+    } else {
+        catalog.remove(b"Outlines");
+    }
+    let count = kids.len() as i64;
+    let pages = dictionary! { "Type" => "Pages", "Kids" => kids, "Count" => count };
+    merged.objects.insert(pages_id, pages.into());
+    catalog.set("Pages", pages_id);
+    catalog.set("PageLabels", dictionary! { "Nums" => labels });
+    let mut names = Dictionary::new();
+    for (key, leaves) in [("Dests", dests), ("EmbeddedFiles", embedded_files)] {
+        if !leaves.is_empty() {
+            names.set(key, dictionary! { "Names" => sorted_leaves(leaves) });
+        }
+    }
+    catalog.set("Names", names);
+    for key in [b"StructTreeRoot".as_slice(), b"MarkInfo", b"OCProperties"] {
+        catalog.remove(key);
+    }
+    let has_layers = !groups.is_empty();
+    if has_layers {
+        catalog.set("OCProperties", dictionary! { "OCGs" => groups });
+    }
+    let catalog_id = merged.add_object(catalog);
+    merged.trailer.set("Root", catalog_id);
+    if has_layers {
+        layers::merge_layers(&mut merged)?;
+    }
+    merged.prune_objects();
+    Ok(merged)
+}
+
+fn resolve<'a>(document: &'a Document, object: &'a Object) -> lopdf::Result<&'a Object> {
+    match object {
+        Object::Reference(id) => document.get_object(*id),
+        object => Ok(object),
+    }
+}
+
+/// Returns the key and value pairs of a name tree, following its intermediate nodes.
+fn name_tree_leaves(document: &Document, tree: Option<&Object>) -> Result<Vec<(Vec<u8>, Object)>> {
+    let Some(tree) = tree else {
+        return Ok(Vec::new());
+    };
+    let node = resolve(document, tree)?.as_dict()?;
+    let mut leaves = Vec::new();
+    if let Ok(names) = node
+        .get(b"Names")
+        .and_then(|names| resolve(document, names))
+    {
+        for pair in names.as_array()?.chunks(2) {
+            if let [key, value] = pair {
+                leaves.push((key.as_str()?.to_vec(), value.clone()));
+            }
+        }
+    }
+    if let Ok(kids) = node.get(b"Kids").and_then(|kids| resolve(document, kids)) {
+        for kid in kids.as_array()? {
+            leaves.extend(name_tree_leaves(document, Some(kid))?);
+        }
+    }
+    Ok(leaves)
+}
+
+/// Sorts name tree leaves by key, keeping only the first of any duplicates.
+fn sorted_leaves(mut leaves: Vec<(Vec<u8>, Object)>) -> Vec<Object> {
+    leaves.sort_by(|a, b| a.0.cmp(&b.0));
+    leaves.dedup_by(|a, b| a.0 == b.0);
+    leaves
+        .into_iter()
+        .flat_map(|(key, value)| [Object::String(key, lopdf::StringFormat::Literal), value])
+        .collect()
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+
+/// The environment variable the server sets for a job to the file it keeps its counters in.
+pub const ENV_VAR: &str = "PEARSON_PLUS_EXTRACTOR_METRICS";
+
+/// The upper bounds in seconds of the buckets of job durations.
+const DURATION_BUCKETS: [f64; 7] = [60.0, 300.0, 600.0, 1800.0, 3600.0, 7200.0, 14400.0];
+
+/// What an extraction counts as it goes, saved to a file for the server running it as a job.
+#[derive(Default)]
+pub struct Counters {
+    path: Option<PathBuf>,
+    pages: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+    retries: AtomicU64,
+}
+
+/// The names of the counters in their files and in the metrics, along with their help texts.
+const COUNTERS: [(&str, &str); 4] = [
+    ("pages", "Pages downloaded."),
+    ("bytes", "Bytes of response bodies received."),
+    ("errors", "Failed attempts at downloading a page."),
+    ("retries", "Pages requested again and downloads resumed."),
+];
+
+impl Counters {
+    /// Saves the counters to `path` as they change.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            ..Self::default()
+        }
+    }
+
+    pub fn add_page(&self) {
+        self.pages.fetch_add(1, Ordering::Relaxed);
+        self.save();
+    }
+
+    pub fn add_bytes(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_errors(&self, errors: usize) {
+        self.errors.fetch_add(errors as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn values(&self) -> [u64; 4] {
+        [&self.pages, &self.bytes, &self.errors, &self.retries]
+            .map(|counter| counter.load(Ordering::Relaxed))
+    }
+
+    /// Writes the counters to their file, if they have one, as lines such as `pages 12`. Metrics
+    /// are no reason to fail an extraction, so an error writing them is ignored.
+    pub fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut file = String::new();
+        for ((name, _), value) in COUNTERS.iter().zip(self.values()) {
+            let _ = writeln!(file, "{} {}", name, value);
+        }
+        // written under another name first so the server never reads half of it
+        let partial = path.with_extension("metrics.partial");
+        if fs::write(&partial, file).is_ok() {
+            let _ = fs::rename(partial, path);
+        }
+    }
+}
+
+/// Reads the pages, bytes, errors and retries a job counted, leaving out lines it doesn't know.
+pub fn load(path: &Path) -> [u64; 4] {
+    let mut values = [0; 4];
+    for line in fs::read_to_string(path).unwrap_or_default().lines() {
+        let Some((name, value)) = line.split_once(' ') else {
+            continue;
+        };
+        if let Some(i) = COUNTERS.iter().position(|(counter, _)| *counter == name) {
+            values[i] = value.trim().parse().unwrap_or_default();
+        }
+    }
+    values
+}
+
+/// The jobs a server has run, by how they ended, and how long they took.
+#[derive(Default)]
+pub struct Jobs(Mutex<JobCounts>);
+
+#[derive(Default)]
+struct JobCounts {
+    done: u64,
+    failed: u64,
+    /// How many jobs took at most the duration of each bucket.
+    buckets: [u64; DURATION_BUCKETS.len()],
+    seconds: f64,
+    /// The counters last read from the file of every job, kept after the file is deleted so the
+    /// totals never go down.
+    counters: HashMap<PathBuf, [u64; 4]>,
+    /// The counters of runs whose file a later run of the same job started over.
+    replaced: [u64; 4],
+}
+
+impl JobCounts {
+    /// Reads the counters of the job that keeps them in `path`.
+    fn update(&mut self, path: &Path) {
+        let values = load(path);
+        let last = self.counters.entry(path.to_path_buf()).or_default();
+        if values
+            .iter()
+            .zip(last.iter())
+            .any(|(value, last)| value < last)
+        {
+            for (replaced, last) in self.replaced.iter_mut().zip(*last) {
+                *replaced += last;
+            }
+        }
+        *last = values;
+    }
+}
+
+impl Jobs {
+    /// Counts a job that ran for `duration` and kept its counters in `metrics`.
+    pub fn finish(&self, succeeded: bool, duration: Duration, metrics: &Path) {
+        let mut jobs = self.0.lock().unwrap();
+        jobs.update(metrics);
+        match succeeded {
+            true => jobs.done += 1,
+            false => jobs.failed += 1,
+        }
+        let seconds = duration.as_secs_f64();
+        for (bucket, &bound) in jobs.buckets.iter_mut().zip(&DURATION_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        jobs.seconds += seconds;
+    }
+
+    /// Renders the metrics of the server in the Prometheus text format, with the counters of the
+    /// jobs in the queue directory `dir` added up, along with those of the jobs it no longer has.
+    pub fn render(&self, dir: &Path) -> Result<String> {
+        let mut jobs = self.0.lock().unwrap();
+        let mut running = 0;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("metrics") => jobs.update(&path),
+                Some("running") => running += 1,
+                _ => {}
+            }
+        }
+        let mut totals = jobs.replaced;
+        for values in jobs.counters.values() {
+            for (total, value) in totals.iter_mut().zip(values) {
+                *total += value;
+            }
+        }
+        let mut metrics = String::new();
+        for ((name, help), total) in COUNTERS.iter().zip(totals) {
+            let name = format!("pearson_plus_extractor_{}_total", name);
+            writeln!(metrics, "# HELP {} {}", name, help)?;
+            writeln!(metrics, "# TYPE {} counter", name)?;
+            writeln!(metrics, "{} {}", name, total)?;
+        }
+        writeln!(
+            metrics,
+            "# HELP pearson_plus_extractor_jobs_total Jobs finished, by how they ended."
+        )?;
+        writeln!(metrics, "# TYPE pearson_plus_extractor_jobs_total counter")?;
+        for (state, count) in [("done", jobs.done), ("failed", jobs.failed)] {
+            writeln!(
+                metrics,
+                "pearson_plus_extractor_jobs_total{{state=\"{}\"}} {}",
+                state, count
+            )?;
+        }
+        writeln!(
+            metrics,
+            "# HELP pearson_plus_extractor_jobs_running Jobs running."
+        )?;
+        writeln!(metrics, "# TYPE pearson_plus_extractor_jobs_running gauge")?;
+        writeln!(metrics, "pearson_plus_extractor_jobs_running {}", running)?;
+        let name = "pearson_plus_extractor_job_duration_seconds";
+        writeln!(metrics, "# HELP {} How long finished jobs ran.", name)?;
+        writeln!(metrics, "# TYPE {} histogram", name)?;
+        for (bound, count) in DURATION_BUCKETS.iter().zip(jobs.buckets) {
+            writeln!(metrics, "{}_bucket{{le=\"{}\"}} {}", name, bound, count)?;
+        }
+        let count = jobs.done + jobs.failed;
+        writeln!(metrics, "{}_bucket{{le=\"+Inf\"}} {}", name, count)?;
+        writeln!(metrics, "{}_sum {}", name, jobs.seconds)?;
+        writeln!(metrics, "{}_count {}", name, count)?;
+        Ok(metrics)
+    }
+}
@@ -0,0 +1,87 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use anyhow::{anyhow, Result};
+
+use crate::{figures::Figure, filename, toc::Toc, Extraction};
+
+/// Writes a Markdown vault for Obsidian and similar tools into `dir`, with one note per TOC
+/// entry holding the text and figures of its pages and a link to its page in a copy of the
+/// document at `pdf`, and a contents note linking every section. The figures are read from
+/// `figures_dir`. Returns the number of notes written.
+pub fn write_vault(
+    dir: &Path,
+    pdf: &Path,
+    extraction: &Extraction,
+    toc: &Toc,
+    figures_dir: &Path,
+) -> Result<usize> {
+    let pdf_name = pdf
+        .file_name()
+        .ok_or_else(|| anyhow!("{} is not a file", pdf.display()))?
+        .to_string_lossy();
+    fs::create_dir_all(dir.join("figures"))?;
+    fs::copy(pdf, dir.join(pdf_name.as_ref()))?;
+    let entries = toc.flatten();
+    let mut names = HashSet::new();
+    let note_names = entries
+        .iter()
+        .map(|(_, entry)| {
+            let name = filename::sanitize(&entry.title);
+            let mut unique = name.clone();
+            for i in 2.. {
+                if names.insert(unique.clone()) {
+                    break;
+                }
+                unique = format!("{name} ({i})");
+            }
+            unique
+        })
+        .collect::<Vec<_>>();
+    let end = extraction.page_texts.len() as u32;
+    let mut contents = "# Contents\n\n".to_string();
+    for (i, (depth, entry)) in entries.iter().enumerate() {
+        let name = &note_names[i];
+        contents += &format!("{}- [[{}]]\n", "  ".repeat(*depth), name);
+        // a section runs until the next entry of any depth, but always holds its own page
+        let next = entries.get(i + 1).map_or(end, |(_, next)| next.page);
+        let pages = entry.page..next.max(entry.page + 1);
+        let mut note = format!("# {}\n\n", entry.title);
+        if let Some(pdf_page) = extraction.pdf_page(entry.page) {
+            note += &format!("[[{pdf_name}#page={pdf_page}|Open in the book]] · [[Contents]]\n\n");
+        }
+        for page in pages {
+            let Some(text) = extraction.page_texts.get(page as usize) else {
+                break;
+            };
+            if extraction.removed_pages.contains(&page) {
+                continue;
+            }
+            if !text.trim().is_empty() {
+                note += text.trim_end();
+                note += "\n\n";
+            }
+            for figure in extraction
+                .figures
+                .iter()
+                .filter(|figure| figure.page == page)
+            {
+                note += &embed_figure(dir, figures_dir, figure)?;
+            }
+        }
+        fs::write(dir.join(format!("{name}.md")), note)?;
+    }
+    fs::write(dir.join("Contents.md"), contents)?;
+    Ok(entries.len())
+}
+
+/// Copies a figure into the vault unless it was exported there, and returns its embed.
+fn embed_figure(dir: &Path, figures_dir: &Path, figure: &Figure) -> Result<String> {
+    let destination = dir.join("figures").join(&figure.file);
+    if !destination.exists() {
+        fs::copy(figures_dir.join(&figure.file), destination)?;
+    }
+    Ok(format!(
+        "![[figures/{}]]\n*{}*\n\n",
+        figure.file, figure.caption
+    ))
+}
@@ -0,0 +1,195 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use reqwest::{header::CONTENT_TYPE, Client, Url};
+
+use crate::{
+    config::{self, Smtp},
+    metrics, queue, smtp,
+};
+
+/// How long to wait for the webhook to answer.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the server reports the jobs that finish.
+#[derive(Default)]
+pub struct Targets {
+    /// The URL of a webhook.
+    pub webhook: Option<String>,
+    /// The mail server and the settings of the emails.
+    pub email: Option<(Smtp, config::Notify)>,
+}
+
+impl Targets {
+    /// Reports `report` to every target, printing the failures rather than returning them, since
+    /// the job is finished either way.
+    pub fn send(&self, report: &Report) {
+        if let Some(url) = &self.webhook {
+            if let Err(error) = webhook(url, report) {
+                println!(
+                    "Couldn't notify the webhook of job {}: {}",
+                    report.job, error
+                );
+            }
+        }
+        if let Some((smtp, notify)) = &self.email {
+            if let Err(error) = email(smtp, notify, report) {
+                println!("Couldn't email the report of job {}: {}", report.job, error);
+            }
+        }
+    }
+}
+
+/// How a job of the queue ended.
+pub struct Report<'a> {
+    /// The name of the job file without its extension.
+    pub job: &'a str,
+    /// The command line arguments of the job.
+    pub args: &'a [String],
+    pub succeeded: bool,
+    pub duration: Duration,
+    /// The file the job kept its counters in.
+    pub metrics: &'a Path,
+    /// The directory the job ran in, which a relative output path is in.
+    pub dir: &'a Path,
+}
+
+impl Report<'_> {
+    fn arg(&self, names: &[&str]) -> Option<&str> {
+        queue::arg(self.args, names)
+    }
+
+    fn status(&self) -> &'static str {
+        if self.succeeded {
+            "done"
+        } else {
+            "failed"
+        }
+    }
+
+    /// The document the job wrote, or would have.
+    fn output(&self) -> PathBuf {
+        self.dir.join(queue::output(self.args))
+    }
+
+    /// The JSON payload of a generic webhook.
+    fn payload(&self) -> String {
+        let [pages, bytes, errors, retries] = metrics::load(self.metrics);
+        let output = self.output();
+        sonic_rs::json!({
+            "job": self.job,
+            "book": {
+                "product_id": self.arg(&["-p", "--product-id"]),
+                "uuid": self.arg(&["-u", "--uuid"]),
+                "title": self.arg(&["--title"]),
+            },
+            "status": self.status(),
+            "output": output.to_string_lossy(),
+            "stats": {
+                "pages": pages,
+                "bytes": bytes,
+                "errors": errors,
+                "retries": retries,
+                "seconds": self.duration.as_secs_f64(),
+            },
+        })
+        .to_string()
+    }
+
+    /// The message of a chat service, such as `Extracted "Biology" (job 17): 1204 pages in 73
+    /// minutes.`
+    fn message(&self) -> String {
+        let [pages, _, errors, _] = metrics::load(self.metrics);
+        let book = match self.arg(&["--title"]) {
+            Some(title) => format!("\"{}\"", title),
+            None => format!(
+                "product {}",
+                self.arg(&["-p", "--product-id"]).unwrap_or("?")
+            ),
+        };
+        let minutes = self.duration.as_secs() / 60;
+        match self.succeeded {
+            true => format!(
+                "Extracted {} (job {}): {} pages in {} minutes.",
+                book, self.job, pages, minutes
+            ),
+            false => format!(
+                "Failed to extract {} (job {}) after {} pages, {} errors and {} minutes.",
+                book, self.job, pages, errors, minutes
+            ),
+        }
+    }
+}
+
+/// Posts `report` to `url`, as a message for Discord and Slack webhooks, which only take those,
+/// and as the full JSON payload for any other.
+fn webhook(url: &str, report: &Report) -> Result<()> {
+    let url = Url::parse(url)?;
+    let body = match url.host_str().unwrap_or_default() {
+        "discord.com" | "discordapp.com" => {
+            sonic_rs::json!({ "content": report.message() }).to_string()
+        }
+        "hooks.slack.com" => sonic_rs::json!({ "text": report.message() }).to_string(),
+        _ => report.payload(),
+    };
+    // the queue runs on threads of its own, outside the runtime of main
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        Client::new()
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .timeout(TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    })
+}
+
+/// Emails `report` to the address of `notify`, with the document attached if it is small enough.
+fn email(smtp: &Smtp, notify: &config::Notify, report: &Report) -> Result<()> {
+    let [pages, bytes, errors, retries] = metrics::load(report.metrics);
+    let output = report.output();
+    let seconds = report.duration.as_secs();
+    let mut body = format!(
+        "{}\r\n\r\nOutput: {}\r\nPages: {}\r\nDownloaded: {:.1} MB\r\nErrors: {}\r\n\
+         Retries: {}\r\nRan for: {}:{:02}:{:02}\r\n",
+        report.message(),
+        output.display(),
+        pages,
+        bytes as f64 / 1_000_000.0,
+        errors,
+        retries,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    let size = fs::metadata(&output).map_or(0, |metadata| metadata.len());
+    let attached = match notify.attach_max {
+        Some(max) if report.succeeded && size > max => {
+            body.push_str(&format!(
+                "\r\nThe document is {:.1} MB, too large to attach.\r\n",
+                size as f64 / 1_000_000.0
+            ));
+            None
+        }
+        Some(_) if report.succeeded => Some(fs::read(&output)?),
+        _ => None,
+    };
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    let subject = report.message();
+    let message = smtp::Message {
+        to: &notify.email,
+        subject: &subject,
+        body: &body,
+        attachment: attached.as_deref().map(|content| (name.as_ref(), content)),
+    };
+    smtp::send(smtp, &message)
+}
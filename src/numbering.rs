@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+/// How many lines at the top and the bottom of a page are searched for its printed number.
+const EDGE_LINES: usize = 3;
+
+/// The zero digits of the decimal digit blocks page numbers are printed with.
+const ZEROS: [char; 8] = [
+    '0', '\u{660}', '\u{6F0}', '\u{966}', '\u{9E6}', '\u{E50}', '\u{1040}', '\u{FF10}',
+];
+
+/// A page number as printed in a book.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Printed {
+    /// A number of the front matter, such as "xiv".
+    Roman(u32),
+    Arabic(u32),
+}
+
+impl Printed {
+    pub fn value(self) -> u32 {
+        match self {
+            Printed::Roman(value) | Printed::Arabic(value) => value,
+        }
+    }
+}
+
+/// Reads a page number such as "12", "xiv", "p. 12", "S. 12 f.", "1.234" or "١٢".
+pub fn parse(label: &str) -> Option<Printed> {
+    let mut label = label.trim();
+    // a word such as "p.", "page", "S." or "стр." before the number
+    if let Some((word, rest)) = label.split_once([' ', '.', '\u{a0}']) {
+        if word.chars().all(char::is_alphabetic) && parse_number(rest.trim()).is_some() {
+            label = rest.trim();
+        }
+    }
+    parse_number(label)
+}
+
+fn parse_number(label: &str) -> Option<Printed> {
+    // "12 f." and "12 ff." for the pages following
+    let label = label
+        .trim_end_matches(['.', ' '])
+        .trim_end_matches("ff")
+        .trim_end_matches('f')
+        .trim_end();
+    arabic(label)
+        .map(Printed::Arabic)
+        .or_else(|| roman(label).map(Printed::Roman))
+}
+
+/// Reads a number written in any of the decimal digits of `ZEROS`, with separators of
+/// thousands.
+fn arabic(label: &str) -> Option<u32> {
+    let mut value = 0u32;
+    let mut digits = 0;
+    let mut chars = label.chars().peekable();
+    while let Some(char) = chars.next() {
+        match digit(char) {
+            Some(digit) => {
+                value = value.checked_mul(10)?.checked_add(digit)?;
+                digits += 1;
+            }
+            // separators only between digits
+            None if matches!(char, ',' | '.' | ' ' | '\'' | '\u{a0}' | '\u{202f}')
+                && digits > 0
+                && chars.peek().copied().and_then(digit).is_some() => {}
+            None => return None,
+        }
+    }
+    (digits > 0).then_some(value)
+}
+
+fn digit(char: char) -> Option<u32> {
+    ZEROS.iter().find_map(|&zero| {
+        let digit = (char as u32).checked_sub(zero as u32)?;
+        (digit < 10).then_some(digit)
+    })
+}
+
+/// Reads a roman numeral such as "xiv" or "XIV", written the usual way.
+fn roman(label: &str) -> Option<u32> {
+    if label.is_empty() || label.len() > 15 {
+        return None;
+    }
+    let lowercase = label.to_ascii_lowercase();
+    let mut value = 0u32;
+    let mut previous = 0;
+    for char in lowercase.chars().rev() {
+        let digit = match char {
+            'i' => 1,
+            'v' => 5,
+            'x' => 10,
+            'l' => 50,
+            'c' => 100,
+            'd' => 500,
+            'm' => 1000,
+            _ => return None,
+        };
+        if digit < previous {
+            // more is subtracted than was added in numerals such as "iiiiiiv"
+            value = value.checked_sub(digit)?;
+        } else {
+            value += digit;
+            previous = digit;
+        }
+    }
+    // rejects words such as "dim" and malformed numerals such as "iiii"
+    (to_roman(value) == lowercase).then_some(value)
+}
+
+/// Writes a number as a lowercase roman numeral.
+pub fn to_roman(mut value: u32) -> String {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut roman = String::new();
+    for (step, numeral) in NUMERALS {
+        while value >= step {
+            roman += numeral;
+            value -= step;
+        }
+    }
+    roman
+}
+
+/// Tells which page a printed page number is on, from the numbers printed alone on a line at
+/// the top or the bottom of the pages.
+#[derive(Default)]
+pub struct Numbering {
+    /// How often each difference between a page and the number printed on it was seen, for the
+    /// roman and arabic numbers apart.
+    offsets: HashMap<(bool, i64), u32>,
+}
+
+impl Numbering {
+    pub fn add_page(&mut self, page: u32, text: &str) {
+        let lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+        let top = lines.iter().take(EDGE_LINES);
+        let bottom = lines
+            .iter()
+            .rev()
+            .take(EDGE_LINES.min(lines.len().saturating_sub(EDGE_LINES)));
+        // a number alone on its line, roman ones only in lowercase like page numbers are
+        let printed = top
+            .chain(bottom)
+            .find_map(|line| match parse_number(line)? {
+                Printed::Roman(_) if line.chars().any(|char| char.is_uppercase()) => None,
+                printed => Some(printed),
+            });
+        if let Some(printed) = printed {
+            let roman = matches!(printed, Printed::Roman(_));
+            *self
+                .offsets
+                .entry((roman, page as i64 - printed.value() as i64))
+                .or_default() += 1;
+        }
+    }
+
+    /// Returns the page `printed` is on, assuming the difference between a page and its
+    /// printed number seen most often for its kind of number holds for all of them.
+    pub fn page(&self, printed: Printed) -> Option<u32> {
+        let roman = matches!(printed, Printed::Roman(_));
+        let (&(_, offset), _) = self
+            .offsets
+            .iter()
+            .filter(|((kind, _), _)| *kind == roman)
+            .max_by_key(|&((_, offset), count)| (count, -offset))?;
+        u32::try_from(printed.value() as i64 + offset).ok()
+    }
+}
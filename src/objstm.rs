@@ -0,0 +1,283 @@
+use std::{collections::BTreeMap, io::Write};
+
+use anyhow::Result;
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream, StringFormat};
+
+/// How many objects go into one object stream.
+const OBJECTS_PER_STREAM: usize = 100;
+
+enum Entry {
+    /// An object at a byte offset of the file, with its generation.
+    Uncompressed(usize, u16),
+    /// An object at an index of an object stream.
+    Compressed(u32, usize),
+}
+
+/// How the objects of a document and the cross-reference to them are written.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Packing {
+    /// Every object on its own with a cross-reference table, which every reader understands.
+    #[default]
+    Table,
+    /// Every object on its own with a compressed cross-reference stream, which takes PDF 1.5.
+    XrefStream,
+    /// The dictionaries and other small objects packed into compressed object streams along
+    /// with a cross-reference stream, which saves a few hundred bytes per page of a long book.
+    ObjectStreams,
+}
+
+/// Saves `document` with a cross-reference stream, with its dictionaries and other small
+/// objects packed into compressed object streams for `Packing::ObjectStreams`.
+pub fn save(document: &Document, packing: Packing, output: &mut impl Write) -> Result<()> {
+    save_spilled(document, packing, output, |_| Ok(None))
+}
+
+/// Saves `document` as `save` does, writing the streams whose contents were moved out of the
+/// document with the contents `spilled` returns for them instead.
+pub fn save_spilled(
+    document: &Document,
+    packing: Packing,
+    output: &mut impl Write,
+    mut spilled: impl FnMut(ObjectId) -> Result<Option<Vec<u8>>>,
+) -> Result<()> {
+    if packing == Packing::Table {
+        return save_table_spilled(document, output, spilled);
+    }
+    let mut written = Written::new(output, &header(&document.version))?;
+    let mut entries = BTreeMap::new();
+    let mut compressible = Vec::new();
+    for (&(id, generation), object) in &document.objects {
+        match object {
+            Object::Stream(stream) => {
+                entries.insert(id, Entry::Uncompressed(written.offset, generation));
+                let content = spilled((id, generation))?;
+                let content = content.as_deref().unwrap_or(&stream.content);
+                written.object(id, generation, |file| {
+                    write_stream_content(file, &stream.dict, content)
+                })?;
+            }
+            object if generation == 0 && packing == Packing::ObjectStreams => {
+                compressible.push((id, object))
+            }
+            object => {
+                entries.insert(id, Entry::Uncompressed(written.offset, generation));
+                written.object(id, generation, |file| write_object(file, object))?;
+            }
+        }
+    }
+    let mut next_id = document.max_id + 1;
+    for chunk in compressible.chunks(OBJECTS_PER_STREAM) {
+        let mut header = Vec::new();
+        let mut data = Vec::new();
+        for (index, &(id, object)) in chunk.iter().enumerate() {
+            write!(header, "{} {} ", id, data.len())?;
+            write_object(&mut data, object);
+            data.push(b'\n');
+            entries.insert(id, Entry::Compressed(next_id, index));
+        }
+        let first = header.len();
+        header.extend(data);
+        let mut stream = Stream::new(
+            dictionary! { "Type" => "ObjStm", "N" => chunk.len() as i64, "First" => first as i64 },
+            header,
+        );
+        stream.compress()?;
+        entries.insert(next_id, Entry::Uncompressed(written.offset, 0));
+        written.object(next_id, 0, |file| write_stream(file, &stream))?;
+        next_id += 1;
+    }
+    let xref_id = next_id;
+    let xref_offset = written.offset;
+    entries.insert(xref_id, Entry::Uncompressed(xref_offset, 0));
+    let mut xref = Vec::new();
+    for id in 0..=xref_id {
+        let (kind, field, generation) = match entries.get(&id) {
+            Some(Entry::Uncompressed(offset, generation)) => (1u8, *offset as u32, *generation),
+            Some(Entry::Compressed(stream, index)) => (2, *stream, *index as u16),
+            None if id == 0 => (0, 0, 65535),
+            None => (0, 0, 0),
+        };
+        xref.push(kind);
+        xref.extend(field.to_be_bytes());
+        xref.extend(generation.to_be_bytes());
+    }
+    let mut dict = dictionary! {
+        "Type" => "XRef",
+        "Size" => xref_id as i64 + 1,
+        "W" => vec![1.into(), 4.into(), 2.into()],
+    };
+    for key in [b"Root".as_slice(), b"Info", b"ID"] {
+        if let Ok(value) = document.trailer.get(key) {
+            dict.set(key, value.clone());
+        }
+    }
+    let mut stream = Stream::new(dict, xref);
+    stream.compress()?;
+    written.object(xref_id, 0, |file| write_stream(file, &stream))?;
+    write!(written.output, "startxref\n{}\n%%EOF\n", xref_offset)?;
+    Ok(())
+}
+
+/// Saves `document` with a cross-reference table, as lopdf does, writing the streams whose
+/// contents were moved out of the document with the contents `spilled` returns for them.
+fn save_table_spilled(
+    document: &Document,
+    output: &mut impl Write,
+    mut spilled: impl FnMut(ObjectId) -> Result<Option<Vec<u8>>>,
+) -> Result<()> {
+    let mut written = Written::new(output, &header(&document.version))?;
+    let mut offsets = BTreeMap::new();
+    for (&(id, generation), object) in &document.objects {
+        offsets.insert(id, (written.offset, generation));
+        match object {
+            Object::Stream(stream) => {
+                let content = spilled((id, generation))?;
+                let content = content.as_deref().unwrap_or(&stream.content);
+                written.object(id, generation, |file| {
+                    write_stream_content(file, &stream.dict, content)
+                })?;
+            }
+            object => written.object(id, generation, |file| write_object(file, object))?,
+        }
+    }
+    let size = document.max_id + 1;
+    let xref_offset = written.offset;
+    let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", size);
+    for id in 1..size {
+        match offsets.get(&id) {
+            Some((offset, generation)) => xref += &format!("{:010} {:05} n \n", offset, generation),
+            None => xref += "0000000000 00000 f \n",
+        }
+    }
+    let mut trailer = document.trailer.clone();
+    trailer.set("Size", size as i64);
+    let mut trailer_bytes = Vec::new();
+    write_dictionary(&mut trailer_bytes, &trailer);
+    written.output.write_all(xref.as_bytes())?;
+    written.output.write_all(b"trailer\n")?;
+    written.output.write_all(&trailer_bytes)?;
+    write!(written.output, "\nstartxref\n{}\n%%EOF\n", xref_offset)?;
+    Ok(())
+}
+
+/// The header of a file of PDF `version`, with the comment of binary characters that tells
+/// transfer programs the file isn't text.
+pub fn header(version: &str) -> Vec<u8> {
+    let mut header = format!("%PDF-{}\n", version).into_bytes();
+    header.extend(b"%\xE2\xE3\xCF\xD3\n");
+    header
+}
+
+/// An output along with how many bytes were written to it, written an object at a time.
+struct Written<'a, W: Write> {
+    output: &'a mut W,
+    offset: usize,
+}
+
+impl<'a, W: Write> Written<'a, W> {
+    fn new(output: &'a mut W, header: &[u8]) -> Result<Self> {
+        output.write_all(header)?;
+        Ok(Self {
+            output,
+            offset: header.len(),
+        })
+    }
+
+    fn object(&mut self, id: u32, generation: u16, body: impl FnOnce(&mut Vec<u8>)) -> Result<()> {
+        let mut bytes = Vec::new();
+        write_indirect(&mut bytes, id, generation, body);
+        self.output.write_all(&bytes)?;
+        self.offset += bytes.len();
+        Ok(())
+    }
+}
+
+/// Writes the object numbered `id` with the body `body` writes.
+pub fn write_indirect(
+    file: &mut Vec<u8>,
+    id: u32,
+    generation: u16,
+    body: impl FnOnce(&mut Vec<u8>),
+) {
+    file.extend(format!("{} {} obj\n", id, generation).as_bytes());
+    body(file);
+    file.extend(b"\nendobj\n");
+}
+
+fn write_stream(file: &mut Vec<u8>, stream: &Stream) {
+    write_stream_content(file, &stream.dict, &stream.content);
+}
+
+pub fn write_stream_content(file: &mut Vec<u8>, dict: &Dictionary, content: &[u8]) {
+    let mut dict = dict.clone();
+    dict.set("Length", content.len() as i64);
+    write_dictionary(file, &dict);
+    file.extend(b"stream\n");
+    file.extend(content);
+    file.extend(b"\nendstream");
+}
+
+fn write_dictionary(file: &mut Vec<u8>, dict: &Dictionary) {
+    file.extend(b"<<");
+    for (key, value) in dict.iter() {
+        write_name(file, key);
+        file.push(b' ');
+        write_object(file, value);
+    }
+    file.extend(b">>");
+}
+
+fn write_name(file: &mut Vec<u8>, name: &[u8]) {
+    file.push(b'/');
+    for &byte in name {
+        if (b'!'..=b'~').contains(&byte) && !b"()<>[]{}/%#".contains(&byte) {
+            file.push(byte);
+        } else {
+            file.extend(format!("#{:02X}", byte).as_bytes());
+        }
+    }
+}
+
+pub fn write_object(file: &mut Vec<u8>, object: &Object) {
+    match object {
+        Object::Null => file.extend(b"null"),
+        Object::Boolean(value) => file.extend(value.to_string().as_bytes()),
+        Object::Integer(value) => file.extend(value.to_string().as_bytes()),
+        Object::Real(value) => file.extend(value.to_string().as_bytes()),
+        Object::Name(name) => write_name(file, name),
+        Object::String(bytes, StringFormat::Literal) => {
+            file.push(b'(');
+            for &byte in bytes {
+                match byte {
+                    b'(' | b')' | b'\\' => file.extend([b'\\', byte]),
+                    b'\r' => file.extend(b"\\r"),
+                    byte => file.push(byte),
+                }
+            }
+            file.push(b')');
+        }
+        Object::String(bytes, StringFormat::Hexadecimal) => {
+            file.push(b'<');
+            for byte in bytes {
+                file.extend(format!("{:02X}", byte).as_bytes());
+            }
+            file.push(b'>');
+        }
+        Object::Array(array) => {
+            file.push(b'[');
+            for (i, item) in array.iter().enumerate() {
+                if i > 0 {
+                    file.push(b' ');
+                }
+                write_object(file, item);
+            }
+            file.push(b']');
+        }
+        Object::Dictionary(dict) => write_dictionary(file, dict),
+        // streams are always written as indirect objects
+        Object::Stream(stream) => write_stream(file, stream),
+        Object::Reference((id, generation)) => {
+            file.extend(format!("{} {} R", id, generation).as_bytes())
+        }
+    }
+}
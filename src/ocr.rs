@@ -0,0 +1,57 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{Text, TextPageData};
+
+/// Recognizes the words on a page image as `recognize` does, on the blocking thread pool so the
+/// downloads in flight carry on meanwhile.
+pub async fn recognize_blocking(image: &[u8], scale: f32, height: u32) -> Result<TextPageData> {
+    let image = image.to_vec();
+    tokio::task::spawn_blocking(move || recognize(&image, scale, height)).await?
+}
+
+/// Recognizes the words on a page image with the `tesseract` command, laid out like annotation
+/// data on a page of `scale` points per image pixel and `height` pixels.
+pub fn recognize(image: &[u8], scale: f32, height: u32) -> Result<TextPageData> {
+    let mut tesseract = Command::new("tesseract")
+        .args(["stdin", "stdout", "tsv"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    tesseract.stdin.take().unwrap().write_all(image)?;
+    let output = tesseract.wait_with_output()?;
+    if !output.status.success() {
+        bail!("tesseract exited with {}", output.status);
+    }
+    let mut data = Vec::new();
+    // level page_num block_num par_num line_num word_num left top width height conf text
+    for line in String::from_utf8_lossy(&output.stdout).lines().skip(1) {
+        let fields: Vec<&str> = line.splitn(12, '\t').collect();
+        if fields.len() < 12 || fields[0] != "5" || fields[11].trim().is_empty() {
+            continue;
+        }
+        let [left, top, width, word_height] =
+            [6, 7, 8, 9].map(|i| fields[i].parse::<f32>().unwrap_or_default() * scale);
+        let word = fields[11].trim();
+        let advance = width / word.chars().count() as f32;
+        let baseline = height as f32 * scale - top - word_height;
+        let stream = word
+            .chars()
+            .enumerate()
+            .map(|(i, char)| {
+                let x = left + advance * i as f32;
+                (x, baseline, advance, word_height, char as u32)
+            })
+            .collect();
+        data.push(Text {
+            matrix: [word_height, 0.0, 0.0, word_height, left, baseline],
+            stream,
+        });
+    }
+    Ok(TextPageData { data })
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+use anyhow::Result;
+use image::{load_from_memory, Rgba, RgbaImage};
+
+use crate::{crossref, TextPageData};
+
+/// The color of the boxes around text runs.
+const RUN_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+/// The color of the boxes around the characters of a run.
+const CHAR_COLOR: Rgba<u8> = Rgba([0, 160, 255, 255]);
+
+/// Saves the image of a page into `dir` as `page0012.png`, with a box drawn around every text
+/// run and every character in it where the text layer puts them, to see whether the two line up
+/// without opening the document. `scale` is the points per pixel of the image.
+pub fn write(dir: &Path, page: u32, png: &[u8], texts: &TextPageData, scale: f32) -> Result<()> {
+    let mut image = load_from_memory(png)?.to_rgba8();
+    for text in &texts.data {
+        let chars = crossref::chars(text);
+        if chars.is_empty() {
+            continue;
+        }
+        for (_, rect) in &chars {
+            draw_box(&mut image, rect, scale, CHAR_COLOR);
+        }
+        let run = crossref::bounds(chars.iter().map(|(_, rect)| rect));
+        draw_box(&mut image, &run, scale, RUN_COLOR);
+    }
+    image.save(dir.join(format!("page{:04}.png", page)))?;
+    Ok(())
+}
+
+/// Draws the outline of a rectangle in points, from the bottom left of the page, clipped to the
+/// image.
+fn draw_box(image: &mut RgbaImage, [x0, y0, x1, y1]: &[f32; 4], scale: f32, color: Rgba<u8>) {
+    let (w, h) = (image.width() as i64, image.height() as i64);
+    let left = (x0 / scale) as i64;
+    let right = (x1 / scale) as i64;
+    // images run from the top down
+    let top = h - (y1 / scale) as i64;
+    let bottom = h - (y0 / scale) as i64;
+    let mut put = |x: i64, y: i64| {
+        if (0..w).contains(&x) && (0..h).contains(&y) {
+            image.put_pixel(x as u32, y as u32, color);
+        }
+    };
+    for x in left..=right {
+        put(x, top);
+        put(x, bottom);
+    }
+    for y in top..=bottom {
+        put(left, y);
+        put(right, y);
+    }
+}
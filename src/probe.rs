@@ -0,0 +1,57 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+/// The most requests at a time recommended, however slow the round trips.
+const MAX_CONCURRENCY: usize = 16;
+/// How much faster downloading several pages at once must be for more requests at a time to help.
+const MIN_SPEEDUP: f64 = 1.2;
+
+/// How fast plus.pearson.com answers from here.
+pub struct Report {
+    /// The median time a HEAD request of a page took.
+    pub rtt: Duration,
+    /// Bytes per second downloading pages one after another.
+    pub sequential: f64,
+    /// Bytes per second downloading the same pages all at once.
+    pub concurrent: f64,
+    /// How many pages were downloaded all at once.
+    pub pages: usize,
+    /// The number of requests at a time that keeps the connection busy while waiting on the round
+    /// trips.
+    pub concurrency: usize,
+}
+
+/// Returns the median of the round trip times.
+pub fn median(mut rtts: Vec<Duration>) -> Duration {
+    rtts.sort_unstable();
+    rtts.get(rtts.len() / 2).copied().unwrap_or_default()
+}
+
+/// Returns how many requests at a time keep the connection busy, when a page takes `per_page`
+/// to download of which `rtt` is spent waiting for its bytes to start arriving, and downloading
+/// several at once was `speedup` times as fast as one after another.
+pub fn concurrency(rtt: Duration, per_page: Duration, speedup: f64) -> usize {
+    if speedup < MIN_SPEEDUP {
+        // the host or the link is as fast as it goes already
+        return 2;
+    }
+    let transfer = per_page.saturating_sub(rtt).max(Duration::from_millis(1));
+    let concurrency = (per_page.as_secs_f64() / transfer.as_secs_f64()).ceil() as usize;
+    concurrency.clamp(1, MAX_CONCURRENCY)
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Round trip time: {} ms", self.rtt.as_millis())?;
+        writeln!(
+            f,
+            "Throughput: {:.1} MB/s one page at a time, {:.1} MB/s with {} at a time",
+            self.sequential / 1_000_000.0,
+            self.concurrent / 1_000_000.0,
+            self.pages
+        )?;
+        write!(f, "Recommended: --max-requests {}", self.concurrency)
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+/// What an extraction reports as it goes.
+pub enum Event {
+    /// A page started downloading.
+    PageStarted(u32),
+    /// A page was downloaded, before it is added to the document.
+    PageDownloaded(u32),
+    /// A page was added to the document.
+    PageFinished(u32),
+    /// A page failed to download and is requested again.
+    Retry { page: u32, error: String },
+    /// Something went wrong that the extraction carries on without, such as a text layer it
+    /// leaves out.
+    Warning(String),
+    /// Anything else, such as the document being saved.
+    Info(String),
+}
+
+/// Receives the events of an extraction, from whichever thread it runs on. A channel can be
+/// sent to from a callback to consume them as a stream instead.
+pub type Callback = Arc<dyn Fn(&Event) + Send + Sync>;
+
+/// Prints the events as the command line shows them, leaving out where pages start and finish.
+pub fn print(event: &Event) {
+    match event {
+        Event::PageStarted(_) | Event::PageFinished(_) => {}
+        Event::PageDownloaded(page) => println!("Downloaded page {:04}.", page),
+        Event::Retry { page, error } => println!("Retrying page {:04}: {}", page, error),
+        Event::Warning(message) | Event::Info(message) => println!("{}", message),
+    }
+}
+
+/// Renders an event as a line of JSON, such as `{"event":"page_finished","page":3}`.
+pub fn to_json(event: &Event) -> String {
+    let value = match event {
+        Event::PageStarted(page) => sonic_rs::json!({ "event": "page_started", "page": page }),
+        Event::PageDownloaded(page) => {
+            sonic_rs::json!({ "event": "page_downloaded", "page": page })
+        }
+        Event::PageFinished(page) => sonic_rs::json!({ "event": "page_finished", "page": page }),
+        Event::Retry { page, error } => {
+            sonic_rs::json!({ "event": "retry", "page": page, "error": error })
+        }
+        Event::Warning(message) => sonic_rs::json!({ "event": "warning", "message": message }),
+        Event::Info(message) => sonic_rs::json!({ "event": "info", "message": message }),
+    };
+    value.to_string()
+}
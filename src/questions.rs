@@ -0,0 +1,134 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use lopdf::Document;
+use sonic_rs::Serialize;
+
+use crate::{split, toc::Toc, Extraction};
+
+/// The headings end-of-chapter questions start under.
+const HEADINGS: [&str; 8] = [
+    "review questions",
+    "questions",
+    "exercises",
+    "problems",
+    "practice problems",
+    "chapter review",
+    "end-of-chapter questions",
+    "check your understanding",
+];
+
+#[derive(Serialize)]
+pub struct Question {
+    /// The title of the chapter of the TOC the question is in, if there is one.
+    pub chapter: Option<String>,
+    pub page: u32,
+    pub number: u32,
+    pub text: String,
+}
+
+/// Finds the numbered questions such as "3. Why…" under a review questions or exercises heading
+/// until the end of each chapter, or of the book without a TOC, and returns them along with the
+/// pages they are on.
+pub fn find(extraction: &Extraction, toc: Option<&Toc>) -> (Vec<Question>, Vec<u32>) {
+    let end = extraction.page_texts.len() as u32;
+    let chapters = match toc {
+        Some(toc) => toc
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, chapter)| {
+                let next = toc.entries.get(i + 1).map_or(end, |next| next.page);
+                (
+                    Some(chapter.title.clone()),
+                    chapter.page..next.max(chapter.page),
+                )
+            })
+            .collect(),
+        None => vec![(None, 0..end)],
+    };
+    let mut questions = Vec::<Question>::new();
+    let mut pages = Vec::new();
+    for (chapter, range) in chapters {
+        let mut in_questions = false;
+        let mut started = false;
+        for page in range {
+            let Some(text) = extraction.page_texts.get(page as usize) else {
+                break;
+            };
+            if extraction.removed_pages.contains(&page) {
+                continue;
+            }
+            let mut on_page = false;
+            let mut continues = false;
+            for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+                if HEADINGS.contains(&line.to_lowercase().trim_end_matches(':')) {
+                    in_questions = true;
+                    continue;
+                }
+                if !in_questions {
+                    continue;
+                }
+                if let Some((number, text)) = numbered(line) {
+                    continues = !text.ends_with(['?', '.', '!', ':']);
+                    questions.push(Question {
+                        chapter: chapter.clone(),
+                        page,
+                        number,
+                        text: text.to_string(),
+                    });
+                    on_page = true;
+                } else if continues {
+                    // a question can run over several lines
+                    let question = questions.last_mut().unwrap();
+                    question.text.push(' ');
+                    question.text.push_str(line);
+                    continues = !line.ends_with(['?', '.', '!', ':']);
+                } else {
+                    continues = false;
+                }
+            }
+            if on_page {
+                pages.push(page);
+            } else {
+                // the questions ended on an earlier page
+                in_questions &= !started;
+            }
+            started |= on_page;
+        }
+    }
+    (questions, pages)
+}
+
+/// Splits a line such as "12. What is…" or "12) What is…" into its number and text.
+fn numbered(line: &str) -> Option<(u32, &str)> {
+    let digits = line.find(|char: char| !char.is_ascii_digit())?;
+    let number = line[..digits].parse().ok()?;
+    let text = line[digits..].strip_prefix(['.', ')'])?;
+    text.starts_with(' ').then(|| (number, text.trim()))
+}
+
+/// Writes the questions as a JSON array of `{ "chapter", "page", "number", "text" }` objects.
+pub fn write_json(path: &Path, questions: &[Question]) -> Result<()> {
+    fs::write(path, sonic_rs::to_string(questions)?)?;
+    Ok(())
+}
+
+/// Writes the pages of the document at `document_path` that hold the questions to `path`, as a
+/// document to print on its own.
+pub fn write_pdf(
+    path: &Path,
+    document_path: &Path,
+    extraction: &Extraction,
+    pages: &[u32],
+) -> Result<()> {
+    let mut document = Document::load(document_path)?;
+    let page_ids = document.get_pages();
+    let kept = pages
+        .iter()
+        .filter_map(|&page| page_ids.get(&extraction.pdf_page(page)?).copied())
+        .collect::<Vec<_>>();
+    split::keep_pages(&mut document, &kept)?;
+    document.save(path)?;
+    Ok(())
+}
@@ -0,0 +1,299 @@
+use std::{
+    collections::HashSet,
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{exit, metrics, notify};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// The extensions of job files, in any state.
+const JOB_EXTENSIONS: [&str; 5] = ["json", "queued", "running", "done", "failed"];
+
+/// Watches `dir` for job files and runs up to `concurrency` of them at a time, forever.
+///
+/// A job is a `name.json` file holding the command line arguments of an extraction as a JSON
+/// array, run with `dir` as the working directory. It is renamed to `name.queued`, `name.running`
+/// and finally `name.done` or `name.failed` as it goes, with its output in `name.log`. Jobs left
+/// queued or running by a previous server are started over.
+///
+/// With `max_requests`, the requests the jobs make at a time are capped by giving each of them an
+/// even share of it, so running several books at once doesn't trip rate limits. The share is fixed
+/// per job by `concurrency` rather than handed out from what the running jobs leave, and a job
+/// asking for fewer with its own `--max-requests` keeps its lower limit.
+///
+/// Jobs of the same book, such as the books of a bundle that share one, take turns and share the
+/// cache in `dir/cache`, so its assets are downloaded once. A job that would write the same
+/// document as a finished one gets a hard link to it instead.
+///
+/// Every job keeps its counters in `name.metrics`, and the jobs that finish are counted in `jobs`
+/// and reported to `targets`.
+pub fn serve(
+    dir: &Path,
+    concurrency: usize,
+    max_requests: Option<usize>,
+    jobs: Arc<metrics::Jobs>,
+    targets: Arc<notify::Targets>,
+) -> Result<()> {
+    let concurrency = concurrency.max(1);
+    let share = max_requests.map(|max_requests| (max_requests / concurrency).max(1));
+    let (sender, receiver) = mpsc::channel::<PathBuf>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let books = Arc::new(Books::default());
+    for _ in 0..concurrency {
+        let receiver = receiver.clone();
+        let jobs = jobs.clone();
+        let targets = targets.clone();
+        let books = books.clone();
+        thread::spawn(move || loop {
+            // release the lock before running the job so the other workers can take the next one
+            let job = receiver.lock().unwrap().recv();
+            let Ok(job) = job else {
+                break;
+            };
+            if let Err(error) = run(&job, share, &jobs, &targets, &books) {
+                println!("Job {} failed to run: {}", job.display(), error);
+            }
+        });
+    }
+    let mut starting = true;
+    loop {
+        let mut jobs = fs::read_dir(dir)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        jobs.sort();
+        for job in jobs {
+            let extension = job.extension().unwrap_or_default();
+            let pending = extension == "json"
+                || (starting && (extension == "queued" || extension == "running"));
+            if pending {
+                let queued = job.with_extension("queued");
+                fs::rename(&job, &queued)?;
+                println!("Queued {}.", queued.display());
+                sender.send(queued)?;
+            }
+        }
+        starting = false;
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// The books jobs are running for, by UUID.
+#[derive(Default)]
+struct Books {
+    running: Mutex<HashSet<String>>,
+    finished: Condvar,
+}
+
+impl Books {
+    /// Waits until no other job runs for the book `uuid`, and marks it running until the turn is
+    /// dropped.
+    fn turn(&self, uuid: &str) -> Turn<'_> {
+        let mut running = self.running.lock().unwrap();
+        while running.contains(uuid) {
+            running = self.finished.wait(running).unwrap();
+        }
+        running.insert(uuid.to_string());
+        Turn {
+            books: self,
+            uuid: uuid.to_string(),
+        }
+    }
+}
+
+struct Turn<'a> {
+    books: &'a Books,
+    uuid: String,
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        self.books.running.lock().unwrap().remove(&self.uuid);
+        self.books.finished.notify_all();
+    }
+}
+
+/// Returns the value of the option called any of `names` in command line arguments, given as
+/// `-p 123`, `--product-id 123` or `--product-id=123`.
+pub fn arg<'a>(args: &'a [String], names: &[&str]) -> Option<&'a str> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if names.contains(&arg.as_str()) {
+            return args.next().map(String::as_str);
+        }
+        let value = names.iter().find_map(|name| {
+            arg.strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('='))
+                .filter(|_| name.starts_with("--"))
+        });
+        if value.is_some() {
+            return value;
+        }
+    }
+    None
+}
+
+/// The document a job writes, relative to the queue directory.
+pub fn output(args: &[String]) -> &str {
+    // the default of --output-path
+    arg(args, &["-o", "--output-path"]).unwrap_or("out.pdf")
+}
+
+/// Returns the arguments without the output path, which tell what a job writes apart from where.
+fn without_output(args: &[String]) -> Vec<&str> {
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output-path" => {
+                args.next();
+            }
+            arg if arg.starts_with("--output-path=") => {}
+            arg => rest.push(arg),
+        }
+    }
+    rest
+}
+
+/// Returns the arguments with their `--max-requests` lowered to `share`, or set to it if they have
+/// none.
+fn with_max_requests(args: &[String], share: usize) -> Vec<String> {
+    let own = arg(args, &["--max-requests"]).and_then(|value| value.parse::<usize>().ok());
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-requests" => {
+                args.next();
+            }
+            arg if arg.starts_with("--max-requests=") => {}
+            arg => rest.push(arg.to_string()),
+        }
+    }
+    let max_requests = own.map_or(share, |own| own.min(share));
+    rest.extend(["--max-requests".to_string(), max_requests.to_string()]);
+    rest
+}
+
+/// Returns the arguments of the other jobs in `dir` along with their extensions.
+fn other_jobs(dir: &Path, job: &Path) -> Result<Vec<(String, Vec<String>)>> {
+    let mut others = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+        if !JOB_EXTENSIONS.contains(&extension.as_ref()) || path.file_stem() == job.file_stem() {
+            continue;
+        }
+        let Ok(args) = sonic_rs::from_str::<Vec<String>>(&fs::read_to_string(&path)?) else {
+            continue;
+        };
+        others.push((extension.into_owned(), args));
+    }
+    Ok(others)
+}
+
+/// Links the document of a finished job with the same arguments as `args` but for the output
+/// path to the output of `args`, and returns where it came from, unless there is none or the
+/// output is taken.
+fn link_duplicate(
+    dir: &Path,
+    args: &[String],
+    others: &[(String, Vec<String>)],
+) -> Option<PathBuf> {
+    let target = dir.join(output(args));
+    if target.exists() {
+        return None;
+    }
+    let key = without_output(args);
+    let source = others
+        .iter()
+        .filter(|(extension, other)| extension == "done" && without_output(other) == key)
+        .map(|(_, other)| dir.join(output(other)))
+        .find(|source| source.is_file())?;
+    let linked = fs::hard_link(&source, &target).or_else(|_| fs::copy(&source, &target).map(drop));
+    linked.ok().map(|_| source)
+}
+
+fn run(
+    job: &Path,
+    max_requests: Option<usize>,
+    jobs: &metrics::Jobs,
+    targets: &notify::Targets,
+    books: &Books,
+) -> Result<()> {
+    let dir = job.parent().unwrap_or(Path::new("."));
+    let args = sonic_rs::from_str::<Vec<String>>(&fs::read_to_string(job)?);
+    let uuid = args
+        .as_ref()
+        .ok()
+        .and_then(|args| arg(args, &["-u", "--uuid"]));
+    let _turn = uuid.map(|uuid| books.turn(uuid));
+    let others = other_jobs(dir, job)?;
+    let start = Instant::now();
+    let running = job.with_extension("running");
+    fs::rename(job, &running)?;
+    let mut log = fs::File::create(job.with_extension("log"))?;
+    let succeeded = match &args {
+        Ok(args) => match link_duplicate(dir, args, &others) {
+            Some(source) => {
+                writeln!(
+                    log,
+                    "Linked {} to {}, which a finished job wrote with the same arguments.",
+                    output(args),
+                    source.display()
+                )?;
+                true
+            }
+            None => {
+                let mut args = args.clone();
+                if let Some(share) = max_requests {
+                    args = with_max_requests(&args, share);
+                }
+                let shared = others
+                    .iter()
+                    .any(|(_, other)| arg(other, &["-u", "--uuid"]) == uuid);
+                if shared && arg(&args, &["--cache"]).is_none() {
+                    let cache = dir.join("cache").to_string_lossy().into_owned();
+                    args.extend(["--cache".to_string(), cache]);
+                }
+                Command::new(env::current_exe()?)
+                    .args(args)
+                    .current_dir(dir)
+                    .env(metrics::ENV_VAR, job.with_extension("metrics"))
+                    .stdin(Stdio::null())
+                    .stdout(log.try_clone()?)
+                    .stderr(log)
+                    .status()
+                    // a partial success still wrote the document
+                    .map(|status| status.success() || status.code() == Some(exit::PARTIAL))?
+            }
+        },
+        Err(error) => {
+            fs::write(
+                job.with_extension("log"),
+                format!("Invalid job file: {error}\n"),
+            )?;
+            false
+        }
+    };
+    let finished = job.with_extension(if succeeded { "done" } else { "failed" });
+    fs::rename(&running, &finished)?;
+    jobs.finish(succeeded, start.elapsed(), &job.with_extension("metrics"));
+    println!("Finished {}.", finished.display());
+    targets.send(&notify::Report {
+        job: &job.file_stem().unwrap_or_default().to_string_lossy(),
+        args: args.as_deref().unwrap_or_default(),
+        succeeded,
+        duration: start.elapsed(),
+        metrics: &job.with_extension("metrics"),
+        dir: &fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf()),
+    });
+    Ok(())
+}
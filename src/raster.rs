@@ -0,0 +1,185 @@
+use std::io::{Cursor, Write};
+
+use anyhow::Result;
+use flate2::{write::ZlibEncoder, Compression};
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngDecoder},
+    DynamicImage, GrayImage, Luma, Rgb, RgbImage,
+};
+use lopdf::{dictionary, Stream};
+
+/// How page images are compressed inside the document.
+#[derive(Clone, Copy, Default)]
+pub enum Encoding {
+    /// The raw samples, deflated.
+    #[default]
+    Lossless,
+    /// A baseline JPEG of the given quality from 1 to 100.
+    Jpeg { quality: u8 },
+}
+
+/// A page image encoded for embedding.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    color_space: &'static str,
+    filter: &'static str,
+    /// Whether the rows are PNG filtered, as in the IDAT data of a PNG.
+    predicted: bool,
+    data: Vec<u8>,
+}
+
+impl Image {
+    pub fn into_stream(self) -> Stream {
+        let mut dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => self.width as i64,
+            "Height" => self.height as i64,
+            "ColorSpace" => self.color_space,
+            "BitsPerComponent" => 8,
+            "Filter" => self.filter,
+        };
+        if self.predicted {
+            let colors = if self.color_space == "DeviceRGB" {
+                3
+            } else {
+                1
+            };
+            dict.set(
+                "DecodeParms",
+                dictionary! {
+                    "Predictor" => 15,
+                    "Colors" => colors,
+                    "BitsPerComponent" => 8,
+                    "Columns" => self.width as i64,
+                },
+            );
+        }
+        // the data is already compressed
+        Stream::new(dict, self.data).with_compression(false)
+    }
+}
+
+/// Decodes a page image into 8-bit gray or RGB, flattening any transparency onto white, and
+/// encodes it for embedding. Paletted, 16-bit and transparent PNGs have no direct equivalent
+/// among the image types of PDF.
+///
+/// Lossless 8-bit gray and RGB PNGs are embedded as they are instead, since PDF reads their
+/// compressed data with the same PNG predictors.
+pub fn decode(png: &[u8], encoding: Encoding) -> Result<Image> {
+    if let Encoding::Lossless = encoding {
+        if let Some(image) = pass_through(png) {
+            return Ok(image);
+        }
+    }
+    let decoder = PngDecoder::new(Cursor::new(png))?;
+    encode(DynamicImage::from_decoder(decoder)?, encoding)
+}
+
+/// Reads the width and height of a PNG from its IHDR chunk, which must come first, without
+/// decoding anything else.
+pub fn png_dimensions(png: &[u8]) -> Option<(u32, u32)> {
+    if !png.starts_with(b"\x89PNG\r\n\x1a\n") || png.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let w = u32::from_be_bytes(png.get(16..20)?.try_into().unwrap());
+    let h = u32::from_be_bytes(png.get(20..24)?.try_into().unwrap());
+    Some((w, h))
+}
+
+/// Converts an image into 8-bit gray or RGB like [`decode`] and encodes it for embedding.
+pub fn encode(image: DynamicImage, encoding: Encoding) -> Result<Image> {
+    let image = match image {
+        image @ (DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_)) => image,
+        image if image.color().has_color() && image.color().has_alpha() => {
+            let rgba = image.to_rgba8();
+            DynamicImage::ImageRgb8(RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+                let [r, g, b, a] = rgba.get_pixel(x, y).0;
+                Rgb([r, g, b].map(|c| over_white(c, a)))
+            }))
+        }
+        image if image.color().has_color() => DynamicImage::ImageRgb8(image.to_rgb8()),
+        image if image.color().has_alpha() => {
+            let luma_alpha = image.to_luma_alpha8();
+            let (w, h) = luma_alpha.dimensions();
+            DynamicImage::ImageLuma8(GrayImage::from_fn(w, h, |x, y| {
+                let [l, a] = luma_alpha.get_pixel(x, y).0;
+                Luma([over_white(l, a)])
+            }))
+        }
+        image => DynamicImage::ImageLuma8(image.to_luma8()),
+    };
+    let (filter, data) = match encoding {
+        Encoding::Lossless => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(image.as_bytes())?;
+            ("FlateDecode", encoder.finish()?)
+        }
+        Encoding::Jpeg { quality } => {
+            let mut data = Vec::new();
+            JpegEncoder::new_with_quality(&mut data, quality).encode_image(&image)?;
+            ("DCTDecode", data)
+        }
+    };
+    Ok(Image {
+        width: image.width(),
+        height: image.height(),
+        color_space: if image.color().has_color() {
+            "DeviceRGB"
+        } else {
+            "DeviceGray"
+        },
+        filter,
+        predicted: false,
+        data,
+    })
+}
+
+/// Returns the compressed data of a non-interlaced, opaque 8-bit gray or RGB PNG as an image.
+fn pass_through(png: &[u8]) -> Option<Image> {
+    let (width, height) = png_dimensions(png)?;
+    // bit depth, color type, compression, filter and interlace method
+    let (color_space, channels) = match png.get(24..29)? {
+        [8, 0, 0, 0, 0] => ("DeviceGray", 1),
+        [8, 2, 0, 0, 0] => ("DeviceRGB", 3),
+        _ => return None,
+    };
+    // every row of the decompressed data starts with the byte of its filter type
+    (width as usize)
+        .checked_mul(channels)?
+        .checked_add(1)?
+        .checked_mul(height as usize)
+        .filter(|_| width > 0 && height > 0)?;
+    let mut data = Vec::new();
+    let mut offset = 8;
+    while offset + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+        let kind = &png[offset + 4..offset + 8];
+        let end = offset.checked_add(8)?.checked_add(length)?;
+        let chunk = png.get(offset + 8..end)?;
+        match kind {
+            b"IDAT" => data.extend(chunk),
+            // a transparent color has to be flattened onto white like an alpha channel
+            b"tRNS" => return None,
+            b"IEND" => break,
+            _ => {}
+        }
+        offset = end.checked_add(4)?;
+    }
+    if data.is_empty() {
+        return None;
+    }
+    Some(Image {
+        width,
+        height,
+        color_space,
+        filter: "FlateDecode",
+        predicted: true,
+        data,
+    })
+}
+
+fn over_white(channel: u8, alpha: u8) -> u8 {
+    ((channel as u32 * alpha as u32 + 255 * (255 - alpha as u32) + 127) / 255) as u8
+}
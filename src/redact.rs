@@ -0,0 +1,69 @@
+use std::{
+    panic,
+    sync::{Mutex, Once},
+};
+
+/// The shortest cookie value worth hiding; shorter ones are flags like `1` or `true`.
+const MIN_SECRET_LENGTH: usize = 8;
+
+/// The secrets of the current session, left out of panics and printed errors.
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Replaces every occurrence of the secrets in `text` with `[redacted]`.
+pub fn redact(text: &str, secrets: &[String]) -> String {
+    let mut text = text.to_string();
+    for secret in secrets {
+        text = text.replace(secret.as_str(), "[redacted]");
+    }
+    text
+}
+
+/// Returns the cookie and auth token along with every cookie value in them, longest first so a
+/// whole header is redacted before its parts.
+pub fn secrets(cookie: &str, auth_token: &str) -> Vec<String> {
+    let mut secrets = vec![cookie.to_string(), auth_token.to_string()];
+    secrets.extend(
+        cookie
+            .split(';')
+            .filter_map(|pair| pair.split_once('=').map(|(_, value)| value.trim()))
+            .map(str::to_string),
+    );
+    secrets.retain(|secret| secret.len() >= MIN_SECRET_LENGTH);
+    secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    secrets
+}
+
+/// Makes panics, which print the error chains of every failed `unwrap`, leave the secrets out,
+/// replacing the secrets of a previous session.
+pub fn install_panic_hook(secrets: Vec<String>) {
+    *SECRETS.lock().unwrap_or_else(|error| error.into_inner()) = secrets;
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(install);
+}
+
+/// Returns `text` with the secrets of the current session replaced.
+pub fn redact_session(text: &str) -> String {
+    redact(
+        text,
+        &SECRETS.lock().unwrap_or_else(|error| error.into_inner()),
+    )
+}
+
+fn install() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let location = info
+            .location()
+            .map(|location| format!(" at {}", location))
+            .unwrap_or_default();
+        let payload = info
+            .payload()
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| info.payload().downcast_ref::<&str>().copied());
+        match payload {
+            Some(message) => eprintln!("panicked{}:\n{}", location, redact_session(message)),
+            None => default_hook(info),
+        }
+    }));
+}
@@ -0,0 +1,134 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use anyhow::{anyhow, bail, Result};
+use sonic_rs::{json, Deserialize};
+
+use crate::{hook, Text};
+
+/// A long-running user command that is asked about every page, one line of JSON per hook and
+/// one line of JSON per reply:
+///
+/// - `{"hook":"on_page_image","page":12,"width":850,"height":1100}`, answered with
+///   `{"skip":true}` to leave the page out or `{"stamp":"..."}` to draw text on it.
+/// - `{"hook":"on_page_text","page":12,"runs":["..."]}`, answered with `{"runs":["..."]}` to
+///   replace the text of the page, laid out at the positions of the original characters.
+/// - `{"hook":"on_finish","pages":345}`, answered with `{}` before the command is closed.
+pub struct Script {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+#[derive(Default, Deserialize)]
+pub struct ImageReply {
+    #[serde(default)]
+    pub skip: bool,
+    #[serde(default)]
+    pub stamp: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct TextReply {
+    #[serde(default)]
+    runs: Option<Vec<String>>,
+}
+
+impl Script {
+    pub fn spawn(command_line: &str) -> Result<Self> {
+        let mut words = hook::split_words(command_line)?.into_iter();
+        let program = words.next().ok_or_else(|| anyhow!("the script is empty"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        Ok(Self {
+            stdin: child.stdin.take(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+        })
+    }
+
+    pub fn on_page_image(&mut self, page: u32, (w, h): (u32, u32)) -> Result<ImageReply> {
+        let request = json!({ "hook": "on_page_image", "page": page, "width": w, "height": h });
+        Ok(sonic_rs::from_str(
+            &self.call(&sonic_rs::to_string(&request)?)?,
+        )?)
+    }
+
+    /// Lets the script rewrite the text of a page.
+    pub fn on_page_text(&mut self, page: u32, texts: &mut [Text]) -> Result<()> {
+        let runs = texts
+            .iter()
+            .map(|text| {
+                text.stream
+                    .iter()
+                    .filter_map(|&(_, _, _, _, char)| char::from_u32(char))
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        let request = json!({ "hook": "on_page_text", "page": page, "runs": runs });
+        let reply: TextReply = sonic_rs::from_str(&self.call(&sonic_rs::to_string(&request)?)?)?;
+        let Some(runs) = reply.runs else {
+            return Ok(());
+        };
+        if runs.len() != texts.len() {
+            bail!(
+                "the script returned {} runs for the {} of page {}",
+                runs.len(),
+                texts.len(),
+                page
+            );
+        }
+        for (text, run) in texts.iter_mut().zip(runs) {
+            relayout(text, &run);
+        }
+        Ok(())
+    }
+
+    /// Tells the script the document is done and waits for it to exit.
+    pub fn on_finish(mut self, pages: u32) -> Result<()> {
+        let request = json!({ "hook": "on_finish", "pages": pages });
+        self.call(&sonic_rs::to_string(&request)?)?;
+        drop(self.stdin.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            bail!("the script exited with {}", status);
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, request: &str) -> Result<String> {
+        let stdin = self.stdin.as_mut().unwrap();
+        writeln!(stdin, "{}", request)?;
+        stdin.flush()?;
+        let mut reply = String::new();
+        if self.stdout.read_line(&mut reply)? == 0 {
+            bail!("the script exited before replying to {}", request);
+        }
+        Ok(reply)
+    }
+}
+
+/// Replaces the characters of `text` with `run`, keeping the position of every original
+/// character and continuing past the last one with its advance.
+fn relayout(text: &mut Text, run: &str) {
+    let Some(&(last_x, y, w, h, _)) = text.stream.last() else {
+        return;
+    };
+    let original = text.stream.clone();
+    text.stream = run
+        .chars()
+        .enumerate()
+        .map(|(i, char)| match original.get(i) {
+            Some(&(x, y, w, h, _)) => (x, y, w, h, char as u32),
+            None => {
+                let x = last_x + w * (i + 1 - original.len()) as f32;
+                (x, y, w, h, char as u32)
+            }
+        })
+        .collect();
+}
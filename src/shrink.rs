@@ -0,0 +1,134 @@
+use std::io::Read;
+
+use anyhow::Result;
+use flate2::read::ZlibDecoder;
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, load_from_memory_with_format, DynamicImage,
+    GrayImage, ImageFormat, RgbImage,
+};
+use lopdf::{filters::png, Dictionary, Document, Object, ObjectId};
+
+/// The scales and JPEG qualities tried in turn until a page image fits its share of the budget.
+const SCALES: [f32; 5] = [1.0, 0.75, 0.5, 0.35, 0.25];
+const QUALITIES: [u8; 4] = [85, 70, 55, 40];
+
+/// Re-encodes the page images as JPEGs, lowering the quality and then the resolution of each one
+/// until the saved document fits in `max_size` bytes. Every image gets a share of the budget left
+/// by everything else in proportion to its pixel count, so the savings on simple pages go to the
+/// busy ones.
+pub fn fit(document: &mut Document, max_size: u64) -> Result<()> {
+    let images = document
+        .objects
+        .iter()
+        .filter(|(_, object)| is_image(object))
+        .map(|(&id, _)| id)
+        .collect::<Vec<_>>();
+    let mut saved = Vec::new();
+    document.save_to(&mut saved)?;
+    let image_size = images
+        .iter()
+        .map(|&id| Ok(document.get_object(id)?.as_stream()?.content.len() as u64))
+        .sum::<Result<u64>>()?;
+    if saved.len() as u64 <= max_size {
+        return Ok(());
+    }
+    let mut budget = max_size.saturating_sub(saved.len() as u64 - image_size);
+    let mut pixels = images
+        .iter()
+        .map(|&id| dimensions(document, id).map(|(w, h)| w * h))
+        .sum::<Result<u64>>()?;
+    for id in images {
+        let (w, h) = dimensions(document, id)?;
+        let share = budget * (w * h) / pixels.max(1);
+        pixels -= w * h;
+        let stream = document.get_object_mut(id)?.as_stream_mut()?;
+        if stream.content.len() as u64 <= share {
+            budget -= stream.content.len() as u64;
+            continue;
+        }
+        let Some(image) = decode(&stream.content, &stream.dict, w, h)? else {
+            budget = budget.saturating_sub(stream.content.len() as u64);
+            continue;
+        };
+        let mut encoded = Vec::new();
+        let mut size = (w, h);
+        'ladder: for scale in SCALES {
+            let scaled = if scale < 1.0 {
+                let (w, h) = ((w as f32 * scale) as u32, (h as f32 * scale) as u32);
+                image.resize_exact(w.max(1), h.max(1), FilterType::Triangle)
+            } else {
+                image.clone()
+            };
+            for quality in QUALITIES {
+                encoded.clear();
+                JpegEncoder::new_with_quality(&mut encoded, quality).encode_image(&scaled)?;
+                size = (scaled.width() as u64, scaled.height() as u64);
+                if encoded.len() as u64 <= share {
+                    break 'ladder;
+                }
+            }
+        }
+        budget = budget.saturating_sub(encoded.len() as u64);
+        stream.dict.set("Width", size.0 as i64);
+        stream.dict.set("Height", size.1 as i64);
+        stream.dict.set("BitsPerComponent", 8);
+        stream.dict.set("Filter", "DCTDecode");
+        stream.dict.remove(b"DecodeParms");
+        stream.set_content(encoded);
+    }
+    Ok(())
+}
+
+fn is_image(object: &Object) -> bool {
+    object
+        .as_stream()
+        .and_then(|stream| stream.dict.get(b"Subtype"))
+        .and_then(Object::as_name)
+        .is_ok_and(|subtype| subtype == b"Image")
+}
+
+fn dimensions(document: &Document, id: ObjectId) -> Result<(u64, u64)> {
+    let dict = &document.get_object(id)?.as_stream()?.dict;
+    let w = dict.get(b"Width")?.as_i64()?;
+    let h = dict.get(b"Height")?.as_i64()?;
+    Ok((w as u64, h as u64))
+}
+
+/// Decodes the 8-bit gray or RGB samples of a page image, PNG predicted or not, or a JPEG,
+/// returning `None` for anything else.
+fn decode(content: &[u8], dict: &Dictionary, w: u64, h: u64) -> Result<Option<DynamicImage>> {
+    let filter = match dict.get(b"Filter").ok() {
+        Some(Object::Array(filters)) if filters.len() == 1 => filters[0].as_name().ok(),
+        Some(filter) => filter.as_name().ok(),
+        None => None,
+    };
+    let samples = match filter {
+        Some(b"DCTDecode") => {
+            return Ok(Some(load_from_memory_with_format(
+                content,
+                ImageFormat::Jpeg,
+            )?));
+        }
+        Some(b"FlateDecode") => {
+            let mut samples = Vec::new();
+            ZlibDecoder::new(content).read_to_end(&mut samples)?;
+            let params = dict.get(b"DecodeParms").and_then(Object::as_dict).ok();
+            let param = |key: &[u8]| params.and_then(|params| params.get(key).ok()?.as_i64().ok());
+            if param(b"Predictor").is_some_and(|predictor| predictor >= 10) {
+                let colors = param(b"Colors").unwrap_or(1) as usize;
+                png::decode_frame(&samples, colors, w as usize)?
+            } else {
+                samples
+            }
+        }
+        None => content.to_vec(),
+        Some(_) => return Ok(None),
+    };
+    let (w, h) = (w as u32, h as u32);
+    let image = if samples.len() as u64 == w as u64 * h as u64 {
+        GrayImage::from_raw(w, h, samples).map(DynamicImage::ImageLuma8)
+    } else {
+        RgbImage::from_raw(w, h, samples).map(DynamicImage::ImageRgb8)
+    };
+    Ok(image)
+}
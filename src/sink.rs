@@ -0,0 +1,399 @@
+use std::io::Write;
+
+use anyhow::Result;
+use lopdf::{
+    content::{Content, Operation},
+    dictionary, Dictionary, Document, Object, ObjectId, Stream,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::{catalog::text_string, objstm, raster::Image, spill::Spill};
+
+/// Points per millimeter.
+pub const MM: f32 = 72.0 / 25.4;
+
+/// The resource name of the font every page writes with, Times Roman in the WinAnsi encoding.
+const FONT: &str = "F1";
+
+/// A page drawn as content stream operations, along with the images and layers they use.
+pub struct Page {
+    /// The width and height in points.
+    size: (f32, f32),
+    operations: Vec<Operation>,
+    images: Vec<Image>,
+    layers: Vec<String>,
+}
+
+impl Page {
+    pub fn new(size: (f32, f32)) -> Self {
+        Self {
+            size,
+            operations: Vec::new(),
+            images: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn add_operation(&mut self, operation: Operation) {
+        self.operations.push(operation);
+    }
+
+    /// Draws `image` stretched over the rectangle of `w` by `h` points whose lower left corner is
+    /// at `x`, `y`.
+    pub fn draw_image(&mut self, image: Image, (x, y, w, h): (f32, f32, f32, f32)) {
+        let name = format!("Im{}", self.images.len());
+        self.images.push(image);
+        self.operations.extend([
+            Operation::new("q", vec![]),
+            Operation::new(
+                "cm",
+                vec![w.into(), 0.into(), 0.into(), h.into(), x.into(), y.into()],
+            ),
+            Operation::new("Do", vec![name.into()]),
+            Operation::new("Q", vec![]),
+        ]);
+    }
+
+    /// Opens a sequence of content a viewer toggles along with the layer called `name` on every
+    /// other page.
+    pub fn begin_layer(&mut self, name: &str) {
+        let property = format!("L{}", self.layers.len());
+        self.layers.push(name.to_string());
+        self.operations
+            .push(Operation::new("BDC", vec!["OC".into(), property.into()]));
+    }
+
+    pub fn end_layer(&mut self) {
+        self.operations.push(Operation::new("EMC", vec![]));
+    }
+
+    /// Opens a text object in the font at `size` points, filled or invisible.
+    pub fn begin_text(&mut self, size: f32, visible: bool) {
+        self.operations.extend([
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec![FONT.into(), size.into()]),
+            Operation::new("Tr", vec![if visible { 0 } else { 3 }.into()]),
+        ]);
+    }
+
+    pub fn set_text_matrix(&mut self, matrix: [f32; 6]) {
+        let operands = matrix.into_iter().map(Object::from).collect();
+        self.operations.push(Operation::new("Tm", operands));
+    }
+
+    pub fn set_text_position(&mut self, x: f32, y: f32) {
+        self.operations
+            .push(Operation::new("Td", vec![x.into(), y.into()]));
+    }
+
+    /// Writes `text` at the current position, leaving out what WinAnsi can't encode.
+    pub fn write_text(&mut self, text: &str) {
+        let text = Document::encode_text(Some("WinAnsiEncoding"), text);
+        self.operations
+            .push(Operation::new("Tj", vec![Object::string_literal(text)]));
+    }
+
+    /// Sets the RGB color text and shapes drawn from here on are filled with.
+    pub fn set_fill_color(&mut self, [r, g, b]: [f32; 3]) {
+        self.operations
+            .push(Operation::new("rg", vec![r.into(), g.into(), b.into()]));
+    }
+
+    pub fn end_text(&mut self) {
+        self.operations.push(Operation::new("ET", vec![]));
+    }
+}
+
+/// Where the pages of a document go as they are drawn.
+pub trait PdfSink {
+    /// Adds `page` after the ones added before.
+    fn add_page(&mut self, page: Page) -> Result<()>;
+
+    /// Completes the document, returning it unless it was already written out.
+    fn finish(self: Box<Self>) -> Result<Option<Document>>;
+
+    /// The bytes of page contents held in memory until the document is complete.
+    fn buffered(&self) -> u64 {
+        0
+    }
+}
+
+/// The page tree and the objects every page shares, numbered ahead of the pages.
+struct Shared {
+    info: Dictionary,
+    next_id: u32,
+    pages_id: ObjectId,
+    font_id: ObjectId,
+    pages: Vec<ObjectId>,
+    /// The optional content group of every layer name, in the order they were first used.
+    layers: Vec<(String, ObjectId)>,
+}
+
+impl Shared {
+    fn new(title: &str, author: Option<&str>) -> Self {
+        let producer = concat!("pearson-plus-extractor ", env!("CARGO_PKG_VERSION"));
+        let mut info = dictionary! {
+            "Title" => text_string(title),
+            "Producer" => text_string(producer),
+        };
+        if let Some(author) = author {
+            info.set("Author", text_string(author));
+        }
+        Self {
+            info,
+            next_id: 3,
+            pages_id: (1, 0),
+            font_id: (2, 0),
+            pages: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    fn new_id(&mut self) -> ObjectId {
+        self.next_id += 1;
+        (self.next_id - 1, 0)
+    }
+
+    /// Numbers the objects of `page`, the page dictionary last.
+    fn page_objects(&mut self, page: Page) -> Result<Vec<(ObjectId, Object)>> {
+        let mut objects = Vec::new();
+        let mut images = Dictionary::new();
+        for (i, image) in page.images.into_iter().enumerate() {
+            let id = self.new_id();
+            objects.push((id, image.into_stream().into()));
+            images.set(format!("Im{i}"), id);
+        }
+        let mut properties = Dictionary::new();
+        for (i, name) in page.layers.iter().enumerate() {
+            let group = match self.layers.iter().find(|(layer, _)| layer == name) {
+                Some(&(_, group)) => group,
+                None => {
+                    let group = self.new_id();
+                    self.layers.push((name.clone(), group));
+                    group
+                }
+            };
+            properties.set(format!("L{i}"), group);
+        }
+        let mut content = Stream::new(
+            dictionary! {},
+            Content {
+                operations: page.operations,
+            }
+            .encode()?,
+        );
+        content.compress()?;
+        let content_id = self.new_id();
+        objects.push((content_id, content.into()));
+        let mut resources = dictionary! { "Font" => dictionary! { FONT => self.font_id } };
+        if !images.is_empty() {
+            resources.set("XObject", images);
+        }
+        if !properties.is_empty() {
+            resources.set("Properties", properties);
+        }
+        let (w, h) = page.size;
+        let page_id = self.new_id();
+        objects.push((
+            page_id,
+            dictionary! {
+                "Type" => "Page",
+                "Parent" => self.pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), w.into(), h.into()],
+                "Contents" => content_id,
+                "Resources" => resources,
+            }
+            .into(),
+        ));
+        self.pages.push(page_id);
+        Ok(objects)
+    }
+
+    /// Numbers the font, layers, page tree, catalog and document information, and returns them
+    /// along with the trailer.
+    fn document_objects(&mut self) -> (Vec<(ObjectId, Object)>, Dictionary) {
+        let mut objects = vec![(
+            self.font_id,
+            dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type1",
+                "BaseFont" => "Times-Roman",
+                "Encoding" => "WinAnsiEncoding",
+            }
+            .into(),
+        )];
+        let kids = self
+            .pages
+            .iter()
+            .map(|&page| page.into())
+            .collect::<Vec<Object>>();
+        objects.push((
+            self.pages_id,
+            dictionary! { "Type" => "Pages", "Count" => kids.len() as i64, "Kids" => kids }.into(),
+        ));
+        let mut catalog = dictionary! { "Type" => "Catalog", "Pages" => self.pages_id };
+        if !self.layers.is_empty() {
+            let mut groups = Vec::new();
+            for (name, group) in &self.layers {
+                objects.push((
+                    *group,
+                    dictionary! { "Type" => "OCG", "Name" => text_string(name) }.into(),
+                ));
+                groups.push(Object::from(*group));
+            }
+            catalog.set(
+                "OCProperties",
+                dictionary! {
+                    "OCGs" => groups.clone(),
+                    "D" => dictionary! { "Order" => groups.clone(), "ON" => groups },
+                },
+            );
+        }
+        let catalog_id = self.new_id();
+        objects.push((catalog_id, catalog.into()));
+        let info_id = self.new_id();
+        objects.push((info_id, self.info.clone().into()));
+        let mut file_id = vec![0; 16];
+        SystemRandom::new().fill(&mut file_id).unwrap();
+        let file_id = Object::String(file_id, lopdf::StringFormat::Hexadecimal);
+        let trailer = dictionary! {
+            "Size" => self.next_id as i64,
+            "Root" => catalog_id,
+            "Info" => info_id,
+            "ID" => vec![file_id.clone(), file_id],
+        };
+        (objects, trailer)
+    }
+}
+
+/// Builds the document in memory for editing once all pages are drawn.
+pub struct DocumentSink<'a> {
+    shared: Shared,
+    document: Document,
+    /// The bytes of stream contents in `document`.
+    buffered: u64,
+    /// Where the contents of the page streams go past a memory cap.
+    spill: Option<&'a mut Spill>,
+}
+
+impl<'a> DocumentSink<'a> {
+    pub fn new(title: &str, author: Option<&str>) -> Self {
+        Self {
+            shared: Shared::new(title, author),
+            document: Document::with_version("1.7"),
+            buffered: 0,
+            spill: None,
+        }
+    }
+
+    /// Builds the document with the contents of its page streams in `spill`, which the document
+    /// has to be saved with.
+    pub fn spilling(title: &str, author: Option<&str>, spill: &'a mut Spill) -> Self {
+        Self {
+            spill: Some(spill),
+            ..Self::new(title, author)
+        }
+    }
+}
+
+impl PdfSink for DocumentSink<'_> {
+    fn add_page(&mut self, page: Page) -> Result<()> {
+        let mut objects = self.shared.page_objects(page)?;
+        for (id, object) in &mut objects {
+            if let Object::Stream(stream) = object {
+                if let Some(spill) = &mut self.spill {
+                    spill.admit(*id, stream)?;
+                }
+                self.buffered += stream.content.len() as u64;
+            }
+        }
+        self.document.objects.extend(objects);
+        Ok(())
+    }
+
+    fn buffered(&self) -> u64 {
+        self.buffered
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<Option<Document>> {
+        let (objects, trailer) = self.shared.document_objects();
+        self.document.objects.extend(objects);
+        self.document.trailer = trailer;
+        self.document.max_id = self.shared.next_id - 1;
+        Ok(Some(self.document))
+    }
+}
+
+/// Writes every page to `output` as soon as it is drawn, so only the page being drawn is held
+/// in memory, and the page tree and cross-reference table once the document is complete.
+pub struct StreamSink<'a, W: Write> {
+    shared: Shared,
+    output: &'a mut W,
+    written: usize,
+    /// The byte offset of every object written so far.
+    offsets: Vec<(u32, usize)>,
+}
+
+impl<'a, W: Write> StreamSink<'a, W> {
+    pub fn new(output: &'a mut W, title: &str, author: Option<&str>) -> Result<Self> {
+        Self::with_version(output, "1.7", title, author)
+    }
+
+    /// Writes a document declaring PDF `version` in its header.
+    pub fn with_version(
+        output: &'a mut W,
+        version: &str,
+        title: &str,
+        author: Option<&str>,
+    ) -> Result<Self> {
+        let header = objstm::header(version);
+        output.write_all(&header)?;
+        Ok(Self {
+            shared: Shared::new(title, author),
+            output,
+            written: header.len(),
+            offsets: Vec::new(),
+        })
+    }
+
+    fn write_objects(&mut self, objects: Vec<(ObjectId, Object)>) -> Result<()> {
+        for ((id, generation), object) in objects {
+            let mut bytes = Vec::new();
+            objstm::write_indirect(&mut bytes, id, generation, |file| {
+                objstm::write_object(file, &object)
+            });
+            self.output.write_all(&bytes)?;
+            self.offsets.push((id, self.written));
+            self.written += bytes.len();
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> PdfSink for StreamSink<'_, W> {
+    fn add_page(&mut self, page: Page) -> Result<()> {
+        let objects = self.shared.page_objects(page)?;
+        self.write_objects(objects)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<Option<Document>> {
+        let (objects, trailer) = self.shared.document_objects();
+        self.write_objects(objects)?;
+        self.offsets.sort_unstable();
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", self.shared.next_id);
+        let mut offsets = self.offsets.iter().peekable();
+        for id in 1..self.shared.next_id {
+            match offsets.next_if(|&&(object, _)| object == id) {
+                Some((_, offset)) => xref += &format!("{:010} 00000 n \n", offset),
+                None => xref += "0000000000 00000 f \n",
+            }
+        }
+        let mut trailer_bytes = Vec::new();
+        objstm::write_object(&mut trailer_bytes, &trailer.into());
+        self.output.write_all(xref.as_bytes())?;
+        self.output.write_all(b"trailer\n")?;
+        self.output.write_all(&trailer_bytes)?;
+        write!(self.output, "\nstartxref\n{}\n%%EOF\n", self.written)?;
+        Ok(None)
+    }
+}
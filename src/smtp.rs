@@ -0,0 +1,164 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use native_tls::TlsConnector;
+
+use crate::config::Smtp;
+
+/// How long to wait for the mail server to reply.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An email with an optional file attached.
+pub struct Message<'a> {
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub body: &'a str,
+    pub attachment: Option<(&'a str, &'a [u8])>,
+}
+
+/// Sends `message` through the mail server of `smtp`.
+pub fn send(smtp: &Smtp, message: &Message) -> Result<()> {
+    check_address(&smtp.from)?;
+    check_address(message.to)?;
+    let stream = TcpStream::connect((smtp.host.as_str(), smtp.port))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let connector = TlsConnector::new()?;
+    if !smtp.starttls {
+        let stream = connector.connect(&smtp.host, stream)?;
+        let mut connection = BufReader::new(stream);
+        reply(&mut connection, 220)?;
+        return transact(&mut connection, smtp, message);
+    }
+    let mut connection = BufReader::new(stream);
+    reply(&mut connection, 220)?;
+    command(&mut connection, "EHLO localhost", 250)?;
+    command(&mut connection, "STARTTLS", 220)?;
+    let stream = connector.connect(&smtp.host, connection.into_inner())?;
+    transact(&mut BufReader::new(stream), smtp, message)
+}
+
+fn transact<S: Read + Write>(
+    connection: &mut BufReader<S>,
+    smtp: &Smtp,
+    message: &Message,
+) -> Result<()> {
+    command(connection, "EHLO localhost", 250)?;
+    let credentials = format!("\0{}\0{}", smtp.username, smtp.password);
+    command(
+        connection,
+        &format!("AUTH PLAIN {}", STANDARD.encode(credentials)),
+        235,
+    )?;
+    command(connection, &format!("MAIL FROM:<{}>", smtp.from), 250)?;
+    command(connection, &format!("RCPT TO:<{}>", message.to), 250)?;
+    command(connection, "DATA", 354)?;
+    let mut data = compose(&smtp.from, message);
+    // a line of a single dot would end the data early
+    data = data.replace("\r\n.", "\r\n..");
+    data.push_str("\r\n.");
+    command(connection, &data, 250)?;
+    command(connection, "QUIT", 221)
+}
+
+/// Fails unless `address` could only be read as one address, in a command or a header alike.
+fn check_address(address: &str) -> Result<()> {
+    if address.is_empty()
+        || address
+            .chars()
+            .any(|char| char.is_control() || char.is_whitespace() || matches!(char, '<' | '>'))
+    {
+        bail!("{:?} isn't a valid email address", address);
+    }
+    Ok(())
+}
+
+/// Writes `line` and checks that the server replies with `expected`.
+fn command<S: Read + Write>(
+    connection: &mut BufReader<S>,
+    line: &str,
+    expected: u16,
+) -> Result<()> {
+    connection.get_mut().write_all(line.as_bytes())?;
+    connection.get_mut().write_all(b"\r\n")?;
+    connection.get_mut().flush()?;
+    reply(connection, expected)
+}
+
+/// Reads a reply, which continues over lines with a dash after the code, such as `250-SIZE`.
+fn reply(connection: &mut impl BufRead, expected: u16) -> Result<()> {
+    loop {
+        let mut line = String::new();
+        if connection.read_line(&mut line)? == 0 {
+            bail!("the mail server closed the connection");
+        }
+        let code = line.get(..3).and_then(|code| code.parse::<u16>().ok());
+        if code != Some(expected) {
+            bail!("the mail server replied {}", line.trim_end());
+        }
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(());
+        }
+    }
+}
+
+fn compose(from: &str, message: &Message) -> String {
+    let boundary = format!(
+        "pearson-plus-extractor-{}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+    );
+    let mut data = format!(
+        "From: <{}>\r\nTo: <{}>\r\nSubject: =?UTF-8?B?{}?=\r\nMIME-Version: 1.0\r\n",
+        from,
+        message.to,
+        STANDARD.encode(message.subject)
+    );
+    data += &format!("Content-Type: multipart/mixed; boundary=\"{boundary}\"\r\n\r\n");
+    data += &format!("--{boundary}\r\nContent-Type: text/plain; charset=utf-8\r\n");
+    data += "Content-Transfer-Encoding: base64\r\n\r\n";
+    wrap(&mut data, &STANDARD.encode(message.body));
+    if let Some((name, content)) = message.attachment {
+        data += &format!("--{boundary}\r\nContent-Type: application/octet-stream\r\n");
+        data += &format!("Content-Disposition: attachment; {}\r\n", filename(name));
+        data += "Content-Transfer-Encoding: base64\r\n\r\n";
+        wrap(&mut data, &STANDARD.encode(content));
+    }
+    data += &format!("--{boundary}--");
+    data
+}
+
+/// Returns the filename parameter of an attachment, percent-encoded as RFC 2231 describes unless
+/// the name is plain ASCII.
+fn filename(name: &str) -> String {
+    if name
+        .bytes()
+        .all(|byte| byte.is_ascii_graphic() && byte != b'"' || byte == b' ')
+    {
+        return format!("filename=\"{}\"", name);
+    }
+    let mut encoded = String::from("filename*=UTF-8''");
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b".-_".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded += &format!("%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+/// Appends base64 `encoded` in lines of 76 characters, as MIME requires.
+fn wrap(data: &mut String, encoded: &str) {
+    for line in encoded.as_bytes().chunks(76) {
+        // base64 is ASCII
+        data.push_str(std::str::from_utf8(line).unwrap());
+        data.push_str("\r\n");
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use lopdf::{Document, ObjectId, Stream};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::{
+    hex,
+    objstm::{self, Packing},
+};
+
+/// The contents of the streams of a document built in memory, held in memory up to a number of
+/// bytes and moved to a temporary file past that, so a long book can be built on a machine with
+/// little memory.
+pub struct Spill {
+    max_memory: u64,
+    /// The bytes of stream contents held in memory.
+    held: u64,
+    path: PathBuf,
+    file: File,
+    /// The bytes written to the file.
+    len: u64,
+    /// The offset and length in the file of every stream moved there.
+    streams: HashMap<ObjectId, (u64, usize)>,
+}
+
+impl Spill {
+    /// Creates the temporary file in the temporary directory of the system.
+    pub fn new(max_memory: u64) -> Result<Self> {
+        let mut suffix = [0; 8];
+        SystemRandom::new().fill(&mut suffix).unwrap();
+        let suffix = hex(&suffix);
+        let path = std::env::temp_dir().join(format!("pearson-plus-extractor-{}.spill", suffix));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self {
+            max_memory,
+            held: 0,
+            path,
+            file,
+            len: 0,
+            streams: HashMap::new(),
+        })
+    }
+
+    /// The bytes of stream contents moved to the file.
+    pub fn spilled(&self) -> u64 {
+        self.len
+    }
+
+    /// Keeps the contents of the stream numbered `id` in memory while they fit, and moves them to
+    /// the file otherwise, leaving the stream empty.
+    pub fn admit(&mut self, id: ObjectId, stream: &mut Stream) -> Result<()> {
+        let size = stream.content.len() as u64;
+        if self.held + size <= self.max_memory {
+            self.held += size;
+            return Ok(());
+        }
+        let content = std::mem::take(&mut stream.content);
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&content)?;
+        self.streams.insert(id, (self.len, content.len()));
+        self.len += size;
+        Ok(())
+    }
+
+    /// Reads the contents of the stream numbered `id` back, unless they are in the document.
+    fn read(&mut self, id: ObjectId) -> Result<Option<Vec<u8>>> {
+        let Some(&(offset, len)) = self.streams.get(&id) else {
+            return Ok(None);
+        };
+        let mut content = vec![0; len];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut content)?;
+        Ok(Some(content))
+    }
+
+    /// Puts the contents of every stream moved to the file back into `document`, for edits that
+    /// need all of them.
+    pub fn restore(&mut self, document: &mut Document) -> Result<()> {
+        let ids = self.streams.keys().copied().collect::<Vec<_>>();
+        for id in ids {
+            let content = self.read(id)?.unwrap();
+            if let Ok(stream) = document.get_object_mut(id)?.as_stream_mut() {
+                stream.set_content(content);
+            }
+        }
+        self.streams.clear();
+        Ok(())
+    }
+
+    /// Saves `document`, reading the contents of the streams moved to the file back one at a
+    /// time.
+    pub fn save(
+        &mut self,
+        document: &Document,
+        packing: Packing,
+        output: &mut impl Write,
+    ) -> Result<()> {
+        objstm::save_spilled(document, packing, output, |id| self.read(id))
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
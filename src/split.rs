@@ -0,0 +1,115 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+/// Splits the document at `path` into `name.part1.pdf`, `name.part2.pdf`, … next to it, each with
+/// at most `every` pages or, going by the size of the page images and contents, roughly at most
+/// `size` bytes. Returns the paths of the parts, which are handed to `prepare` before any is
+/// written, to make way for them. Links and destinations that point into another part are left
+/// pointing nowhere.
+pub fn split(
+    path: &Path,
+    every: Option<u32>,
+    size: Option<u64>,
+    prepare: impl FnOnce(&[PathBuf]) -> Result<()>,
+) -> Result<Vec<PathBuf>> {
+    let document = Document::load(path)?;
+    let pages = document.get_pages().into_values().collect::<Vec<_>>();
+    let mut parts = Vec::<Vec<ObjectId>>::new();
+    let mut part_size = 0;
+    for &page in &pages {
+        let page_size = page_size(&document, page);
+        let full = parts.last().is_none_or(|part| {
+            every.is_some_and(|every| part.len() as u32 >= every)
+                || size.is_some_and(|size| part_size + page_size > size)
+        });
+        if full {
+            parts.push(Vec::new());
+            part_size = 0;
+        }
+        parts.last_mut().unwrap().push(page);
+        part_size += page_size;
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let paths = (1..=parts.len())
+        .map(|i| path.with_file_name(format!("{}.part{}.pdf", stem, i)))
+        .collect::<Vec<_>>();
+    prepare(&paths)?;
+    for (part, path) in parts.iter().zip(&paths) {
+        let mut document = document.clone();
+        keep_pages(&mut document, part)?;
+        document.save(path)?;
+    }
+    Ok(paths)
+}
+
+/// The size of the streams a page uses, not counting the ones shared with every page.
+fn page_size(document: &Document, page: ObjectId) -> u64 {
+    fn visit(document: &Document, object: &Object, seen: &mut HashSet<ObjectId>) -> u64 {
+        match object {
+            Object::Reference(id) if seen.insert(*id) => document
+                .get_object(*id)
+                .map_or(0, |object| visit(document, object, seen)),
+            Object::Array(array) => array.iter().map(|item| visit(document, item, seen)).sum(),
+            Object::Dictionary(dict) => visit_dict(document, dict, seen),
+            Object::Stream(stream) => {
+                stream.content.len() as u64 + visit_dict(document, &stream.dict, seen)
+            }
+            _ => 0,
+        }
+    }
+    fn visit_dict(document: &Document, dict: &Dictionary, seen: &mut HashSet<ObjectId>) -> u64 {
+        dict.iter()
+            // don't wander off into the page tree, the shared fonts or other pages
+            .filter(|(key, _)| !matches!(key.as_slice(), b"Parent" | b"Font" | b"Dest" | b"P"))
+            .map(|(_, value)| visit(document, value, seen))
+            .sum()
+    }
+    visit(document, &Object::Reference(page), &mut HashSet::new())
+}
+
+/// Removes every page but `kept` from a document with a single level page tree, along with
+/// everything only they used.
+pub fn keep_pages(document: &mut Document, kept: &[ObjectId]) -> Result<()> {
+    let removed = document
+        .get_pages()
+        .into_values()
+        .filter(|page| !kept.contains(page))
+        .collect::<BTreeSet<_>>();
+    let pages_id = document.catalog()?.get(b"Pages")?.as_reference()?;
+    let pages = document.get_dictionary_mut(pages_id)?;
+    pages.set(
+        "Kids",
+        kept.iter()
+            .map(|&page| page.into())
+            .collect::<Vec<Object>>(),
+    );
+    pages.set("Count", kept.len() as i64);
+    for object in document.objects.values_mut() {
+        unlink(object, &removed);
+    }
+    for page in &removed {
+        document.objects.remove(page);
+    }
+    document.prune_objects();
+    Ok(())
+}
+
+fn unlink(object: &mut Object, removed: &BTreeSet<ObjectId>) {
+    match object {
+        Object::Reference(id) if removed.contains(id) => *object = Object::Null,
+        Object::Array(array) => array.iter_mut().for_each(|item| unlink(item, removed)),
+        Object::Dictionary(dict) => dict
+            .iter_mut()
+            .for_each(|(_, value)| unlink(value, removed)),
+        Object::Stream(stream) => stream
+            .dict
+            .iter_mut()
+            .for_each(|(_, value)| unlink(value, removed)),
+        _ => {}
+    }
+}
@@ -0,0 +1,56 @@
+use clap::ValueEnum;
+
+use crate::{
+    sink::{Page, MM},
+    tags,
+};
+
+const FONT_SIZE: f32 = 8.0;
+const MARGIN: f32 = 4.0 * MM;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Position {
+    Header,
+    Footer,
+}
+
+pub struct Stamp {
+    /// The text drawn on every page, with `{title}` and `{page}` replaced.
+    pub template: String,
+    pub position: Position,
+}
+
+impl Stamp {
+    /// Draws the stamp centered at the top or bottom of `page`, which is `w` by `h` points,
+    /// marked as a pagination artifact when the document is tagged.
+    pub fn draw(
+        &self,
+        page: &mut Page,
+        title: &str,
+        page_number: u32,
+        (w, h): (f32, f32),
+        tagged: bool,
+    ) {
+        let text = self
+            .template
+            .replace("{title}", title)
+            .replace("{page}", &page_number.to_string());
+        // builtin fonts come without metrics, so guess half an em per character
+        let width = FONT_SIZE * 0.5 * text.chars().count() as f32;
+        let x = ((w - width) / 2.0).max(MARGIN);
+        let y = match self.position {
+            Position::Header => h - MARGIN - FONT_SIZE,
+            Position::Footer => MARGIN,
+        };
+        if tagged {
+            tags::begin_artifact(page);
+        }
+        page.begin_text(FONT_SIZE, true);
+        page.set_text_position(x, y);
+        page.write_text(&text);
+        page.end_text();
+        if tagged {
+            tags::end(page);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{figures, toc::Toc};
+
+/// The word count, reading time and figure count of a range of pages.
+pub struct Stats {
+    pub title: String,
+    /// The first and last page.
+    pub pages: (u32, u32),
+    pub words: usize,
+    pub minutes: f32,
+    pub figures: usize,
+}
+
+/// Computes the statistics of every top level TOC entry from the text of each page, or of the
+/// whole book without a TOC.
+pub fn compute(pages: &[(u32, String)], toc: Option<&Toc>, words_per_minute: f32) -> Vec<Stats> {
+    let last_page = pages.last().map_or(0, |(page, _)| *page);
+    let mut chapters = match toc {
+        Some(toc) => toc
+            .entries
+            .iter()
+            .map(|entry| (entry.title.clone(), entry.page))
+            .collect::<Vec<_>>(),
+        None => vec![("Whole book".to_string(), 0)],
+    };
+    chapters.sort_by_key(|(_, page)| *page);
+    let ends = chapters
+        .iter()
+        .skip(1)
+        .map(|(_, page)| page.saturating_sub(1))
+        .chain([last_page])
+        .collect::<Vec<_>>();
+    chapters
+        .into_iter()
+        .zip(ends)
+        .map(|((title, first), last)| {
+            let texts = pages
+                .iter()
+                .filter(|(page, _)| (first..=last).contains(page))
+                .map(|(_, text)| text);
+            let mut words = 0;
+            let mut figure_count = 0;
+            for text in texts {
+                words += text.split_whitespace().count();
+                figure_count += text.lines().filter_map(figures::label).count();
+            }
+            Stats {
+                title,
+                pages: (first, last),
+                words,
+                minutes: words as f32 / words_per_minute,
+                figures: figure_count,
+            }
+        })
+        .collect()
+}
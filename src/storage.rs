@@ -0,0 +1,192 @@
+use std::{fs, future::Future, path::PathBuf, pin::Pin, time::SystemTime};
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{Client, Url};
+use ring::{digest, hmac};
+
+use crate::{config, hex, warc};
+
+pub type Stored<'a> = Pin<Box<dyn Future<Output = Result<String>> + 'a>>;
+
+/// Where finished documents are kept once they are written out locally.
+pub trait Storage {
+    /// Stores `content` under `name`, returning where it can be found.
+    fn store<'a>(&'a self, name: &'a str, content: Vec<u8>) -> Stored<'a>;
+}
+
+/// Returns the storage for `destination`: an s3:// bucket, an http:// or https:// WebDAV
+/// collection, or a local directory.
+pub fn open(destination: &str, config: &config::Config) -> Result<Box<dyn Storage>> {
+    if let Some(location) = destination.strip_prefix("s3://") {
+        let account = config.s3.clone().ok_or_else(|| {
+            anyhow!("add an s3 account to config.json to upload to {destination}")
+        })?;
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            bail!("{destination} doesn't name a bucket");
+        }
+        return Ok(Box::new(S3 {
+            client: Client::new(),
+            account,
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        }));
+    }
+    if destination.starts_with("http://") || destination.starts_with("https://") {
+        let mut collection = Url::parse(destination)?;
+        if !collection.path().ends_with('/') {
+            collection.set_path(&format!("{}/", collection.path()));
+        }
+        return Ok(Box::new(WebDav {
+            client: Client::new(),
+            collection,
+            account: config.webdav.clone(),
+        }));
+    }
+    Ok(Box::new(Local {
+        dir: PathBuf::from(destination),
+    }))
+}
+
+/// A directory, such as a synced folder or a network share.
+pub struct Local {
+    dir: PathBuf,
+}
+
+impl Storage for Local {
+    fn store<'a>(&'a self, name: &'a str, content: Vec<u8>) -> Stored<'a> {
+        Box::pin(async move {
+            fs::create_dir_all(&self.dir)?;
+            let path = self.dir.join(name);
+            fs::write(&path, content)?;
+            Ok(path.display().to_string())
+        })
+    }
+}
+
+/// A bucket of Amazon S3 or a compatible object storage, written with signature version 4.
+pub struct S3 {
+    client: Client,
+    account: config::S3,
+    bucket: String,
+    prefix: String,
+}
+
+impl Storage for S3 {
+    fn store<'a>(&'a self, name: &'a str, content: Vec<u8>) -> Stored<'a> {
+        Box::pin(async move {
+            let key = match self.prefix.as_str() {
+                "" => name.to_string(),
+                prefix => format!("{prefix}/{name}"),
+            };
+            // compatible storages are addressed by path, Amazon by virtual host
+            let url = match &self.account.endpoint {
+                Some(endpoint) => format!(
+                    "{}/{}/{}",
+                    endpoint.trim_end_matches('/'),
+                    self.bucket,
+                    encode_path(&key)
+                ),
+                None => format!(
+                    "https://{}.s3.{}.amazonaws.com/{}",
+                    self.bucket,
+                    self.account.region,
+                    encode_path(&key)
+                ),
+            };
+            let url = Url::parse(&url)?;
+            let host = match url.port() {
+                Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+                None => url.host_str().unwrap_or_default().to_string(),
+            };
+            let timestamp = warc::date(SystemTime::now()).replace(['-', ':'], "");
+            let date = &timestamp[..8];
+            let payload_hash = hex(digest::digest(&digest::SHA256, &content).as_ref());
+            let canonical_request = format!(
+                "PUT\n{}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\n\
+                 x-amz-date:{timestamp}\n\nhost;x-amz-content-sha256;x-amz-date\n{payload_hash}",
+                url.path()
+            );
+            let scope = format!("{date}/{}/s3/aws4_request", self.account.region);
+            let string_to_sign = format!(
+                "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+                hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+            );
+            let mut signing_key = format!("AWS4{}", self.account.secret_key).into_bytes();
+            for part in [date, &self.account.region, "s3", "aws4_request"] {
+                signing_key = sign(&signing_key, part.as_bytes());
+            }
+            let authorization = format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, \
+                 SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+                self.account.access_key,
+                hex(&sign(&signing_key, string_to_sign.as_bytes()))
+            );
+            let resp = self
+                .client
+                .put(url.clone())
+                .header("x-amz-content-sha256", payload_hash)
+                .header("x-amz-date", timestamp)
+                .header("authorization", authorization)
+                .body(content)
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                bail!(
+                    "the upload to s3://{}/{key} failed with {}",
+                    self.bucket,
+                    resp.status()
+                );
+            }
+            Ok(format!("s3://{}/{key}", self.bucket))
+        })
+    }
+}
+
+/// A collection on a WebDAV server, such as Nextcloud.
+pub struct WebDav {
+    client: Client,
+    collection: Url,
+    account: Option<config::WebDav>,
+}
+
+impl Storage for WebDav {
+    fn store<'a>(&'a self, name: &'a str, content: Vec<u8>) -> Stored<'a> {
+        Box::pin(async move {
+            let url = self.collection.join(&encode_path(name))?;
+            let mut request = self.client.put(url.clone()).body(content);
+            if let Some(account) = &self.account {
+                let credentials = format!("{}:{}", account.username, account.password);
+                request = request.header(
+                    "authorization",
+                    format!("Basic {}", STANDARD.encode(credentials)),
+                );
+            }
+            let resp = request.send().await?;
+            if !resp.status().is_success() {
+                bail!("the upload to {url} failed with {}", resp.status());
+            }
+            Ok(url.to_string())
+        })
+    }
+}
+
+fn sign(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, message).as_ref().to_vec()
+}
+
+/// Percent-encodes every byte of `path` but the unreserved characters and slashes.
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded += &format!("%{:02X}", byte),
+        }
+    }
+    encoded
+}
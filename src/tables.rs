@@ -0,0 +1,137 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+
+use crate::TextPageData;
+
+/// The fewest rows and columns a grid of text needs to count as a table.
+const MIN_ROWS: usize = 3;
+const MIN_COLUMNS: usize = 2;
+
+/// A run of characters without a wide gap, with its horizontal extent.
+struct Cell {
+    text: String,
+    left: f32,
+    right: f32,
+}
+
+/// Finds the tables on a page, made of at least `MIN_ROWS` consecutive lines whose cells line up
+/// into the same columns, and writes each one into `dir` as `page0012-table-1.csv`. Returns the
+/// number of tables written.
+pub fn extract(dir: &Path, page: u32, texts: &TextPageData) -> Result<usize> {
+    let mut tables = 0;
+    let mut rows = Vec::<Vec<(usize, String)>>::new();
+    let mut columns = Vec::<(f32, f32)>::new();
+    for line in lines(texts) {
+        let assigned = line
+            .iter()
+            .map(|cell| column(&columns, cell))
+            .collect::<Option<Vec<_>>>()
+            .filter(|assigned| {
+                line.len() >= 2 && assigned.windows(2).all(|pair| pair[0] < pair[1])
+            });
+        if let Some(assigned) = assigned.filter(|_| columns.len() >= MIN_COLUMNS) {
+            let mut row = Vec::new();
+            for (cell, i) in line.into_iter().zip(assigned) {
+                columns[i] = (columns[i].0.min(cell.left), columns[i].1.max(cell.right));
+                row.push((i, cell.text));
+            }
+            rows.push(row);
+            continue;
+        }
+        tables += write_table(dir, page, tables + 1, &rows, columns.len())?;
+        columns = line.iter().map(|cell| (cell.left, cell.right)).collect();
+        rows = vec![line.into_iter().map(|cell| cell.text).enumerate().collect()];
+    }
+    tables += write_table(dir, page, tables + 1, &rows, columns.len())?;
+    Ok(tables)
+}
+
+/// Returns the index of the only column the cell overlaps.
+fn column(columns: &[(f32, f32)], cell: &Cell) -> Option<usize> {
+    let mut overlapping = columns
+        .iter()
+        .enumerate()
+        .filter(|(_, (left, right))| cell.left < *right && cell.right > *left);
+    match (overlapping.next(), overlapping.next()) {
+        (Some((i, _)), None) => Some(i),
+        _ => None,
+    }
+}
+
+/// Splits the text of a page into lines from top to bottom, and the lines into cells at gaps
+/// wider than twice the median character width.
+fn lines(texts: &TextPageData) -> Vec<Vec<Cell>> {
+    let mut chars = texts
+        .data
+        .iter()
+        .flat_map(|text| &text.stream)
+        .filter_map(|&(x, y, w, h, char)| Some((x, y, w, h, char::from_u32(char)?)))
+        .collect::<Vec<_>>();
+    if chars.is_empty() {
+        return Vec::new();
+    }
+    let median = |values: &mut Vec<f32>| {
+        values.sort_by(f32::total_cmp);
+        values[values.len() / 2]
+    };
+    let width = median(&mut chars.iter().map(|c| c.2).collect());
+    let height = median(&mut chars.iter().map(|c| c.3).collect());
+    // top to bottom, then left to right within a line
+    chars.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let mut lines = Vec::<Vec<(f32, f32, f32, f32, char)>>::new();
+    for char in chars {
+        match lines.last_mut() {
+            Some(line) if (line[0].1 - char.1).abs() < height * 0.5 => line.push(char),
+            _ => lines.push(vec![char]),
+        }
+    }
+    lines
+        .into_iter()
+        .map(|mut line| {
+            line.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let mut cells = Vec::<Cell>::new();
+            for (x, _, w, _, char) in line {
+                match cells.last_mut() {
+                    Some(cell) if x - cell.right < width * 2.0 => {
+                        cell.text.push(char);
+                        cell.right = cell.right.max(x + w);
+                    }
+                    _ if char.is_whitespace() => {}
+                    _ => cells.push(Cell {
+                        text: char.to_string(),
+                        left: x,
+                        right: x + w,
+                    }),
+                }
+            }
+            cells
+        })
+        .collect()
+}
+
+/// Writes the rows as a table if there are enough of them, returning the number of tables written.
+fn write_table(
+    dir: &Path,
+    page: u32,
+    number: usize,
+    rows: &[Vec<(usize, String)>],
+    columns: usize,
+) -> Result<usize> {
+    if rows.len() < MIN_ROWS || columns < MIN_COLUMNS {
+        return Ok(0);
+    }
+    let mut csv = Vec::new();
+    for row in rows {
+        let mut fields = vec![""; columns];
+        for (column, text) in row {
+            fields[*column] = text.trim();
+        }
+        crate::csv::write_record(&mut csv, &fields)?;
+    }
+    fs::write(
+        dir.join(format!("page{:04}-table-{}.csv", page, number)),
+        csv,
+    )?;
+    Ok(1)
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use lopdf::{content::Operation, dictionary, Document, Object};
+
+use crate::{catalog::text_string, sink::Page, toc::Toc};
+
+/// Opens a marked-content sequence for the structure element `tag` numbered `mcid` on its page.
+pub fn begin(page: &mut Page, tag: &str, mcid: u32) {
+    let properties = dictionary! { "MCID" => mcid };
+    page.add_operation(Operation::new("BDC", vec![tag.into(), properties.into()]));
+}
+
+/// Opens a marked-content sequence for page furniture that isn't part of the structure tree.
+pub fn begin_artifact(page: &mut Page) {
+    let properties = dictionary! { "Type" => "Pagination" };
+    page.add_operation(Operation::new(
+        "BDC",
+        vec!["Artifact".into(), properties.into()],
+    ));
+}
+
+pub fn end(page: &mut Page) {
+    page.add_operation(Operation::new("EMC", vec![]));
+}
+
+/// Builds the structure tree of a document whose every page was tagged with a `Figure` as
+/// MCID 0 followed by `runs[page]` paragraphs, adding headings for the entries of `toc`.
+pub fn add_structure_tree(document: &mut Document, runs: &[u32], toc: Option<&Toc>) -> Result<()> {
+    let pages = document.get_pages();
+    let root = document.new_object_id();
+    let document_element = document.new_object_id();
+    let mut headings = BTreeMap::<u32, Vec<_>>::new();
+    for (depth, entry) in toc.map(Toc::flatten).unwrap_or_default() {
+        headings.entry(entry.page).or_default().push((depth, entry));
+    }
+    let mut kids = Vec::new();
+    let mut parent_tree = Vec::new();
+    for (page, &runs) in runs.iter().enumerate() {
+        let page = page as u32;
+        let Some(&page_id) = pages.get(&(page + 1)) else {
+            continue;
+        };
+        for (depth, entry) in headings.remove(&page).unwrap_or_default() {
+            let tag = format!("H{}", (depth + 1).min(6));
+            kids.push(Object::from(document.add_object(dictionary! {
+                "Type" => "StructElem",
+                "S" => tag.as_str(),
+                "P" => document_element,
+                "Pg" => page_id,
+                "T" => text_string(&entry.title),
+                "ActualText" => text_string(&entry.title),
+            })));
+        }
+        let mut marked = Vec::new();
+        for mcid in 0..=runs {
+            let element = document.add_object(dictionary! {
+                "Type" => "StructElem",
+                "S" => if mcid == 0 { "Figure" } else { "P" },
+                "P" => document_element,
+                "Pg" => page_id,
+                "K" => mcid,
+            });
+            if mcid == 0 {
+                let alt = format!("Page {}", page);
+                document
+                    .get_dictionary_mut(element)?
+                    .set("Alt", Object::string_literal(alt));
+            }
+            kids.push(element.into());
+            marked.push(Object::from(element));
+        }
+        let key = parent_tree.len() as i64 / 2;
+        document
+            .get_dictionary_mut(page_id)?
+            .set("StructParents", key);
+        parent_tree.push(key.into());
+        parent_tree.push(marked.into());
+    }
+    document.objects.insert(
+        document_element,
+        dictionary! {
+            "Type" => "StructElem",
+            "S" => "Document",
+            "P" => root,
+            "K" => kids,
+        }
+        .into(),
+    );
+    let parent_tree_next_key = parent_tree.len() as i64 / 2;
+    document.objects.insert(
+        root,
+        dictionary! {
+            "Type" => "StructTreeRoot",
+            "K" => document_element,
+            "ParentTree" => dictionary! { "Nums" => parent_tree },
+            "ParentTreeNextKey" => parent_tree_next_key,
+        }
+        .into(),
+    );
+    let catalog = document.catalog_mut()?;
+    catalog.set("StructTreeRoot", root);
+    catalog.set("MarkInfo", dictionary! { "Marked" => true });
+    Ok(())
+}
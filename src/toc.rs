@@ -0,0 +1,271 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use lopdf::{
+    content::{Content, Operation},
+    dictionary, Document, Object, ObjectId, Stream,
+};
+use serde::{de::Error, Deserializer};
+use sonic_rs::{Deserialize, JsonValueTrait, Value};
+
+use crate::{
+    catalog::names_mut,
+    numbering::{self, Numbering, Printed},
+};
+
+#[derive(Deserialize)]
+pub struct Entry {
+    pub title: String,
+    /// The page the entry starts on, counted from the cover at 0.
+    #[serde(skip)]
+    pub page: u32,
+    /// The page as the TOC gives it, a page counted from the cover as a number, or the page
+    /// number printed in the book as a string such as "xiv" or "S. 12".
+    #[serde(rename = "page", deserialize_with = "deserialize_page")]
+    reference: Reference,
+    #[serde(default)]
+    pub children: Vec<Entry>,
+}
+
+impl Entry {
+    /// The page number of the entry as the TOC gives it.
+    pub fn label(&self) -> String {
+        match self.reference {
+            Reference::Page(page) => page.to_string(),
+            Reference::Printed(Printed::Roman(value)) => numbering::to_roman(value),
+            Reference::Printed(Printed::Arabic(value)) => value.to_string(),
+        }
+    }
+}
+
+enum Reference {
+    Page(u32),
+    Printed(Printed),
+}
+
+fn deserialize_page<'de, D>(deserializer: D) -> Result<Reference, D::Error>
+where
+    D: Deserializer<'de>,
+    D::Error: Error,
+{
+    let value = Value::deserialize(deserializer)?;
+    if let Some(page) = value.as_u64() {
+        return u32::try_from(page)
+            .map(Reference::Page)
+            .map_err(D::Error::custom);
+    }
+    let label = value
+        .as_str()
+        .ok_or_else(|| D::Error::custom("a page must be a number or a string"))?;
+    numbering::parse(label)
+        .map(Reference::Printed)
+        .ok_or_else(|| D::Error::custom(format!("{label:?} isn't a page number")))
+}
+
+pub struct Toc {
+    pub entries: Vec<Entry>,
+    /// The JSON the entries were read from.
+    pub source: String,
+}
+
+impl Toc {
+    /// Reads a table of contents from a JSON array of `{ "title", "page", "children" }` entries.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let source = fs::read_to_string(path)?;
+        let mut entries = sonic_rs::from_str::<Vec<Entry>>(&source)?;
+        visit_mut(&mut entries, &mut |entry| {
+            if let Reference::Page(page) = entry.reference {
+                entry.page = page;
+            }
+        });
+        Ok(Self { entries, source })
+    }
+
+    /// Whether some entries give the page number printed in the book, to be found with
+    /// `resolve` once the text of the pages is known.
+    pub fn has_printed_pages(&self) -> bool {
+        self.flatten()
+            .iter()
+            .any(|(_, entry)| matches!(entry.reference, Reference::Printed(_)))
+    }
+
+    /// Finds the pages of the entries given as printed page numbers from the numbers printed on
+    /// the pages, or takes them as pages counted from the cover when the book doesn't print
+    /// numbers of their kind. Returns the titles of the entries taken so, with their pages.
+    pub fn resolve(&mut self, numbering: &Numbering) -> Vec<(String, u32)> {
+        let mut unresolved = Vec::new();
+        visit_mut(&mut self.entries, &mut |entry| {
+            let Reference::Printed(printed) = entry.reference else {
+                return;
+            };
+            entry.page = numbering.page(printed).unwrap_or_else(|| {
+                unresolved.push((entry.title.clone(), printed.value()));
+                printed.value()
+            });
+        });
+        unresolved
+    }
+
+    /// Returns every entry along with its nesting depth, in reading order.
+    pub fn flatten(&self) -> Vec<(usize, &Entry)> {
+        fn visit<'a>(entries: &'a [Entry], depth: usize, flat: &mut Vec<(usize, &'a Entry)>) {
+            for entry in entries {
+                flat.push((depth, entry));
+                visit(&entry.children, depth + 1, flat);
+            }
+        }
+        let mut flat = Vec::new();
+        visit(&self.entries, 0, &mut flat);
+        flat
+    }
+
+    /// Returns every entry along with a destination name made of its position in the TOC, such
+    /// as `chapter.3.2` for the second child of the third entry.
+    pub fn names(&self) -> Vec<(String, &Entry)> {
+        fn visit<'a>(entries: &'a [Entry], prefix: &str, named: &mut Vec<(String, &'a Entry)>) {
+            for (i, entry) in entries.iter().enumerate() {
+                let name = format!("{}.{}", prefix, i + 1);
+                visit(&entry.children, &name, named);
+                named.push((name, entry));
+            }
+        }
+        let mut named = Vec::new();
+        visit(&self.entries, "chapter", &mut named);
+        named
+    }
+}
+
+fn visit_mut(entries: &mut [Entry], f: &mut impl FnMut(&mut Entry)) {
+    for entry in entries {
+        f(entry);
+        visit_mut(&mut entry.children, f);
+    }
+}
+
+const MARGIN: f32 = 54.0;
+const FONT_SIZE: f32 = 11.0;
+const LINE_HEIGHT: f32 = 16.0;
+const INDENT: f32 = 18.0;
+
+/// Inserts contents pages at the front of `document` with an entry linking to the page of every
+/// TOC entry, and returns the number of pages inserted.
+pub fn insert_contents(document: &mut Document, toc: &Toc) -> Result<u32> {
+    let pages = document.get_pages();
+    let (w, h) = match pages.get(&1) {
+        Some(&page) => {
+            let media_box = document
+                .get_dictionary(page)?
+                .get(b"MediaBox")?
+                .as_array()?;
+            (media_box[2].as_float()?, media_box[3].as_float()?)
+        }
+        None => (612.0, 792.0),
+    };
+    let font = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let resources = document.add_object(dictionary! {
+        "Font" => dictionary! { "F1" => font },
+    });
+    let pages_id = document.catalog()?.get(b"Pages")?.as_reference()?;
+    let entries = toc.flatten();
+    let lines = ((h - 2.0 * MARGIN - 2.0 * LINE_HEIGHT) / LINE_HEIGHT).max(1.0) as usize;
+    let mut contents_pages = Vec::new();
+    for (i, chunk) in entries.chunks(lines).enumerate() {
+        let mut operations = Vec::new();
+        let mut annotations = Vec::new();
+        let mut y = h - MARGIN;
+        if i == 0 {
+            write_line(&mut operations, 18.0, MARGIN, y, "Contents");
+        }
+        y -= 2.0 * LINE_HEIGHT;
+        for (depth, entry) in chunk {
+            let x = MARGIN + INDENT * *depth as f32;
+            let number_x = w - MARGIN - 30.0;
+            let max_chars = ((number_x - x - 12.0) / (FONT_SIZE * 0.5)).max(4.0) as usize;
+            let title = if entry.title.chars().count() > max_chars {
+                let title = entry.title.chars().take(max_chars - 3).collect::<String>();
+                format!("{}...", title.trim_end())
+            } else {
+                entry.title.clone()
+            };
+            write_line(&mut operations, FONT_SIZE, x, y, &title);
+            write_line(&mut operations, FONT_SIZE, number_x, y, &entry.label());
+            if let Some(&target) = pages.get(&(entry.page + 1)) {
+                let rect = vec![
+                    x.into(),
+                    (y - 4.0).into(),
+                    (w - MARGIN).into(),
+                    (y + FONT_SIZE).into(),
+                ];
+                annotations.push(Object::Reference(document.add_object(dictionary! {
+                    "Type" => "Annot",
+                    "Subtype" => "Link",
+                    "Rect" => rect,
+                    "Border" => vec![0.into(), 0.into(), 0.into()],
+                    "Dest" => vec![target.into(), "Fit".into()],
+                })));
+            }
+            y -= LINE_HEIGHT;
+        }
+        let content = Content { operations }.encode()?;
+        let content = document.add_object(Stream::new(dictionary! {}, content));
+        contents_pages.push(document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), w.into(), h.into()],
+            "Contents" => content,
+            "Resources" => resources,
+            "Annots" => annotations,
+        }));
+    }
+    insert_pages(document, pages_id, &contents_pages)?;
+    Ok(contents_pages.len() as u32)
+}
+
+/// Adds a named destination for every TOC entry to the catalog, so `#nameddest=chapter.3.2`
+/// opens the document at that entry.
+pub fn add_named_destinations(document: &mut Document, toc: &Toc) -> Result<()> {
+    let pages = document.get_pages();
+    let mut names = toc
+        .names()
+        .into_iter()
+        .filter_map(|(name, entry)| Some((name, *pages.get(&(entry.page + 1))?)))
+        .collect::<Vec<_>>();
+    // name tree leaves must be sorted by their byte representation
+    names.sort();
+    let names = names
+        .into_iter()
+        .flat_map(|(name, page)| {
+            let dest: Object = vec![page.into(), "Fit".into()].into();
+            [Object::string_literal(name), dest]
+        })
+        .collect::<Vec<_>>();
+    let dests = document.add_object(dictionary! { "Names" => names });
+    names_mut(document)?.set("Dests", dests);
+    Ok(())
+}
+
+/// Writes `text` at `x`, `y` in the font `F1` at `size` points.
+pub fn write_line(operations: &mut Vec<Operation>, size: f32, x: f32, y: f32, text: &str) {
+    let text = Document::encode_text(Some("WinAnsiEncoding"), text);
+    operations.extend([
+        Operation::new("BT", vec![]),
+        Operation::new("Tf", vec!["F1".into(), size.into()]),
+        Operation::new("Td", vec![x.into(), y.into()]),
+        Operation::new("Tj", vec![Object::string_literal(text)]),
+        Operation::new("ET", vec![]),
+    ]);
+}
+
+fn insert_pages(document: &mut Document, pages_id: ObjectId, new_pages: &[ObjectId]) -> Result<()> {
+    let pages = document.get_dictionary_mut(pages_id)?;
+    let kids = pages.get_mut(b"Kids")?.as_array_mut()?;
+    kids.splice(0..0, new_pages.iter().map(|&page| Object::Reference(page)));
+    let count = kids.len() as i64;
+    pages.set("Count", count);
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::{env, fs};
+
+use anyhow::{anyhow, bail, Result};
+use reqwest::{header::USER_AGENT, Client};
+use ring::digest::{digest, SHA256};
+use sonic_rs::Deserialize;
+
+use crate::hex;
+
+const RELEASES: &str =
+    "https://api.github.com/repos/apersomany/pearson-plus-extractor/releases/latest";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Installs the latest GitHub release over the running binary if it is newer, verifying it
+/// against the SHA-256 checksum published next to it, and returns the installed version.
+///
+/// The binary of a release is named like `pearson-plus-extractor-x86_64-linux`, or with `.exe`
+/// on Windows, and its checksum is in the asset of the same name with `.sha256` appended.
+pub async fn self_update(check_only: bool) -> Result<Option<String>> {
+    let client = Client::new();
+    let release = client
+        .get(RELEASES)
+        .header(USER_AGENT, "pearson-plus-extractor")
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let release: Release = sonic_rs::from_str(&release)?;
+    let version = release.tag_name.trim_start_matches('v');
+    if !is_newer(version, env!("CARGO_PKG_VERSION")) {
+        return Ok(None);
+    }
+    if check_only {
+        return Ok(Some(version.to_string()));
+    }
+    let name = format!(
+        "pearson-plus-extractor-{}-{}{}",
+        env::consts::ARCH,
+        env::consts::OS,
+        env::consts::EXE_SUFFIX
+    );
+    let url = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.as_str())
+            .ok_or_else(|| anyhow!("release {} has no {}", release.tag_name, name))
+    };
+    let binary_url = url(&name)?;
+    let checksum_url = url(&format!("{}.sha256", name))?;
+    let checksum = client.get(checksum_url).send().await?.error_for_status()?;
+    let checksum = checksum.text().await?;
+    let expected = checksum.split_whitespace().next().unwrap_or_default();
+    let binary = client.get(binary_url).send().await?.error_for_status()?;
+    let binary = binary.bytes().await?;
+    let actual = hex(digest(&SHA256, &binary).as_ref());
+    if !actual.eq_ignore_ascii_case(expected) {
+        bail!("the checksum of {} doesn't match, not installing it", name);
+    }
+    let current = env::current_exe()?;
+    let new = current.with_extension("new");
+    let old = current.with_extension("old");
+    fs::write(&new, &binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new, fs::Permissions::from_mode(0o755))?;
+    }
+    // Windows can rename a running executable but not overwrite or delete it
+    fs::rename(&current, &old)?;
+    if let Err(error) = fs::rename(&new, &current) {
+        fs::rename(&old, &current)?;
+        return Err(error.into());
+    }
+    let _ = fs::remove_file(&old);
+    Ok(Some(version.to_string()))
+}
+
+/// Compares dotted version numbers such as `0.10.2` and `0.9.0`.
+fn is_newer(version: &str, current: &str) -> bool {
+    let parse = |version: &str| {
+        version
+            .split(['.', '-', '+'])
+            .map(|part| part.parse::<u64>().unwrap_or_default())
+            .take(3)
+            .collect::<Vec<_>>()
+    };
+    parse(version) > parse(current)
+}
@@ -0,0 +1,74 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use lopdf::{content::Content, Document, Object};
+
+use crate::Extraction;
+
+/// Re-extracts the text layer of the document at `paths`, which is split into parts there unless
+/// it is just one, and compares it against the annotation data, returning every page number along
+/// with the characters that didn't survive the round trip.
+pub fn verify_text(paths: &[PathBuf], extraction: &Extraction) -> Result<Vec<(u32, Vec<char>)>> {
+    let documents = paths
+        .iter()
+        .map(Document::load)
+        .collect::<Result<Vec<_>, _>>()?;
+    // the pages of the parts one after another, as they were in the document
+    let pages = documents
+        .iter()
+        .flat_map(|document| {
+            let count = document.get_pages().len() as u32;
+            (1..=count).map(move |page_number| (document, page_number))
+        })
+        .collect::<Vec<_>>();
+    let mut damaged = Vec::new();
+    for (page, expected) in extraction.page_texts.iter().enumerate() {
+        let Some(pdf_page) = extraction.pdf_page(page as u32) else {
+            continue;
+        };
+        let &(document, page_number) = pages
+            .get(pdf_page as usize - 1)
+            .ok_or_else(|| anyhow!("the document has no page {}", pdf_page))?;
+        let extracted = extract_text(document, page_number)?;
+        let mut counts = HashMap::<char, i64>::new();
+        for char in expected.chars().filter(|char| !char.is_whitespace()) {
+            *counts.entry(char).or_default() += 1;
+        }
+        for char in extracted.chars().filter(|char| !char.is_whitespace()) {
+            *counts.entry(char).or_default() -= 1;
+        }
+        let mut lost = counts
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .flat_map(|(char, count)| std::iter::repeat_n(char, count as usize))
+            .collect::<Vec<_>>();
+        if !lost.is_empty() {
+            lost.sort_unstable();
+            damaged.push((page as u32, lost));
+        }
+    }
+    Ok(damaged)
+}
+
+/// Extracts the text shown on a page, assuming the WinAnsi encoding the pages are written in.
+/// `Document::extract_text` doesn't follow the indirect font dictionary of the pages and would
+/// decode everything as StandardEncoding instead.
+fn extract_text(document: &Document, page_number: u32) -> Result<String> {
+    // a truncated or altered document may have lost the page
+    let page = *document
+        .get_pages()
+        .get(&page_number)
+        .ok_or_else(|| anyhow!("the document has no page {}", page_number))?;
+    let content = Content::decode(&document.get_page_content(page)?)?;
+    let mut text = String::new();
+    for operation in content.operations {
+        if let "Tj" | "TJ" = operation.operator.as_str() {
+            for operand in operation.operands {
+                if let Object::String(bytes, _) = operand {
+                    text.push_str(&Document::decode_text(Some("WinAnsiEncoding"), &bytes));
+                }
+            }
+        }
+    }
+    Ok(text)
+}
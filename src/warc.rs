@@ -0,0 +1,374 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Result};
+use flate2::{bufread::GzDecoder, write::GzEncoder, Compression};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, SET_COOKIE, TRANSFER_ENCODING},
+    Request, StatusCode, Version,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::{extractor::Fetched, hex};
+
+/// Writes every exchange with plus.pearson.com to a WARC file, one gzip member per record when
+/// its name ends in `.gz`. The session headers are added by the client after the requests are
+/// recorded and the cookies it sets are left out, so the archive can be shared.
+pub struct Recorder {
+    file: Mutex<BufWriter<File>>,
+    gzip: bool,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let recorder = Self {
+            file: Mutex::new(BufWriter::new(File::create(path)?)),
+            gzip: path.extension().is_some_and(|extension| extension == "gz"),
+        };
+        let info = format!(
+            "software: pearson-plus-extractor/{}\r\nformat: WARC File Format 1.1\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        recorder.write(
+            &record_id(),
+            "warcinfo",
+            None,
+            "application/warc-fields",
+            info.as_bytes(),
+        )?;
+        Ok(recorder)
+    }
+
+    /// Writes a request record for `request` and a response record for the response it got.
+    pub fn record(
+        &self,
+        request: &Request,
+        version: Version,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<()> {
+        let url = request.url();
+        let mut head = format!(
+            "{} {}{} HTTP/1.1\r\nHost: {}\r\n",
+            request.method(),
+            url.path(),
+            url.query()
+                .map(|query| format!("?{query}"))
+                .unwrap_or_default(),
+            url.host_str().unwrap_or_default()
+        );
+        write_headers(&mut head, request.headers());
+        let request_id = record_id();
+        self.write(
+            &request_id,
+            "request",
+            Some((url.as_str(), None)),
+            "application/http; msgtype=request",
+            head.as_bytes(),
+        )?;
+        // the body was already decoded from the transfer encoding
+        let mut head = format!("{:?} {}\r\n", version, status);
+        let mut headers = headers.clone();
+        headers.remove(TRANSFER_ENCODING);
+        headers.remove(SET_COOKIE);
+        write_headers(&mut head, &headers);
+        self.write(
+            &record_id(),
+            "response",
+            Some((url.as_str(), Some(&request_id))),
+            "application/http; msgtype=response",
+            &[head.as_bytes(), body].concat(),
+        )
+    }
+
+    fn write(
+        &self,
+        id: &str,
+        kind: &str,
+        target: Option<(&str, Option<&str>)>,
+        content_type: &str,
+        block: &[u8],
+    ) -> Result<()> {
+        let mut header = format!(
+            "WARC/1.1\r\nWARC-Type: {kind}\r\nWARC-Record-ID: {id}\r\nWARC-Date: {}\r\n",
+            date(SystemTime::now())
+        );
+        if let Some((uri, concurrent_to)) = target {
+            header += &format!("WARC-Target-URI: {uri}\r\n");
+            if let Some(concurrent_to) = concurrent_to {
+                header += &format!("WARC-Concurrent-To: {concurrent_to}\r\n");
+            }
+        }
+        header += &format!(
+            "Content-Type: {content_type}\r\nContent-Length: {}\r\n\r\n",
+            block.len()
+        );
+        let record = [header.as_bytes(), block, b"\r\n\r\n"].concat();
+        let mut file = self.file.lock().unwrap();
+        if self.gzip {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&record)?;
+            file.write_all(&encoder.finish()?)?;
+        } else {
+            file.write_all(&record)?;
+        }
+        // keep the archive complete up to the last exchange if the extraction fails
+        file.flush()?;
+        Ok(())
+    }
+}
+
+fn write_headers(head: &mut String, headers: &HeaderMap) {
+    for (name, value) in headers {
+        head.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    head.push_str("\r\n");
+}
+
+/// Returns a random version 4 UUID as a URN.
+fn record_id() -> String {
+    let mut bytes = [0; 16];
+    SystemRandom::new().fill(&mut bytes).unwrap();
+    bytes[6] = bytes[6] & 0x0f | 0x40;
+    bytes[8] = bytes[8] & 0x3f | 0x80;
+    let hex = hex(&bytes);
+    format!(
+        "<urn:uuid:{}-{}-{}-{}-{}>",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Formats `time` as a UTC timestamp such as `2024-05-01T12:00:00Z`.
+pub fn date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, seconds) = ((seconds / 86400) as i64, seconds % 86400);
+    // the civil date of a day count, as described by Howard Hinnant
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// The responses of a WARC file written with `Recorder`, answered in the order they were
+/// recorded for each method and URL, so retries see the same failures they saw then.
+///
+/// Only where the responses are is kept in memory, and each one is read from the file when it is
+/// asked for, so the recording of a large book doesn't have to fit in memory.
+pub struct Replay {
+    file: Mutex<File>,
+    responses: Mutex<HashMap<(String, String), VecDeque<Location>>>,
+}
+
+/// Where the block of a recorded response is, `offset` bytes into the file, or into the gzip
+/// member starting at `member` once decompressed.
+#[derive(Clone, Copy)]
+struct Location {
+    member: Option<u64>,
+    offset: u64,
+    length: usize,
+}
+
+impl Replay {
+    /// Indexes the records of the WARC file at `path` in one pass, one gzip member at a time when
+    /// it is compressed.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut index = Index::default();
+        if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+            // a member per record as `Recorder` writes them, though a member may hold several
+            while !reader.fill_buf()?.is_empty() {
+                let member = reader.stream_position()?;
+                let mut records = Vec::new();
+                GzDecoder::new(&mut reader).read_to_end(&mut records)?;
+                index.add(&mut Cursor::new(records), Some(member))?;
+            }
+        } else {
+            index.add(&mut reader, None)?;
+        }
+        Ok(Self {
+            file: Mutex::new(reader.into_inner()),
+            responses: Mutex::new(index.responses),
+        })
+    }
+
+    /// Returns the next recorded response to `request`, repeating the last one once they ran out.
+    pub fn respond(&self, request: &Request) -> Result<Fetched> {
+        let key = (request.method().to_string(), request.url().to_string());
+        let location = match self.responses.lock().unwrap().get_mut(&key) {
+            Some(queue) if queue.len() > 1 => queue.pop_front().unwrap(),
+            Some(queue) => queue[0],
+            None => bail!("{} {} isn't in the recording", key.0, key.1),
+        };
+        self.read(location)
+    }
+
+    /// Returns the body of the last successful response recorded to a GET of `url`.
+    pub fn body(&self, url: &str) -> Option<Vec<u8>> {
+        let key = ("GET".to_string(), url.to_string());
+        let locations = self.responses.lock().unwrap().get(&key)?.clone();
+        locations
+            .iter()
+            .rev()
+            .filter_map(|&location| self.read(location).ok())
+            .find(|resp| resp.status.is_success())
+            .map(|resp| resp.body)
+    }
+
+    fn read(&self, location: Location) -> Result<Fetched> {
+        let mut file = self.file.lock().unwrap();
+        let mut block = vec![0; location.length];
+        match location.member {
+            Some(member) => {
+                file.seek(SeekFrom::Start(member))?;
+                let mut records = GzDecoder::new(BufReader::new(&mut *file));
+                io::copy(&mut (&mut records).take(location.offset), &mut io::sink())?;
+                records.read_exact(&mut block)?;
+            }
+            None => {
+                file.seek(SeekFrom::Start(location.offset))?;
+                file.read_exact(&mut block)?;
+            }
+        }
+        parse_response(&block)
+    }
+}
+
+/// The responses found so far while loading a `Replay`, along with the methods of the requests
+/// they answer by record ID.
+#[derive(Default)]
+struct Index {
+    methods: HashMap<String, String>,
+    responses: HashMap<(String, String), VecDeque<Location>>,
+}
+
+impl Index {
+    /// Adds the records of `reader`, the whole archive or the decompressed gzip member at
+    /// `member`, skipping over the blocks of all but the requests.
+    fn add(&mut self, reader: &mut (impl BufRead + Seek), member: Option<u64>) -> Result<()> {
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        while let Some(fields) = read_head(reader)? {
+            let field = |name: &str| fields.get(&name.to_ascii_lowercase()).map(String::as_str);
+            let length = field("Content-Length")
+                .and_then(|length| length.parse::<usize>().ok())
+                .ok_or_else(|| anyhow!("a record has no Content-Length"))?;
+            let offset = reader.stream_position()?;
+            if offset + length as u64 > end {
+                bail!("truncated record");
+            }
+            if let (Some(id), Some(uri)) = (field("WARC-Record-ID"), field("WARC-Target-URI")) {
+                match field("WARC-Type") {
+                    Some("request") => {
+                        let mut block = vec![0; length];
+                        reader.read_exact(&mut block)?;
+                        let method = block.split(|&byte| byte == b' ').next().unwrap_or_default();
+                        let method = String::from_utf8_lossy(method).into_owned();
+                        self.methods.insert(id.to_string(), method);
+                    }
+                    Some("response") => {
+                        let method = field("WARC-Concurrent-To")
+                            .and_then(|request| self.methods.get(request))
+                            .map_or("GET", String::as_str);
+                        let key = (method.to_string(), uri.to_string());
+                        let location = Location {
+                            member,
+                            offset,
+                            length,
+                        };
+                        self.responses.entry(key).or_default().push_back(location);
+                    }
+                    _ => {}
+                }
+            }
+            reader.seek(SeekFrom::Start(offset + length as u64))?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the head of the next record of `reader` past the blank lines before it, returning its
+/// fields, or `None` at the end of the archive.
+fn read_head(reader: &mut impl BufRead) -> Result<Option<HashMap<String, String>>> {
+    let mut lines = Vec::new();
+    loop {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            if lines.is_empty() {
+                return Ok(None);
+            }
+            bail!("truncated record");
+        }
+        match (line.trim_ascii().is_empty(), lines.is_empty()) {
+            (true, true) => {}
+            (true, false) => break,
+            (false, _) => lines.push(String::from_utf8_lossy(&line).into_owned()),
+        }
+    }
+    // the first line is the version
+    Ok(Some(parse_fields(lines.iter().skip(1).map(String::as_str))))
+}
+
+/// Splits a message at the blank line after its head.
+fn split_head(message: &[u8]) -> Option<(String, &[u8])> {
+    let end = message
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&message[..end]).into_owned();
+    Some((head, &message[end + 4..]))
+}
+
+fn parse_fields<'a>(lines: impl Iterator<Item = &'a str>) -> HashMap<String, String> {
+    lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect()
+}
+
+fn parse_response(block: &[u8]) -> Result<Fetched> {
+    let (head, body) = split_head(block).ok_or_else(|| anyhow!("a response has no head"))?;
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| anyhow!("a response has no status"))?;
+    let mut headers = HeaderMap::new();
+    for (name, value) in parse_fields(lines) {
+        headers.append(HeaderName::try_from(name)?, HeaderValue::try_from(value)?);
+    }
+    Ok(Fetched {
+        status: StatusCode::from_u16(status)?,
+        headers,
+        body: body.to_vec(),
+    })
+}
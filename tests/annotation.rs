@@ -0,0 +1,324 @@
+//! Tests of reading the text layer of a page from its annotation data. Besides the cases below,
+//! the property tests feed the parser a few thousand random and mangled annotations from a fixed
+//! seed, so a failure can be reproduced, and check that it never panics and never lets a
+//! coordinate that isn't finite through.
+
+use pearson_plus_extractor::{annotation, TextPageData};
+
+const IDENTITY: [f32; 6] = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Encodes a text layer the way the server sends it, as a string inside the annotation data.
+fn encode(texts: &str) -> String {
+    sonic_rs::to_string(&sonic_rs::json!({"TextPageData": texts})).unwrap()
+}
+
+fn parse(texts: &str) -> TextPageData {
+    annotation::parse(&encode(texts)).unwrap()
+}
+
+fn assert_finite(data: &TextPageData) {
+    for text in &data.data {
+        assert!(
+            text.matrix.iter().all(|n| n.is_finite()),
+            "{:?}",
+            text.matrix
+        );
+        for (x, y, w, h, _) in &text.stream {
+            assert!([x, y, w, h].iter().all(|n| n.is_finite()));
+        }
+    }
+}
+
+#[test]
+fn well_formed() {
+    let data = parse(r#"{"texts": [{"mt": [2, 0, 0, 2, 5, 6], "cs": [[1, 2, 3, 4, 65]]}]}"#);
+    assert_eq!(data.data.len(), 1);
+    assert_eq!(data.data[0].matrix, [2.0, 0.0, 0.0, 2.0, 5.0, 6.0]);
+    assert_eq!(data.data[0].stream, vec![(1.0, 2.0, 3.0, 4.0, 65)]);
+}
+
+#[test]
+fn not_encoded_twice() {
+    let annotation =
+        r#"{"TextPageData": {"texts": [{"mt": [1, 0, 0, 1, 0, 0], "cs": [[1, 2, 3, 4, 65]]}]}}"#;
+    let data = annotation::parse(annotation).unwrap();
+    assert_eq!(data.data[0].stream, vec![(1.0, 2.0, 3.0, 4.0, 65)]);
+}
+
+#[test]
+fn missing_fields() {
+    assert!(annotation::parse("{}").unwrap().data.is_empty());
+    assert!(parse("{}").data.is_empty());
+    let data = parse(r#"{"texts": [{}, {"cs": [[1, 2, 3, 4, 65]]}, {"mt": [1, 0, 0, 1, 0, 0]}]}"#);
+    assert_eq!(data.data.len(), 3);
+    assert!(data.data[0].stream.is_empty());
+    assert_eq!(data.data[1].matrix, IDENTITY);
+    assert_eq!(data.data[1].stream.len(), 1);
+    assert!(data.data[2].stream.is_empty());
+}
+
+#[test]
+fn short_tuples() {
+    let data = parse(r#"{"texts": [{"cs": [[1, 2, 65], [1, 2], [1], [], [1, 2, 3, 4]]}]}"#);
+    // without a code point there is no character to write
+    assert!(data.data[0].stream.is_empty());
+    let data = parse(r#"{"texts": [{"cs": [[1, 2, null, null, 65]]}]}"#);
+    assert_eq!(data.data[0].stream, vec![(1.0, 2.0, 0.0, 0.0, 65)]);
+}
+
+#[test]
+fn extra_fields() {
+    let data = parse(
+        r#"{"version": 3, "texts": [
+            {"font": "x", "mt": [1, 0, 0, 1, 0, 0], "cs": [[1, 2, 3, 4, 65, "extra", 7]]}
+        ]}"#,
+    );
+    assert_eq!(data.data[0].stream, vec![(1.0, 2.0, 3.0, 4.0, 65)]);
+}
+
+#[test]
+fn bad_coordinates() {
+    let data = parse(
+        r#"{"texts": [{"mt": [1e999, 0, 0, 1, 0, 0], "cs": [
+            [1e999, 2, 3, 4, 65],
+            [NaN, 2, 3, 4, 66],
+            [null, 2, -Infinity, 4, 66],
+            ["1", 2, 3, 4, 67],
+            [1, 2, -1e999, "wide", 68],
+            [1, 2, Infinity, NaN, 68],
+            [1, 2, 3, 4, -1],
+            [1, 2, 3, 4, 1.5],
+            [1, 2, 3, 4, 69.0]
+        ]}]}"#,
+    );
+    assert_eq!(data.data[0].matrix, IDENTITY);
+    assert_eq!(
+        data.data[0].stream,
+        vec![
+            (1.0, 2.0, 0.0, 0.0, 68),
+            (1.0, 2.0, 0.0, 0.0, 68),
+            (1.0, 2.0, 3.0, 4.0, 69)
+        ]
+    );
+}
+
+#[test]
+fn non_finite_in_strings() {
+    let data = parse(
+        r#"{"texts": [
+            {"mt": [NaN, 0, 0, 1, 0, 0], "cs": [[1, 2, 3, 4, 65]], "note": "1e999 \" NaN"}
+        ]}"#,
+    );
+    assert_eq!(data.data[0].matrix, IDENTITY);
+    assert_eq!(data.data[0].stream, vec![(1.0, 2.0, 3.0, 4.0, 65)]);
+}
+
+#[test]
+fn wrong_types() {
+    for texts in [
+        r#"{"texts": 5}"#,
+        r#"{"texts": {"mt": [1, 0, 0, 1, 0, 0]}}"#,
+        r#"[1, 2, 3]"#,
+        r#""texts""#,
+        "null",
+    ] {
+        assert!(parse(texts).data.is_empty(), "{}", texts);
+    }
+    let data = parse(r#"{"texts": [5, null, {"mt": "m", "cs": {"0": [1, 2, 3, 4, 65]}}]}"#);
+    assert_eq!(data.data.len(), 1);
+    assert_eq!(data.data[0].matrix, IDENTITY);
+    assert!(data.data[0].stream.is_empty());
+}
+
+#[test]
+fn later_versions() {
+    for texts in [
+        r#"{"textRuns": [{"matrix": [2, 0, 0, 2, 5, 6], "chars": [[1, 2, 3, 4, 65]]}]}"#,
+        r#"{"runs": [{"transform": [2, 0, 0, 2, 5, 6], "glyphs": [[1, 2, 3, 4, 65]]}]}"#,
+    ] {
+        let data = parse(texts);
+        assert_eq!(
+            data.data[0].matrix,
+            [2.0, 0.0, 0.0, 2.0, 5.0, 6.0],
+            "{}",
+            texts
+        );
+        assert_eq!(
+            data.data[0].stream,
+            vec![(1.0, 2.0, 3.0, 4.0, 65)],
+            "{}",
+            texts
+        );
+    }
+}
+
+#[test]
+fn char_objects() {
+    let data = parse(
+        r#"{"texts": [{"cs": [
+            {"x": 1, "y": 2, "w": 3, "h": 4, "c": 65},
+            {"x": 5, "y": 6, "width": 7, "height": 8, "char": "B"},
+            {"x": 9, "y": 10, "code": 67},
+            {"x": 11, "char": "D"},
+            {"x": 12, "y": 13, "char": "EF"}
+        ]}]}"#,
+    );
+    assert_eq!(
+        data.data[0].stream,
+        vec![
+            (1.0, 2.0, 3.0, 4.0, 65),
+            (5.0, 6.0, 7.0, 8.0, 66),
+            (9.0, 10.0, 0.0, 0.0, 67)
+        ]
+    );
+}
+
+#[test]
+fn wrapped() {
+    let texts = r#"{"texts": [{"cs": [[1, 2, 3, 4, 65]]}]}"#;
+    for annotation in [
+        sonic_rs::json!({"textPageData": texts}),
+        sonic_rs::json!({"textLayer": texts}),
+        sonic_rs::json!({"data": {"TextPageData": texts}}),
+        sonic_rs::json!({"result": {"annotation": {"TextPageData": texts}}}),
+    ] {
+        let annotation = sonic_rs::to_string(&annotation).unwrap();
+        let data = annotation::parse(&annotation).unwrap();
+        assert_eq!(data.data[0].stream.len(), 1, "{}", annotation);
+    }
+    let data = parse(r#"{"page": {"data": {"runs": [{"glyphs": [[1, 2, 3, 4, 65]]}]}}}"#);
+    assert_eq!(data.data[0].stream.len(), 1);
+}
+
+#[test]
+fn unknown_version() {
+    let annotation = encode(r#"{"lines": [{"mt": [1, 0, 0, 1, 0, 0]}], "v": 9}"#);
+    let Err(error) = annotation::parse(&annotation) else {
+        panic!("read a text layer in an unknown format");
+    };
+    assert!(error.is::<annotation::UnknownFormat>());
+    assert!(error.to_string().contains("lines, v"), "{}", error);
+}
+
+#[test]
+fn unparsable() {
+    assert!(annotation::parse("").is_err());
+    assert!(annotation::parse(r#"{"TextPageData": "#).is_err());
+    assert!(annotation::parse(&encode(r#"{"texts": [{"mt": [1, 0"#)).is_err());
+}
+
+/// A small generator of pseudo random numbers, xorshift64.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[self.below(choices.len() as u64) as usize]
+    }
+}
+
+/// Writes a JSON value that is usually what the field holds, and sometimes anything else.
+fn value(rng: &mut Rng, depth: u32) -> String {
+    match rng.below(if depth > 2 { 8 } else { 10 }) {
+        0..=3 => format!("{}", rng.below(2000) as f64 / 7.0 - 100.0),
+        4 => rng
+            .pick(&[
+                "1e999",
+                "-1e999",
+                "1e-999",
+                "NaN",
+                "-Infinity",
+                "0",
+                "-0",
+                "4294967296",
+            ])
+            .to_string(),
+        5 => rng
+            .pick(&["null", "true", "\"x\"", "\"NaN\"", "{}", "[]"])
+            .to_string(),
+        6 => format!("{}", rng.below(0x11_0000)),
+        7 => format!("{}", rng.next() as i64),
+        _ => {
+            let len = rng.below(8);
+            let items = (0..len).map(|_| value(rng, depth + 1)).collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+    }
+}
+
+fn numbers(rng: &mut Rng, len: u64) -> String {
+    let items = (0..len).map(|_| value(rng, 3)).collect::<Vec<_>>();
+    format!("[{}]", items.join(", "))
+}
+
+fn text_run(rng: &mut Rng) -> String {
+    let mut fields = Vec::new();
+    if rng.below(8) != 0 {
+        let len = if rng.below(4) == 0 { rng.below(9) } else { 6 };
+        fields.push(format!("\"mt\": {}", numbers(rng, len)));
+    }
+    if rng.below(8) != 0 {
+        let chars = (0..rng.below(12))
+            .map(|_| {
+                let len = if rng.below(4) == 0 { rng.below(8) } else { 5 };
+                numbers(rng, len)
+            })
+            .collect::<Vec<_>>();
+        fields.push(format!("\"cs\": [{}]", chars.join(", ")));
+    }
+    if rng.below(4) == 0 {
+        fields.push(format!("\"extra\": {}", value(rng, 0)));
+    }
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn text_page_data(rng: &mut Rng) -> String {
+    let runs = (0..rng.below(6)).map(|_| text_run(rng)).collect::<Vec<_>>();
+    format!("{{\"texts\": [{}]}}", runs.join(", "))
+}
+
+#[test]
+fn random_annotations() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..3000 {
+        let texts = text_page_data(&mut rng);
+        let data = annotation::parse(&encode(&texts))
+            .unwrap_or_else(|error| panic!("{}: {}", error, texts));
+        assert_finite(&data);
+    }
+}
+
+#[test]
+fn mangled_annotations() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..3000 {
+        let mut bytes = encode(&text_page_data(&mut rng)).into_bytes();
+        for _ in 0..rng.below(4) + 1 {
+            let at = rng.below(bytes.len() as u64) as usize;
+            match rng.below(3) {
+                0 => bytes.truncate(at),
+                1 => bytes[at] = b"{}[],:\"\\0e-."[rng.below(12) as usize],
+                _ => {
+                    bytes.remove(at);
+                }
+            }
+            if bytes.is_empty() {
+                break;
+            }
+        }
+        // anything is fine but a panic or a coordinate that isn't a number
+        if let Ok(data) = annotation::parse(&String::from_utf8_lossy(&bytes)) {
+            assert_finite(&data);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Golden tests of the page layout. Every case lays out the same page and compares the document
+//! with its reference in tests/golden, so any change to the layout shows up. Run them with
+//! UPDATE_GOLDEN=1 to write the references again after a deliberate change.
+
+use std::{env, fs, path::PathBuf};
+
+use pearson_plus_extractor::{
+    assemble::{self, Layout},
+    sink::{Page, PdfSink, StreamSink, MM},
+    stamp::{Position, Stamp},
+};
+
+mod common;
+
+use common::{image, texts, H, W};
+
+fn layout() -> Layout<'static> {
+    Layout {
+        title: "Golden",
+        layers: false,
+        tagged: false,
+        text_visible: false,
+    }
+}
+
+/// Writes a document of `page` and returns it, with the random file identifier and the version
+/// of the producer left out.
+fn document(page: Page) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut sink = StreamSink::new(&mut output, "Golden", Some("Author")).unwrap();
+    sink.add_page(page).unwrap();
+    Box::new(sink).finish().unwrap();
+    let start = find(&output, b"/ID").expect("the trailer has no /ID");
+    for byte in &mut output[start + b"/ID".len()..] {
+        match byte {
+            b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F' => *byte = b'0',
+            b']' => break,
+            _ => {}
+        }
+    }
+    let version = env!("CARGO_PKG_VERSION").as_bytes();
+    while let Some(start) = find(&output, version) {
+        output.splice(start..start + version.len(), *b"x.y.z");
+    }
+    output
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Compares `document` with the reference called `name`.
+fn check(name: &str, document: &[u8]) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.pdf"));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, document).unwrap();
+        return;
+    }
+    let golden = fs::read(&path).unwrap_or_else(|error| {
+        panic!(
+            "{}: {error}, run with UPDATE_GOLDEN=1 to write it",
+            path.display()
+        )
+    });
+    assert!(
+        golden == document,
+        "the layout differs from {}, run with UPDATE_GOLDEN=1 if the change is deliberate",
+        path.display()
+    );
+}
+
+#[test]
+fn page_text() {
+    let (_, text) = assemble::page(1, image(5), (W, H), texts(), &[], &layout());
+    assert_eq!(text, "Hi\nOK\n");
+}
+
+#[test]
+fn page_size() {
+    // 12 pixels per millimeter
+    let (w, h) = assemble::page_size(120, 240);
+    assert!((w - 10.0 * MM).abs() < 1e-3 && (h - 20.0 * MM).abs() < 1e-3);
+}
+
+#[test]
+fn plain() {
+    let (page, _) = assemble::page(1, image(5), (W, H), texts(), &[], &layout());
+    check("plain", &document(page));
+}
+
+#[test]
+fn layers() {
+    let layout = Layout {
+        layers: true,
+        ..layout()
+    };
+    let (page, _) = assemble::page(1, image(5), (W, H), texts(), &[], &layout);
+    check("layers", &document(page));
+}
+
+#[test]
+fn tagged() {
+    let layout = Layout {
+        tagged: true,
+        ..layout()
+    };
+    let (page, _) = assemble::page(1, image(5), (W, H), texts(), &[], &layout);
+    check("tagged", &document(page));
+}
+
+#[test]
+fn text_visible() {
+    let layout = Layout {
+        text_visible: true,
+        ..layout()
+    };
+    let (page, _) = assemble::page(1, image(5), (W, H), texts(), &[], &layout);
+    check("text-visible", &document(page));
+}
+
+#[test]
+fn stamped() {
+    let stamp = Stamp {
+        template: "{title} {page}".to_string(),
+        position: Position::Footer,
+    };
+    let (page, _) = assemble::page(7, image(5), (W, H), texts(), &[&stamp], &layout());
+    check("stamped", &document(page));
+}
+
+#[test]
+fn cover() {
+    let size = assemble::page_size(W, H);
+    let page = assemble::cover(image(5), size, (1.0, 2.0, 8.0, 10.0), &layout());
+    check("cover", &document(page));
+}
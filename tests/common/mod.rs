@@ -0,0 +1,53 @@
+//! Fixtures shared by the tests: a page image, the text layer laid over it and temporary files.
+
+// every test uses only some of them
+#![allow(dead_code)]
+
+use std::{env, path::PathBuf};
+
+use image::{codecs::png::PngEncoder, ColorType, DynamicImage, GrayImage, ImageEncoder, Luma};
+use pearson_plus_extractor::{
+    annotation,
+    raster::{self, Encoding, Image},
+    TextPageData,
+};
+
+pub const W: u32 = 40;
+pub const H: u32 = 50;
+
+/// "Hi" and "OK" as two text runs, with the widths and heights of their characters.
+pub const TEXTS: &str = r#"{"texts": [
+    {"mt": [6, 0, 0, 6, 0, 0], "cs": [[2, 8, 3, 6, 72], [5, 8, 2, 6, 105]]},
+    {"mt": [4, 0, 0, 4, 0, 0], "cs": [[2, 2, 3, 4, 79], [5, 2, 3, 4, 75]]}
+]}"#;
+
+/// A page image of `W` by `H` pixels, a different gradient for every `seed`.
+pub fn image(seed: u32) -> Image {
+    raster::encode(DynamicImage::ImageLuma8(gradient(seed)), Encoding::Lossless).unwrap()
+}
+
+/// The page image of `image`, encoded as PNG as the player serves it.
+pub fn png(seed: u32) -> Vec<u8> {
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(gradient(seed).as_raw(), W, H, ColorType::L8)
+        .unwrap();
+    png
+}
+
+fn gradient(seed: u32) -> GrayImage {
+    GrayImage::from_fn(W, H, |x, y| Luma([(x * seed + y * 3) as u8]))
+}
+
+pub fn texts() -> TextPageData {
+    let annotation = sonic_rs::to_string(&sonic_rs::json!({"TextPageData": TEXTS})).unwrap();
+    annotation::parse(&annotation).unwrap()
+}
+
+/// A path in the temporary directory for the test called `name`.
+pub fn temporary(name: &str) -> PathBuf {
+    env::temp_dir().join(format!(
+        "pearson-plus-extractor-{}-{name}",
+        std::process::id()
+    ))
+}
@@ -0,0 +1,367 @@
+//! Tests of running extractions, from responses recorded into a WARC file as `--record` does, and
+//! of interrupting the command line in the middle of one.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use lopdf::Document;
+use pearson_plus_extractor::{
+    cancel::CancelToken,
+    extractor::{Extractor, Options},
+    progress::{self, Event},
+    toc::Toc,
+    warc::{Recorder, Replay},
+};
+use reqwest::{header::HeaderMap, Client, StatusCode, Version};
+
+mod common;
+
+use common::{png, temporary};
+
+const PAGES: u32 = 5;
+const ASSETS: &str = "https://plus.pearson.com/eplayer/pdfassets/prod1/1/abc";
+
+/// Records a book of `PAGES` pages without text layers or metadata, returning the replay of it.
+fn replay(name: &str) -> Replay {
+    replay_with(name, &[])
+}
+
+/// Records a book as `replay` does, with the responses in `first` answered before the others.
+fn replay_with(name: &str, first: &[(&str, &str, StatusCode, &str)]) -> Replay {
+    replay_from(temporary(&format!("{name}.warc")), first)
+}
+
+/// Records a book as `replay_with` does into the WARC file at `path`.
+fn replay_from(path: PathBuf, first: &[(&str, &str, StatusCode, &str)]) -> Replay {
+    let recorder = Recorder::create(&path).unwrap();
+    let client = Client::new();
+    let record_request = |method: &str, asset: String, status: StatusCode, body: &[u8]| {
+        let url = format!("{ASSETS}/{asset}");
+        let request = match method {
+            "HEAD" => client.head(url),
+            _ => client.get(url),
+        };
+        recorder
+            .record(
+                &request.build().unwrap(),
+                Version::HTTP_11,
+                status,
+                &HeaderMap::new(),
+                body,
+            )
+            .unwrap();
+    };
+    for &(method, asset, status, body) in first {
+        record_request(method, asset.to_string(), status, body.as_bytes());
+    }
+    let record =
+        |asset: String, status: StatusCode, body: &[u8]| record_request("GET", asset, status, body);
+    for page in 0..PAGES {
+        record(format!("pages/page{page}"), StatusCode::OK, &png(page + 1));
+    }
+    for page in 0..=PAGES {
+        record(
+            format!("annotations/page{page}"),
+            StatusCode::NOT_FOUND,
+            b"",
+        );
+    }
+    record(format!("pages/page{PAGES}"), StatusCode::NOT_FOUND, b"");
+    record("metadata".to_string(), StatusCode::NOT_FOUND, b"");
+    drop(recorder);
+    let replay = Replay::load(&path).unwrap();
+    // the replay reads from the file it keeps open, which outlives its name only on Unix
+    if cfg!(unix) {
+        let _ = fs::remove_file(path);
+    }
+    replay
+}
+
+fn options() -> Options {
+    Options {
+        pdf_version: "1.4",
+        ..Options::default()
+    }
+}
+
+#[tokio::test]
+async fn complete() {
+    let mut extractor = Extractor::new("", "")
+        .unwrap()
+        .with_replay(replay("complete"))
+        .with_progress(|_| {});
+    let mut output = Vec::new();
+    let extraction = extractor
+        .run(1, "abc", &mut options(), &mut output)
+        .await
+        .unwrap();
+    assert!(!extraction.cancelled);
+    assert_eq!(extraction.page_texts.len(), PAGES as usize);
+    let document = Document::load_mem(&output).unwrap();
+    assert_eq!(document.get_pages().len(), PAGES as usize);
+}
+
+#[tokio::test]
+async fn compressed() {
+    let replay = replay_from(temporary("compressed.warc.gz"), &[]);
+    let mut extractor = Extractor::new("", "")
+        .unwrap()
+        .with_replay(replay)
+        .with_progress(|_| {});
+    let mut output = Vec::new();
+    let extraction = extractor
+        .run(1, "abc", &mut options(), &mut output)
+        .await
+        .unwrap();
+    assert_eq!(extraction.page_texts.len(), PAGES as usize);
+    let document = Document::load_mem(&output).unwrap();
+    assert_eq!(document.get_pages().len(), PAGES as usize);
+}
+
+#[tokio::test]
+async fn progress() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recording = events.clone();
+    let mut extractor = Extractor::new("", "")
+        .unwrap()
+        .with_replay(replay("progress"))
+        .with_progress(move |event| recording.lock().unwrap().push(progress::to_json(event)));
+    extractor
+        .run(1, "abc", &mut options(), Vec::new())
+        .await
+        .unwrap();
+    let events = events.lock().unwrap();
+    let page_events = events
+        .iter()
+        .filter(|event| event.contains(r#""page":"#))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let event = |kind: &str, page: u32| format!(r#"{{"event":"{kind}","page":{page}}}"#);
+    // the cover, then every page downloads while the one before is added, up to the page past the
+    // last one, which doesn't exist
+    let mut expected = vec![event("page_started", 0), event("page_finished", 0)];
+    expected.push(event("page_started", 1));
+    for page in 1..PAGES {
+        expected.push(event("page_downloaded", page));
+        expected.push(event("page_started", page + 1));
+        expected.push(event("page_finished", page));
+    }
+    assert_eq!(page_events, expected);
+    let count = |kind: &str| {
+        let kind = format!(r#""event":"{kind}""#);
+        events.iter().filter(|event| event.contains(&kind)).count()
+    };
+    assert_eq!(count("page_started"), PAGES as usize + 1);
+    assert_eq!(count("page_downloaded"), PAGES as usize - 1);
+    assert_eq!(count("page_finished"), PAGES as usize);
+    assert_eq!(count("retry"), 0);
+    assert_eq!(count("warning"), 0);
+    assert!(events.last().unwrap().contains("Saving the document."));
+}
+
+#[tokio::test]
+async fn metadata_refused() {
+    let replay = replay_with(
+        "metadata",
+        &[
+            ("GET", "metadata", StatusCode::FORBIDDEN, ""),
+            ("HEAD", "pages/page0", StatusCode::OK, ""),
+        ],
+    );
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let recording = warnings.clone();
+    let mut extractor = Extractor::new("", "")
+        .unwrap()
+        .with_replay(replay)
+        .with_progress(move |event| {
+            if let Event::Warning(message) = event {
+                recording.lock().unwrap().push(message.clone());
+            }
+        });
+    let mut options = options();
+    let extraction = extractor
+        .run(1, "abc", &mut options, Vec::new())
+        .await
+        .unwrap();
+    // the book is still extracted, only without its language
+    assert_eq!(extraction.page_texts.len(), PAGES as usize);
+    assert_eq!(options.lang, None);
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].starts_with("Couldn't get the metadata of the book"));
+}
+
+#[tokio::test]
+async fn cmap_refused() {
+    // a text layer in the codes of a font of the book rather than Unicode
+    let annotation = sonic_rs::json!({"TextPageData": r#"{"texts": [
+        {"mt": [6, 0, 0, 6, 0, 0], "cs": [[2, 8, 3, 6, 57345], [5, 8, 2, 6, 57346]]}
+    ]}"#})
+    .to_string();
+    let replay = replay_with(
+        "cmap",
+        &[
+            ("GET", "annotations/page1", StatusCode::OK, &annotation),
+            ("GET", "cmap", StatusCode::FORBIDDEN, ""),
+            ("HEAD", "pages/page0", StatusCode::OK, ""),
+        ],
+    );
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let recording = warnings.clone();
+    let mut extractor = Extractor::new("", "")
+        .unwrap()
+        .with_replay(replay)
+        .with_progress(move |event| {
+            if let Event::Warning(message) = event {
+                recording.lock().unwrap().push(message.clone());
+            }
+        });
+    let mut options = options();
+    let extraction = extractor
+        .run(1, "abc", &mut options, Vec::new())
+        .await
+        .unwrap();
+    // the book is still extracted, with the codes as they are
+    assert_eq!(extraction.page_texts.len(), PAGES as usize);
+    assert!(options.cmap.is_none());
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 1, "{warnings:?}");
+    assert!(warnings[0].starts_with("Couldn't get the character map of the book"));
+}
+
+#[tokio::test]
+async fn unresolved_toc() {
+    let path = temporary("toc.json");
+    fs::write(&path, r#"[{"title": "Preface", "page": "xiv"}]"#).unwrap();
+    let toc = Toc::load(&path).unwrap();
+    let _ = fs::remove_file(path);
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let recording = warnings.clone();
+    let mut extractor = Extractor::new("", "")
+        .unwrap()
+        .with_replay(replay("toc"))
+        .with_progress(move |event| {
+            if let Event::Warning(message) = event {
+                recording.lock().unwrap().push(message.clone());
+            }
+        });
+    let mut options = Options {
+        toc: Some(toc),
+        ..options()
+    };
+    extractor
+        .run(1, "abc", &mut options, Vec::new())
+        .await
+        .unwrap();
+    // the pages print no numbers, so the entry is taken as page 14 from the cover
+    assert_eq!(options.toc.unwrap().entries[0].page, 14);
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(
+        *warnings,
+        ["Found no printed page numbers like the one of \"Preface\", taking it as page 0014."]
+    );
+}
+
+#[tokio::test]
+async fn cancelled_midway() {
+    let cancel = CancelToken::new();
+    let cancelling = cancel.clone();
+    let mut extractor = Extractor::new("", "")
+        .unwrap()
+        .with_replay(replay("cancelled"))
+        .with_progress(move |event| {
+            if let Event::PageDownloaded(1) = event {
+                cancelling.cancel();
+            }
+        });
+    let mut output = Vec::new();
+    let extraction = extractor
+        .run_with_cancel(1, "abc", &mut options(), &mut output, &cancel)
+        .await
+        .unwrap();
+    assert!(extraction.cancelled);
+    // the cover and the page downloaded before the token was cancelled
+    assert_eq!(extraction.page_texts.len(), 2);
+    let document = Document::load_mem(&output).unwrap();
+    assert_eq!(document.get_pages().len(), 2);
+}
+
+#[tokio::test]
+async fn cancelled_before_the_cover() {
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    let mut extractor = Extractor::new("", "")
+        .unwrap()
+        .with_replay(replay("before"))
+        .with_progress(|_| {});
+    let result = extractor
+        .run_with_cancel(1, "abc", &mut options(), Vec::new(), &cancel)
+        .await;
+    assert!(result.is_err());
+}
+
+/// Interrupts the command line while the first page hangs, behind a proxy that never answers,
+/// which has to stop without leaving the document it started behind.
+#[cfg(unix)]
+#[test]
+fn interrupted() {
+    use std::{
+        net::TcpListener,
+        process::{Command, Stdio},
+        sync::mpsc,
+        thread,
+        time::Instant,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = format!("http://{}", listener.local_addr().unwrap());
+    let (connected, connection) = mpsc::channel();
+    thread::spawn(move || {
+        let mut held = Vec::new();
+        for stream in listener.incoming() {
+            held.push(stream.unwrap());
+            let _ = connected.send(());
+        }
+    });
+    let output_path = temporary("interrupted.pdf");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pearson-plus-extractor"))
+        .args(["-c", "x", "-p", "1", "-u", "abc", "--no-space-check", "-o"])
+        .arg(&output_path)
+        .env("HTTPS_PROXY", &proxy)
+        .env_remove("NO_PROXY")
+        .env_remove("no_proxy")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    connection.recv_timeout(Duration::from_secs(10)).unwrap();
+    // SAFETY: kill only sends a signal to the child
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if start.elapsed() > Duration::from_secs(5) {
+            let _ = child.kill();
+            panic!("the extraction didn't stop when interrupted");
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    let mut temporary_path = output_path.clone().into_os_string();
+    temporary_path.push(".tmp");
+    let left = [PathBuf::from(temporary_path), output_path]
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect::<Vec<_>>();
+    for path in &left {
+        let _ = fs::remove_file(path);
+    }
+    // exited with the error of a run that has no pages, rather than killed by the signal
+    assert_eq!(status.code(), Some(1));
+    assert!(left.is_empty(), "{left:?} were left behind");
+}
@@ -0,0 +1,42 @@
+//! Tests of turning book and chapter titles into file names valid on every platform.
+
+use pearson_plus_extractor::filename::sanitize;
+
+#[test]
+fn invalid_characters() {
+    assert_eq!(sanitize("Physics: A/B <Test>?"), "Physics_ A_B _Test__");
+    assert_eq!(sanitize("tab\there"), "tab_here");
+}
+
+#[test]
+fn trailing_dots_and_spaces() {
+    assert_eq!(sanitize("  Chapter 1. "), "Chapter 1");
+    assert_eq!(sanitize("..."), "untitled");
+    assert_eq!(sanitize(""), "untitled");
+}
+
+#[test]
+fn reserved_names() {
+    assert_eq!(sanitize("CON"), "_CON");
+    assert_eq!(sanitize("nul.pdf"), "_nul.pdf");
+    assert_eq!(sanitize("com1"), "_com1");
+    assert_eq!(sanitize("LPT9.txt"), "_LPT9.txt");
+    assert_eq!(sanitize("COMA"), "COMA");
+    assert_eq!(sanitize("CONSOLE"), "CONSOLE");
+}
+
+#[test]
+fn non_ascii_titles() {
+    assert_eq!(sanitize("Olé"), "Olé");
+    assert_eq!(sanitize("Olé.pdf"), "Olé.pdf");
+    assert_eq!(sanitize("日本"), "日本");
+    assert_eq!(sanitize("Çé1"), "Çé1");
+}
+
+#[test]
+fn long_titles() {
+    let title = "é".repeat(150);
+    let sanitized = sanitize(&title);
+    assert!(sanitized.len() <= 200);
+    assert_eq!(sanitized, "é".repeat(100));
+}
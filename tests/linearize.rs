@@ -0,0 +1,126 @@
+//! Tests of saving documents linearized, checking that the offsets the linearization dictionary
+//! and the first-page trailer give point where they should.
+
+use lopdf::{xref::XrefEntry, Document, Object};
+use pearson_plus_extractor::{
+    assemble::{self, Layout},
+    linearize,
+    sink::{DocumentSink, PdfSink},
+};
+
+mod common;
+
+use common::{image, texts, H, W};
+
+const PAGES: u32 = 5;
+
+/// Writes a linearized document of `pages` pages, laid out as the extractor lays out a book.
+fn linearized(pages: u32) -> Vec<u8> {
+    let layout = Layout {
+        title: "Linearized",
+        layers: true,
+        tagged: false,
+        text_visible: false,
+    };
+    let mut sink = DocumentSink::new("Linearized", Some("Author"));
+    for number in 1..=pages {
+        let (page, _) = assemble::page(number, image(number), (W, H), texts(), &[], &layout);
+        sink.add_page(page).unwrap();
+    }
+    let document = Box::new(sink).finish().unwrap().unwrap();
+    let mut output = Vec::new();
+    linearize::save(&document, &mut output).unwrap();
+    output
+}
+
+fn integer(dict: &lopdf::Dictionary, key: &[u8]) -> usize {
+    dict.get(key).and_then(Object::as_i64).unwrap() as usize
+}
+
+/// Returns the linearization parameter dictionary, the first object of the file.
+fn parameters(document: &Document) -> &lopdf::Dictionary {
+    document
+        .objects
+        .values()
+        .filter_map(|object| object.as_dict().ok())
+        .find(|dict| dict.has(b"Linearized"))
+        .expect("the document has no linearization dictionary")
+}
+
+/// Returns the /Prev of the first-page trailer, which lopdf leaves out when it merges it with the
+/// main one.
+fn prev(output: &[u8]) -> usize {
+    let find = |needle: &[u8], from: usize| {
+        from + output[from..]
+            .windows(needle.len())
+            .position(|window| window == needle)
+            .unwrap()
+    };
+    let start = find(b"/Prev", find(b"trailer", 0)) + b"/Prev".len();
+    let digits = output[start..]
+        .iter()
+        .skip_while(|byte| byte.is_ascii_whitespace())
+        .take_while(|byte| byte.is_ascii_digit())
+        .map(|&byte| byte as char)
+        .collect::<String>();
+    digits.parse().unwrap()
+}
+
+fn assert_at(output: &[u8], offset: usize, expected: &[u8]) {
+    assert!(
+        output[offset..].starts_with(expected),
+        "{:?} is not at {offset}, {:?} is",
+        String::from_utf8_lossy(expected),
+        String::from_utf8_lossy(&output[offset..(offset + 20).min(output.len())])
+    );
+}
+
+#[test]
+fn offsets() {
+    let output = linearized(PAGES);
+    let document = Document::load_mem(&output).unwrap();
+    assert_eq!(document.get_pages().len(), PAGES as usize);
+    let parameters = parameters(&document);
+    assert_eq!(integer(parameters, b"N"), PAGES as usize);
+    // the length of the whole file
+    assert_eq!(integer(parameters, b"L"), output.len());
+    // the white space before the first entry of the main cross-reference table
+    let main_xref = integer(parameters, b"T");
+    assert_at(&output, main_xref, b"\n0000000000 65535 f ");
+    // the end of the first page, where the objects of the second one start
+    let first_page_end = integer(parameters, b"E");
+    assert_at(&output, first_page_end, b"1 0 obj");
+    let first_page = integer(parameters, b"O") as u32;
+    let Some(&XrefEntry::Normal { offset, .. }) = document.reference_table.get(first_page) else {
+        panic!("the first page isn't in the cross-reference table");
+    };
+    assert!((offset as usize) < first_page_end);
+    // the first-page trailer points on to the main cross-reference table
+    let prev = prev(&output);
+    assert_at(&output, prev, b"xref\n");
+    assert!(prev < main_xref && main_xref - prev < 20);
+    let hints = parameters.get(b"H").and_then(Object::as_array).unwrap();
+    let hint_offset = hints[0].as_i64().unwrap() as usize;
+    assert!(hint_offset < offset as usize);
+}
+
+#[test]
+fn object_offsets() {
+    let output = linearized(PAGES);
+    let document = Document::load_mem(&output).unwrap();
+    for (&id, entry) in &document.reference_table.entries {
+        if let XrefEntry::Normal { offset, .. } = *entry {
+            assert_at(&output, offset as usize, format!("{id} 0 obj").as_bytes());
+        }
+    }
+}
+
+#[test]
+fn single_page() {
+    let output = linearized(1);
+    let document = Document::load_mem(&output).unwrap();
+    let parameters = parameters(&document);
+    assert_eq!(integer(parameters, b"L"), output.len());
+    assert_at(&output, integer(parameters, b"T"), b"\n0000000000 65535 f ");
+    assert_at(&output, prev(&output), b"xref\n");
+}
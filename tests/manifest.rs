@@ -0,0 +1,132 @@
+//! Tests of checking a document against the manifest written along with it, which has to point
+//! at exactly the pages that changed.
+
+use std::{fs, path::PathBuf};
+
+use lopdf::{Document, Object, ObjectId};
+use pearson_plus_extractor::{
+    manifest::{self, Page},
+    sink::{self, PdfSink, StreamSink},
+};
+use ring::digest::{digest, SHA256};
+
+mod common;
+
+use common::{image, temporary};
+
+const PAGES: u32 = 3;
+
+/// The files downloaded for the pages of the book.
+fn assets() -> Vec<Vec<u8>> {
+    (0..PAGES)
+        .map(|page| format!("page {page}").into_bytes())
+        .collect()
+}
+
+fn asset_name(page: u32) -> String {
+    format!("1/abc/pages/page{page}")
+}
+
+/// Writes a document of a page for every asset and its manifest, returning their paths.
+fn write(name: &str) -> (PathBuf, PathBuf) {
+    let document_path = temporary(&format!("{name}.pdf"));
+    let manifest_path = temporary(&format!("{name}.json"));
+    let mut output = Vec::new();
+    let mut sink = StreamSink::new(&mut output, "Manifest", None).unwrap();
+    for page in 0..PAGES {
+        let mut drawn = sink::Page::new((20.0, 30.0));
+        drawn.draw_image(image(page + 1), (0.0, 0.0, 20.0, 30.0));
+        sink.add_page(drawn).unwrap();
+    }
+    Box::new(sink).finish().unwrap();
+    fs::write(&document_path, output).unwrap();
+    let digests = assets()
+        .iter()
+        .map(|asset| digest(&SHA256, asset).as_ref().to_vec())
+        .collect::<Vec<_>>();
+    let pages = digests
+        .iter()
+        .enumerate()
+        .map(|(page, digest)| Page {
+            page: page as u32,
+            pdf_page: page as u32 + 1,
+            digest,
+        })
+        .collect::<Vec<_>>();
+    let listed = manifest::write(&manifest_path, &document_path, &pages, 1, "abc").unwrap();
+    assert_eq!(listed, PAGES as usize);
+    (document_path, manifest_path)
+}
+
+/// Returns the image drawn on the page `id`.
+fn xobject_of(document: &Document, id: ObjectId) -> ObjectId {
+    let dereference = |object| document.dereference(object).unwrap().1;
+    let page = document.get_dictionary(id).unwrap();
+    let resources = dereference(page.get(b"Resources").unwrap());
+    let xobjects = dereference(resources.as_dict().unwrap().get(b"XObject").unwrap());
+    let (_, xobject) = xobjects.as_dict().unwrap().iter().next().unwrap();
+    xobject.as_reference().unwrap()
+}
+
+fn remove(paths: (PathBuf, PathBuf)) {
+    let _ = fs::remove_file(paths.0);
+    let _ = fs::remove_file(paths.1);
+}
+
+#[test]
+fn intact() {
+    let paths = write("intact");
+    let assets = assets();
+    let source = |asset: &str| {
+        let page = (0..PAGES).find(|&page| asset_name(page) == asset)?;
+        Some(assets[page as usize].clone())
+    };
+    let mismatches = manifest::verify(&paths.0, &paths.1, Some(&source)).unwrap();
+    remove(paths);
+    assert!(mismatches.is_empty(), "{mismatches:?}");
+}
+
+#[test]
+fn altered_image() {
+    let paths = write("altered");
+    let mut document = Document::load(&paths.0).unwrap();
+    let page = document.get_pages()[&2];
+    let id = xobject_of(&document, page);
+    let replacement = image(7).into_stream().content;
+    document
+        .get_object_mut(id)
+        .and_then(Object::as_stream_mut)
+        .unwrap()
+        .set_content(replacement);
+    document.save(&paths.0).unwrap();
+    let mismatches = manifest::verify(&paths.0, &paths.1, None).unwrap();
+    remove(paths);
+    assert_eq!(
+        mismatches,
+        [
+            "The document differs from the one the manifest was written for.",
+            "The image of page 0001 on page 2 of the document was altered.",
+        ]
+    );
+}
+
+#[test]
+fn altered_assets() {
+    let paths = write("assets");
+    let mut assets = assets();
+    assets[2] = b"something else".to_vec();
+    let source = |asset: &str| {
+        // the first page was never kept
+        let page = (1..PAGES).find(|&page| asset_name(page) == asset)?;
+        Some(assets[page as usize].clone())
+    };
+    let mismatches = manifest::verify(&paths.0, &paths.1, Some(&source)).unwrap();
+    remove(paths);
+    assert_eq!(
+        mismatches,
+        [
+            "The downloaded image of page 0000 is missing.",
+            "The downloaded image of page 0002 differs from the one in the document.",
+        ]
+    );
+}
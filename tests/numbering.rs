@@ -0,0 +1,53 @@
+//! Tests of reading printed page numbers and telling which page they are on.
+
+use pearson_plus_extractor::numbering::{self, Numbering, Printed};
+
+#[test]
+fn arabic() {
+    assert!(numbering::parse("12") == Some(Printed::Arabic(12)));
+    assert!(numbering::parse("p. 12") == Some(Printed::Arabic(12)));
+    assert!(numbering::parse("S. 12 f.") == Some(Printed::Arabic(12)));
+    assert!(numbering::parse("1.234") == Some(Printed::Arabic(1234)));
+    assert!(numbering::parse("١٢") == Some(Printed::Arabic(12)));
+    assert!(numbering::parse("99999999999").is_none());
+    assert!(numbering::parse("12a").is_none());
+}
+
+#[test]
+fn roman() {
+    assert!(numbering::parse("xiv") == Some(Printed::Roman(14)));
+    assert!(numbering::parse("XIV") == Some(Printed::Roman(14)));
+    assert!(numbering::parse("mcmxcix") == Some(Printed::Roman(1999)));
+    assert_eq!(numbering::to_roman(14), "xiv");
+}
+
+#[test]
+fn malformed_roman() {
+    for label in ["iiii", "dim", "iiiiiiv", "vx", "iiiiiiiiiiiiiiii", ""] {
+        assert!(numbering::parse(label).is_none(), "{label}");
+    }
+}
+
+#[test]
+fn page() {
+    let mut numbering = Numbering::default();
+    numbering.add_page(3, "Contents\n\nv");
+    numbering.add_page(4, "vi\nPreface");
+    for page in 10..20 {
+        numbering.add_page(page, &format!("Chapter 1\nSome text.\n{}", page - 9));
+    }
+    // a stray number on one page doesn't outweigh the others
+    numbering.add_page(20, "Figure\n42");
+    assert_eq!(numbering.page(Printed::Arabic(1)), Some(10));
+    assert_eq!(numbering.page(Printed::Arabic(100)), Some(109));
+    assert_eq!(numbering.page(Printed::Roman(7)), Some(5));
+    // before the first page
+    assert_eq!(numbering.page(Printed::Roman(1)), None);
+}
+
+#[test]
+fn no_numbers() {
+    let mut numbering = Numbering::default();
+    numbering.add_page(1, "Just text.");
+    assert_eq!(numbering.page(Printed::Arabic(1)), None);
+}
@@ -0,0 +1,111 @@
+//! Round trips of documents saved with every way of packing their objects, read back with lopdf.
+
+use anyhow::Result;
+use image::{DynamicImage, GrayImage, Luma};
+use lopdf::{Document, Object, ObjectId};
+use pearson_plus_extractor::{
+    objstm::{self, Packing},
+    raster::{self, Encoding},
+    sink::{DocumentSink, Page, PdfSink},
+};
+
+const PACKINGS: [Packing; 3] = [Packing::Table, Packing::XrefStream, Packing::ObjectStreams];
+
+/// A document of a few pages with an image and a line of text each, many more objects than fit
+/// in one object stream.
+fn document() -> Document {
+    let mut sink = DocumentSink::new("Round trip", Some("Author"));
+    for i in 0..120 {
+        let image = GrayImage::from_fn(8, 8, |x, y| Luma([(x * i + y) as u8]));
+        let image = raster::encode(DynamicImage::ImageLuma8(image), Encoding::Lossless).unwrap();
+        let mut page = Page::new((100.0, 150.0));
+        page.draw_image(image, (0.0, 0.0, 100.0, 150.0));
+        page.begin_text(12.0, false);
+        page.set_text_position(10.0, 20.0);
+        page.write_text(&format!("Page {i}"));
+        page.end_text();
+        sink.add_page(page).unwrap();
+    }
+    Box::new(sink).finish().unwrap().unwrap()
+}
+
+fn save(document: &Document, packing: Packing) -> Document {
+    let mut output = Vec::new();
+    objstm::save(document, packing, &mut output).unwrap();
+    Document::load_mem(&output).unwrap()
+}
+
+/// Checks that `reloaded` has every object of `original` as it was, streams with the contents
+/// `content` gives for them.
+fn assert_same(
+    original: &Document,
+    reloaded: &Document,
+    content: impl Fn(ObjectId, &[u8]) -> Vec<u8>,
+) {
+    assert_eq!(original.get_pages().len(), reloaded.get_pages().len());
+    assert_eq!(
+        format!("{:?}", original.trailer.get(b"Root").unwrap()),
+        format!("{:?}", reloaded.trailer.get(b"Root").unwrap())
+    );
+    for (&id, object) in &original.objects {
+        let copy = reloaded
+            .get_object(id)
+            .unwrap_or_else(|_| panic!("{id:?} is missing"));
+        match (object, copy) {
+            (Object::Stream(stream), Object::Stream(copy)) => {
+                assert_eq!(copy.content, content(id, &stream.content), "{id:?}");
+                for (key, value) in stream.dict.iter().filter(|(key, _)| *key != b"Length") {
+                    let copied = copy.dict.get(key).unwrap();
+                    assert_eq!(format!("{value:?}"), format!("{copied:?}"), "{id:?}");
+                }
+            }
+            (Object::Stream(_), _) => panic!("{id:?} is no longer a stream"),
+            (object, copy) => assert_eq!(format!("{object:?}"), format!("{copy:?}"), "{id:?}"),
+        }
+    }
+}
+
+#[test]
+fn round_trip() {
+    let document = document();
+    for packing in PACKINGS {
+        let reloaded = save(&document, packing);
+        assert_same(&document, &reloaded, |_, content| content.to_vec());
+    }
+}
+
+#[test]
+fn packed_into_object_streams() {
+    let document = document();
+    let mut packed = Vec::new();
+    objstm::save(&document, Packing::ObjectStreams, &mut packed).unwrap();
+    let mut table = Vec::new();
+    objstm::save(&document, Packing::Table, &mut table).unwrap();
+    let object_streams = |output: &[u8]| output.windows(7).filter(|w| w == b"/ObjStm").count();
+    assert!(object_streams(&packed) > 1);
+    assert_eq!(object_streams(&table), 0);
+    assert!(packed.len() < table.len());
+}
+
+#[test]
+fn spilled() {
+    let document = document();
+    let spilled = |id: ObjectId| -> Result<Option<Vec<u8>>> {
+        let stream = document.get_object(id)?.as_stream()?;
+        // the contents of the page streams, as a spill keeps them
+        let is_image = stream
+            .dict
+            .get(b"Subtype")
+            .and_then(Object::as_name)
+            .is_ok();
+        Ok((!is_image).then(|| [b"% spilled\n".as_slice(), &stream.content].concat()))
+    };
+    for packing in PACKINGS {
+        let mut output = Vec::new();
+        objstm::save_spilled(&document, packing, &mut output, spilled).unwrap();
+        let reloaded = Document::load_mem(&output).unwrap();
+        assert_same(&document, &reloaded, |id, content| {
+            spilled(id).unwrap().unwrap_or_else(|| content.to_vec())
+        });
+    }
+}
@@ -0,0 +1,74 @@
+//! Tests of embedding page images, as they are where PDF reads PNG data the same way and decoded
+//! otherwise.
+
+use flate2::Crc;
+use image::{codecs::png::PngEncoder, ColorType, ImageEncoder};
+use pearson_plus_extractor::raster::{self, Encoding};
+
+const W: u32 = 4;
+const H: u32 = 3;
+
+fn png(color: ColorType, channels: u32) -> Vec<u8> {
+    let samples = (0..W * H * channels)
+        .map(|i| (i * 7) as u8)
+        .collect::<Vec<_>>();
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(&samples, W, H, color)
+        .unwrap();
+    png
+}
+
+/// Inserts a chunk right after the IHDR chunk of `png`.
+fn insert_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend(kind);
+    chunk.extend(data);
+    let mut crc = Crc::new();
+    crc.update(&chunk[4..]);
+    chunk.extend(crc.sum().to_be_bytes());
+    // the signature and the 25 bytes of the IHDR chunk
+    png.splice(33..33, chunk);
+}
+
+fn passed_through(png: &[u8]) -> bool {
+    let stream = raster::decode(png, Encoding::Lossless)
+        .unwrap()
+        .into_stream();
+    stream.dict.has(b"DecodeParms")
+}
+
+#[test]
+fn opaque() {
+    assert!(passed_through(&png(ColorType::L8, 1)));
+    assert!(passed_through(&png(ColorType::Rgb8, 3)));
+}
+
+#[test]
+fn alpha() {
+    assert!(!passed_through(&png(ColorType::La8, 2)));
+    assert!(!passed_through(&png(ColorType::Rgba8, 4)));
+}
+
+#[test]
+fn transparent_color() {
+    let mut gray = png(ColorType::L8, 1);
+    insert_chunk(&mut gray, b"tRNS", &[0, 7]);
+    assert!(!passed_through(&gray));
+    let mut rgb = png(ColorType::Rgb8, 3);
+    insert_chunk(&mut rgb, b"tRNS", &[0, 0, 0, 7, 0, 14]);
+    assert!(!passed_through(&rgb));
+}
+
+#[test]
+fn sixteen_bit() {
+    assert!(!passed_through(&png(ColorType::L16, 2)));
+}
+
+#[test]
+fn oversized_chunk() {
+    let mut png = png(ColorType::L8, 1);
+    // the length of the chunk after IHDR, past the end of the file
+    png[33..37].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(raster::decode(&png, Encoding::Lossless).is_err());
+}